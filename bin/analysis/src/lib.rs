@@ -0,0 +1,622 @@
+//! Analyses that find problems in a program without running it: reads of
+//! variables that might not have been assigned, and with an interval for
+//! every variable, arithmetic that might overflow or divide by zero.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use language::*;
+
+/// Whether a variable has been assigned at some point in the program.
+/// Variables that are missing from `State` have not been
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Assignment {
+    Definitely,
+    /// On some ways of getting here but not others
+    Possibly
+}
+
+type State = BTreeMap<String, Assignment>;
+
+fn merge(a: &State, b: &State) -> State {
+    let mut merged = State::new();
+
+    for var in a.keys().chain(b.keys()) {
+        let both = a.get(var) == Some(&Assignment::Definitely) && b.get(var) == Some(&Assignment::Definitely);
+        merged.insert(var.clone(), if both { Assignment::Definitely } else { Assignment::Possibly });
+    }
+
+    return merged;
+}
+
+/// How sure the analysis is that a read fails
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Uninitialised {
+    /// Assigned on some ways of getting to the read but not others
+    Possibly,
+    /// Not assigned on any way of getting to the read, so the read fails
+    /// whenever it runs
+    Definitely
+}
+
+/// A variable read that can run before the variable is assigned
+#[derive(Debug, Clone, PartialEq)]
+pub struct UninitialisedRead {
+    pub var: String,
+    /// The statement that reads it
+    pub loc: Location,
+    pub kind: Uninitialised
+}
+
+impl fmt::Display for UninitialisedRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            Uninitialised::Possibly => "possibly",
+            Uninitialised::Definitely => "definitely"
+        };
+        write!(f, "{}: Variable {:?} is {} uninitialised", self.loc, self.var, kind)
+    }
+}
+
+#[derive(Default)]
+struct DefiniteAssignment {
+    reads: Vec<UninitialisedRead>,
+    /// Statements and variables already reported, as a REPEAT body is
+    /// looked at more than once
+    reported: BTreeSet<(Location, String)>
+}
+
+impl DefiniteAssignment {
+    fn exp(&mut self, exp: &Expression, loc: Location, state: &mut State, report: bool) {
+        match exp {
+            Expression::Val(_) => (),
+            Expression::Var(var) => {
+                let kind = match state.get(var) {
+                    Some(Assignment::Definitely) => return,
+                    Some(Assignment::Possibly) => Uninitialised::Possibly,
+                    None => Uninitialised::Definitely
+                };
+
+                if report && self.reported.insert((loc, var.clone())) {
+                    self.reads.push(UninitialisedRead{var: var.clone(), loc, kind});
+                }
+                // Only runs that had it assigned get any further
+                state.insert(var.clone(), Assignment::Definitely);
+            },
+            Expression::BinOp(_, lhs, rhs) => {
+                self.exp(lhs, loc, state, report);
+                self.exp(rhs, loc, state, report);
+            }
+        }
+    }
+
+    /// Works out the state after `block`, reporting reads if `report` is set
+    fn block(&mut self, block: &Block, state: &mut State, report: bool) {
+        for stmt in block.statements.iter() {
+            let loc = stmt.location();
+
+            match stmt {
+                Statement::AssignStatement { var, exp, .. } => {
+                    self.exp(exp, loc, state, report);
+                    state.insert(var.clone(), Assignment::Definitely);
+                },
+                Statement::OutputStatement { to_output, .. } => self.exp(to_output, loc, state, report),
+                Statement::AssertStatement { exp, expected, .. } => {
+                    self.exp(exp, loc, state, report);
+                    if let Some(expected) = expected {
+                        self.exp(expected, loc, state, report);
+                    }
+                },
+                Statement::RepeatStatement { times, body, .. } => {
+                    self.exp(times, loc, state, report);
+
+                    // The top of the body is reached from before the loop and
+                    // from the end of the body, so merge until nothing changes
+                    let mut top = state.clone();
+                    loop {
+                        let mut after = top.clone();
+                        self.block(body, &mut after, false);
+                        let merged = merge(&top, &after);
+                        if merged == top {
+                            break;
+                        }
+                        top = merged;
+                    }
+
+                    // Nothing in a body that never runs can fail
+                    let runs = !matches!(times, Expression::Val(count) if *count <= 0);
+                    let mut after = top.clone();
+                    self.block(body, &mut after, report && runs);
+
+                    // The body might not run at all unless the count says so
+                    *state = match times {
+                        Expression::Val(count) if *count > 0 => after,
+                        Expression::Val(_) => state.clone(),
+                        _ => top
+                    };
+                },
+                Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                    self.exp(condition, loc, state, report);
+                    let mut arms = Vec::new();
+
+                    let mut arm_state = state.clone();
+                    self.block(body, &mut arm_state, report);
+                    arms.push(arm_state);
+
+                    // Each ELSEIF condition is only worked out when the ones
+                    // before it were false
+                    let mut tested = state.clone();
+                    for (condition, block) in else_if {
+                        self.exp(condition, loc, &mut tested, report);
+                        let mut arm_state = tested.clone();
+                        self.block(block, &mut arm_state, report);
+                        arms.push(arm_state);
+                    }
+
+                    let mut arm_state = tested;
+                    self.block(else_body, &mut arm_state, report);
+                    arms.push(arm_state);
+
+                    *state = arms.into_iter().reduce(|a, b| merge(&a, &b)).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// Every read in `program` that could run before the variable it reads has
+/// been assigned, in the order they appear
+pub fn uninitialised_reads(program: &Program) -> Vec<UninitialisedRead> {
+    let mut analysis = DefiniteAssignment::default();
+    analysis.block(&program.program, &mut State::new(), true);
+    // Each test starts with nothing assigned
+    for test in program.tests.iter() {
+        analysis.block(&test.body, &mut State::new(), true);
+    }
+
+    let mut reads = analysis.reads;
+    reads.sort_by_key(|read| read.loc);
+    return reads;
+}
+
+/// The values something can take, both ends included. The ends are `i64`
+/// so arithmetic on `i32` ranges can be done without overflowing
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64
+}
+
+const I32: Interval = Interval{lo: i32::MIN as i64, hi: i32::MAX as i64};
+const BOOL: Interval = Interval{lo: 0, hi: 1};
+
+impl Interval {
+    pub fn new(lo: i64, hi: i64) -> Self {
+        Self{lo, hi}
+    }
+
+    pub fn constant(value: i32) -> Self {
+        Self::new(value as i64, value as i64)
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+
+    pub fn join(&self, other: &Interval) -> Interval {
+        Interval::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+
+    fn meet(&self, other: &Interval) -> Option<Interval> {
+        let met = Interval::new(self.lo.max(other.lo), self.hi.min(other.hi));
+        (met.lo <= met.hi).then_some(met)
+    }
+
+    /// Any end still moving goes straight to the limit of an `i32`, so
+    /// loops that keep growing something stop being looked at
+    fn widen(&self, next: &Interval) -> Interval {
+        Interval::new(
+            if next.lo < self.lo { I32.lo } else { self.lo },
+            if next.hi > self.hi { I32.hi } else { self.hi }
+        )
+    }
+
+    /// The smallest interval holding all of `values`
+    fn hull(values: impl IntoIterator<Item = i64>) -> Interval {
+        let mut values = values.into_iter();
+        let first = values.next().unwrap();
+        values.fold(Interval::new(first, first), |i, v| Interval::new(i.lo.min(v), i.hi.max(v)))
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.lo == self.hi {
+            true => write!(f, "{}", self.lo),
+            false => write!(f, "[{}, {}]", self.lo, self.hi)
+        }
+    }
+}
+
+/// The range of each variable at some point. Variables that are missing
+/// haven't been assigned on any way of getting there
+pub type Ranges = BTreeMap<String, Interval>;
+
+/// Where two ways through the program meet. `None` is a point that can't
+/// be reached
+fn join(a: &Option<Ranges>, b: &Option<Ranges>) -> Option<Ranges> {
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (Some(a), None) | (None, Some(a)) => return Some(a.clone()),
+        (None, None) => return None
+    };
+
+    let mut joined = a.clone();
+    for (var, interval) in b {
+        let entry = joined.entry(var.clone()).or_insert(*interval);
+        *entry = entry.join(interval);
+    }
+    return Some(joined);
+}
+
+fn widen(old: &Option<Ranges>, next: &Option<Ranges>) -> Option<Ranges> {
+    let (Some(old), Some(next)) = (old, next) else {
+        return next.clone();
+    };
+
+    return Some(next.iter().map(|(var, interval)| {
+        let widened = match old.get(var) {
+            Some(before) => before.widen(interval),
+            None => *interval
+        };
+        (var.clone(), widened)
+    }).collect());
+}
+
+/// Something that might go wrong when the program runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProblemKind {
+    Overflow(Op, Interval, Interval),
+    DivideByZero(Op, Interval),
+    NegativeRepeat(Interval)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// The statement it can happen in
+    pub loc: Location,
+    pub kind: ProblemKind
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ProblemKind::Overflow(op, lhs, rhs) => {
+                let name = match op {
+                    Op::Add => "addition",
+                    Op::Sub => "subtraction",
+                    Op::Multiply => "multiplication",
+                    Op::Divide => "division",
+                    _ => "remainder"
+                };
+                write!(f, "{}: {} of {} and {} can overflow", self.loc, name, lhs, rhs)
+            },
+            ProblemKind::DivideByZero(op, rhs) => {
+                let name = if *op == Op::Divide { "division" } else { "remainder" };
+                write!(f, "{}: {} by {}, which can be zero", self.loc, name, rhs)
+            },
+            ProblemKind::NegativeRepeat(count) => write!(f, "{}: REPEAT count {} can be negative", self.loc, count)
+        }
+    }
+}
+
+/// REPEATs that run at most this many times are followed round exactly,
+/// longer ones are widened
+const MAX_EXACT_ITERATIONS: i64 = 64;
+
+/// Nested REPEATs are followed exactly only while their counts multiplied
+/// together stay within this, or the work would grow with each level
+const MAX_EXACT_UNROLLING: i64 = 4096;
+
+#[derive(Default)]
+struct IntervalAnalysis {
+    before: BTreeMap<Location, Ranges>,
+    assigned: BTreeMap<Location, Interval>,
+    problems: Vec<Problem>,
+    /// How many times round a loop can still be followed exactly, shared
+    /// out between the loops nested in the ones being followed
+    budget: i64
+}
+
+fn flip(op: Op) -> Op {
+    match op {
+        Op::LessThan => Op::GreaterThan,
+        Op::LessThanOrEqual => Op::GreaterThanOrEqual,
+        Op::GreaterThan => Op::LessThan,
+        Op::GreaterThanOrEqual => Op::LessThanOrEqual,
+        op => op
+    }
+}
+
+fn negate(op: Op) -> Option<Op> {
+    match op {
+        Op::LessThan => Some(Op::GreaterThanOrEqual),
+        Op::LessThanOrEqual => Some(Op::GreaterThan),
+        Op::GreaterThan => Some(Op::LessThanOrEqual),
+        Op::GreaterThanOrEqual => Some(Op::LessThan),
+        Op::Equal => Some(Op::NotEqual),
+        Op::NotEqual => Some(Op::Equal),
+        _ => None
+    }
+}
+
+impl IntervalAnalysis {
+    fn report(&mut self, loc: Location, kind: ProblemKind) {
+        // Loops are looked at more than once, but the ranges only grow so
+        // the first report for a place is kept
+        let same_place = |p: &Problem| p.loc == loc && std::mem::discriminant(&p.kind) == std::mem::discriminant(&kind)
+            && match (&p.kind, &kind) {
+                (ProblemKind::Overflow(a, ..), ProblemKind::Overflow(b, ..)) |
+                (ProblemKind::DivideByZero(a, _), ProblemKind::DivideByZero(b, _)) => a == b,
+                _ => true
+            };
+        match self.problems.iter_mut().find(|p| same_place(p)) {
+            Some(problem) => problem.kind = kind,
+            None => self.problems.push(Problem{loc, kind})
+        }
+    }
+
+    /// The range of `exp`, or `None` if working it out always fails
+    fn exp(&mut self, exp: &Expression, ranges: &Ranges, loc: Location) -> Option<Interval> {
+        let (op, lhs, rhs) = match exp {
+            Expression::Val(value) => return Some(Interval::constant(*value)),
+            // Reading a variable that was never assigned fails
+            Expression::Var(var) => return ranges.get(var).copied(),
+            Expression::BinOp(op, lhs, rhs) => (*op, self.exp(lhs, ranges, loc)?, self.exp(rhs, ranges, loc)?)
+        };
+
+        let corners = |f: fn(i64, i64) -> i64| Interval::hull([f(lhs.lo, rhs.lo), f(lhs.lo, rhs.hi), f(lhs.hi, rhs.lo), f(lhs.hi, rhs.hi)]);
+        let decided = |always: bool, never: bool| match (always, never) {
+            (true, _) => Interval::constant(1),
+            (_, true) => Interval::constant(0),
+            _ => BOOL
+        };
+
+        let result = match op {
+            Op::Add | Op::Sub | Op::Multiply => {
+                let result = match op {
+                    Op::Add => Interval::new(lhs.lo + rhs.lo, lhs.hi + rhs.hi),
+                    Op::Sub => Interval::new(lhs.lo - rhs.hi, lhs.hi - rhs.lo),
+                    _ => corners(|a, b| a * b)
+                };
+                if result.lo < I32.lo || result.hi > I32.hi {
+                    self.report(loc, ProblemKind::Overflow(op, lhs, rhs));
+                }
+                result
+            },
+            Op::Divide | Op::Remainder => {
+                if rhs.contains(0) {
+                    self.report(loc, ProblemKind::DivideByZero(op, rhs));
+                }
+                // The one quotient that doesn't fit, which the remainder
+                // fails on too
+                if lhs.contains(I32.lo) && rhs.contains(-1) {
+                    self.report(loc, ProblemKind::Overflow(op, lhs, rhs));
+                    if lhs == Interval::constant(i32::MIN) && rhs == Interval::constant(-1) {
+                        return None;
+                    }
+                }
+
+                // Split the divisor either side of 0, on each side the
+                // result is largest and smallest at the corners
+                let sides = [rhs.meet(&Interval::new(I32.lo, -1)), rhs.meet(&Interval::new(1, I32.hi))];
+                let mut result: Option<Interval> = None;
+                for side in sides.into_iter().flatten() {
+                    let part = match op {
+                        Op::Divide => Interval::hull([lhs.lo / side.lo, lhs.lo / side.hi, lhs.hi / side.lo, lhs.hi / side.hi]),
+                        _ => {
+                            // The remainder is smaller than the divisor and
+                            // has the sign of what is divided
+                            let most = side.lo.abs().max(side.hi.abs()) - 1;
+                            Interval::new(if lhs.lo < 0 { lhs.lo.max(-most) } else { 0 }, if lhs.hi > 0 { lhs.hi.min(most) } else { 0 })
+                        }
+                    };
+                    result = Some(result.map_or(part, |r| r.join(&part)));
+                }
+                result?
+            },
+            Op::And => decided(!lhs.contains(0) && !rhs.contains(0), lhs == Interval::constant(0) || rhs == Interval::constant(0)),
+            Op::Or => decided(!lhs.contains(0) || !rhs.contains(0), lhs == Interval::constant(0) && rhs == Interval::constant(0)),
+            Op::Equal => decided(lhs.lo == lhs.hi && lhs == rhs, lhs.meet(&rhs).is_none()),
+            Op::NotEqual => decided(lhs.meet(&rhs).is_none(), lhs.lo == lhs.hi && lhs == rhs),
+            Op::LessThan => decided(lhs.hi < rhs.lo, lhs.lo >= rhs.hi),
+            Op::LessThanOrEqual => decided(lhs.hi <= rhs.lo, lhs.lo > rhs.hi),
+            Op::GreaterThan => decided(lhs.lo > rhs.hi, lhs.hi <= rhs.lo),
+            Op::GreaterThanOrEqual => decided(lhs.lo >= rhs.hi, lhs.hi < rhs.lo)
+        };
+
+        // Runs that overflow stop, so only values that fit carry on
+        return result.meet(&I32);
+    }
+
+    /// Narrows `ranges` to the runs where `condition` comes out as `truth`,
+    /// `None` if there are none
+    fn refine(&mut self, condition: &Expression, truth: bool, ranges: &Ranges, loc: Location) -> Option<Ranges> {
+        let value = self.exp(condition, ranges, loc)?;
+        let possible = if truth { value != Interval::constant(0) } else { value.contains(0) };
+        if !possible {
+            return None;
+        }
+
+        let mut ranges = ranges.clone();
+        match condition {
+            Expression::Var(var) if !truth => { ranges.insert(var.clone(), Interval::constant(0)); },
+            Expression::BinOp(Op::And, lhs, rhs) if truth => {
+                ranges = self.refine(lhs, true, &ranges, loc)?;
+                ranges = self.refine(rhs, true, &ranges, loc)?;
+            },
+            Expression::BinOp(Op::Or, lhs, rhs) if !truth => {
+                ranges = self.refine(lhs, false, &ranges, loc)?;
+                ranges = self.refine(rhs, false, &ranges, loc)?;
+            },
+            Expression::BinOp(op, lhs, rhs) => {
+                let Some(op) = (if truth { Some(*op) } else { negate(*op) }) else {
+                    return Some(ranges);
+                };
+
+                // A variable compared with something else
+                let (var, op, other) = match (lhs.as_ref(), rhs.as_ref()) {
+                    (Expression::Var(var), other) => (var, op, other),
+                    (other, Expression::Var(var)) => (var, flip(op), other),
+                    _ => return Some(ranges)
+                };
+                let (Some(current), Some(other)) = (ranges.get(var).copied(), self.exp(other, &ranges, loc)) else {
+                    return Some(ranges);
+                };
+
+                let narrowed = match op {
+                    Op::LessThan => current.meet(&Interval::new(I32.lo, other.hi - 1)),
+                    Op::LessThanOrEqual => current.meet(&Interval::new(I32.lo, other.hi)),
+                    Op::GreaterThan => current.meet(&Interval::new(other.lo + 1, I32.hi)),
+                    Op::GreaterThanOrEqual => current.meet(&Interval::new(other.lo, I32.hi)),
+                    Op::Equal => current.meet(&other),
+                    Op::NotEqual if other.lo == other.hi && current.lo == other.lo => current.meet(&Interval::new(other.lo + 1, I32.hi)),
+                    Op::NotEqual if other.lo == other.hi && current.hi == other.lo => current.meet(&Interval::new(I32.lo, other.lo - 1)),
+                    _ => Some(current)
+                };
+                ranges.insert(var.clone(), narrowed?);
+            },
+            _ => ()
+        }
+
+        return Some(ranges);
+    }
+
+    fn block(&mut self, block: &Block, ranges: Option<Ranges>) -> Option<Ranges> {
+        let mut ranges = ranges;
+
+        for stmt in block.statements.iter() {
+            let current = ranges?;
+            let loc = stmt.location();
+
+            let seen = self.before.get(&loc).cloned();
+            self.before.insert(loc, join(&seen, &Some(current.clone())).unwrap());
+
+            ranges = match stmt {
+                Statement::AssignStatement { var, exp, .. } => self.exp(exp, &current, loc).map(|value| {
+                    let seen = self.assigned.get(&loc).copied().unwrap_or(value);
+                    self.assigned.insert(loc, seen.join(&value));
+
+                    let mut after = current.clone();
+                    after.insert(var.clone(), value);
+                    after
+                }),
+                Statement::OutputStatement { to_output, .. } => self.exp(to_output, &current, loc).map(|_| current),
+                // Only runs where the assertion holds get past it
+                Statement::AssertStatement { exp, expected, .. } => {
+                    let condition = match expected {
+                        Some(expected) => Expression::BinOp(Op::Equal, Box::new(exp.clone()), Box::new(expected.clone())),
+                        None => exp.clone()
+                    };
+                    self.refine(&condition, true, &current, loc)
+                },
+                Statement::RepeatStatement { times, body, .. } => self.repeat(times, body, current, loc),
+                Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                    let mut after = None;
+                    let mut rest = Some(current);
+
+                    for (condition, block) in std::iter::once((condition, body)).chain(else_if.iter().map(|(c, b)| (c, b))) {
+                        let Some(state) = rest else {
+                            break;
+                        };
+                        let taken = self.refine(condition, true, &state, loc);
+                        after = join(&after, &self.block(block, taken));
+                        rest = self.refine(condition, false, &state, loc);
+                    }
+
+                    join(&after, &self.block(else_body, rest))
+                }
+            };
+        }
+
+        return ranges;
+    }
+
+    fn repeat(&mut self, times: &Expression, body: &Block, ranges: Ranges, loc: Location) -> Option<Ranges> {
+        let count = self.exp(times, &ranges, loc)?;
+        if count.lo < 0 {
+            self.report(loc, ProblemKind::NegativeRepeat(count));
+        }
+        // Only counts that aren't negative get past the check
+        let count = count.meet(&Interval::new(0, I32.hi))?;
+
+        if count.hi <= MAX_EXACT_ITERATIONS && count.hi <= self.budget {
+            let budget = self.budget;
+            self.budget /= count.hi.max(1);
+            let after = self.unroll(body, ranges, count);
+            self.budget = budget;
+            return after;
+        }
+
+        let mut head = Some(ranges);
+        loop {
+            let next = widen(&head, &join(&head, &self.block(body, head.clone())));
+            if next == head {
+                return head;
+            }
+            head = next;
+        }
+    }
+
+    /// Follows a REPEAT round exactly, `count` times at most
+    fn unroll(&mut self, body: &Block, ranges: Ranges, count: Interval) -> Option<Ranges> {
+        // What the variables can be after each number of times round
+        let mut current = Some(ranges);
+        let mut after = if count.lo == 0 { current.clone() } else { None };
+
+        for iteration in 1..=count.hi {
+            let next = self.block(body, current.clone());
+            if next == current {
+                // Every later time round is the same
+                return join(&after, &next);
+            }
+            current = next;
+            if iteration >= count.lo {
+                after = join(&after, &current);
+            }
+        }
+        return after;
+    }
+}
+
+/// What the interval analysis found
+#[derive(Debug, Default)]
+pub struct Intervals {
+    /// The range of each variable just before each statement runs, for
+    /// every statement that can be reached, including those in tests
+    pub before: BTreeMap<Location, Ranges>,
+    /// The range of what each assignment that can finish assigns
+    pub assigned: BTreeMap<Location, Interval>,
+    /// The ranges once the program, not counting its tests, has finished,
+    /// `None` if it can't
+    pub after: Option<Ranges>,
+    /// Everything that might go wrong, in source order
+    pub problems: Vec<Problem>
+}
+
+/// Works out a range for every variable at every statement, and what could
+/// overflow, divide by zero or give REPEAT a negative count
+pub fn intervals(program: &Program) -> Intervals {
+    let mut analysis = IntervalAnalysis{budget: MAX_EXACT_UNROLLING, ..Default::default()};
+    let after = analysis.block(&program.program, Some(Ranges::new()));
+    for test in program.tests.iter() {
+        analysis.block(&test.body, Some(Ranges::new()));
+    }
+
+    let mut problems = analysis.problems;
+    problems.sort_by_key(|p| p.loc);
+    return Intervals{before: analysis.before, assigned: analysis.assigned, after, problems};
+}
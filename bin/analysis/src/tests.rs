@@ -0,0 +1,240 @@
+use crate::*;
+
+fn reads(source: &str) -> Vec<String> {
+    uninitialised_reads(&parser::parse_source(source).unwrap()).iter().map(|r| r.to_string()).collect()
+}
+
+#[test]
+fn definitely_uninitialised() {
+    assert_eq!(reads("OUTPUT x + x;\nOUTPUT x;\ny = z;"), vec![
+        "1:1: Variable \"x\" is definitely uninitialised",
+        "3:1: Variable \"z\" is definitely uninitialised"
+    ]);
+
+    // Assigning in the same statement is too late
+    assert_eq!(reads("x = x + 1;"), vec!["1:1: Variable \"x\" is definitely uninitialised"]);
+}
+
+#[test]
+fn branches() {
+    let source = "IF (a) {
+    x = 1;
+    y = 1;
+} ELSEIF (b) {
+    x = 2;
+} ELSE {
+    x = 3;
+    y = 3;
+}
+OUTPUT x;
+OUTPUT y;
+IF (1) {
+    z = 1;
+} ELSEIF (z) {
+}";
+
+    assert_eq!(reads(source), vec![
+        "1:1: Variable \"a\" is definitely uninitialised",
+        "1:1: Variable \"b\" is definitely uninitialised",
+        "11:1: Variable \"y\" is possibly uninitialised",
+        // The ELSEIF condition only runs when the IF arm didn't
+        "12:1: Variable \"z\" is definitely uninitialised"
+    ]);
+}
+
+#[test]
+fn loops() {
+    let source = "n = 3;
+REPEAT (n) {
+    OUTPUT later;
+    later = 1;
+    always = 2;
+}
+OUTPUT always;
+REPEAT (2) {
+    sure = 1;
+}
+OUTPUT sure;
+REPEAT (0) {
+    never = 1;
+}
+OUTPUT never;";
+
+    // A REPEAT body might not run at all, unless its count says it does
+    assert_eq!(reads(source), vec![
+        "3:5: Variable \"later\" is possibly uninitialised",
+        "7:1: Variable \"always\" is possibly uninitialised",
+        "15:1: Variable \"never\" is definitely uninitialised"
+    ]);
+}
+
+/// Whatever the interpreter fails to read has to have been reported
+#[test]
+fn agrees_with_the_interpreter() {
+    assert!(reads(include_str!("../../../examples/fibbonachi.xa")).is_empty());
+    assert!(reads(include_str!("../../../examples/moreops.xa")).is_empty());
+
+    for source in [
+        "IF (0) {\n    x = 1;\n}\nOUTPUT x;",
+        "REPEAT (2) {\n    IF (y) {\n        OUTPUT 1;\n    }\n    y = 1;\n}",
+        "a = 1;\nREPEAT (a - 1) {\n    b = 1;\n}\nOUTPUT b;"
+    ] {
+        let program = parser::parse_source(source).unwrap();
+        let mut interpreter = interpreter::Interpreter::new();
+        let error = interpreter.run(&program).unwrap_err();
+
+        let interpreter::ErrorType::UninitialisedMemory(message) = error else { panic!("{:?}", error) };
+        let var = message.split('"').nth(1).unwrap();
+        assert!(
+            uninitialised_reads(&program).iter().any(|r| r.var == var && Some(r.loc) == interpreter.error_location()),
+            "{}", source
+        );
+    }
+}
+
+fn problems(source: &str) -> Vec<String> {
+    intervals(&parser::parse_source(source).unwrap()).problems.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+fn ranges_through_branches_and_loops() {
+    let source = "i = 0;
+j = 10;
+REPEAT (5) {
+    i = i + 2;
+    IF (i > 4) {
+        j = j - i;
+    }
+}
+OUTPUT i;
+REPEAT (1000) {
+    i = i + 1;
+}
+OUTPUT i;";
+    let found = intervals(&parser::parse_source(source).unwrap());
+    let before = |line, column| found.before[&Location::new(line, column)].clone();
+
+    // Short loops are followed round exactly
+    assert_eq!(before(9, 1)["i"], Interval::constant(10));
+    assert_eq!(before(9, 1)["j"], Interval::constant(-14));
+    assert_eq!(before(6, 9)["i"], Interval::new(6, 10));
+    assert_eq!(before(4, 5)["i"], Interval::new(0, 8));
+    assert_eq!(found.assigned[&Location::new(4, 5)], Interval::new(2, 10));
+
+    // Long ones are widened, so only have a lower bound
+    assert_eq!(before(13, 1)["i"], Interval::new(10, i32::MAX as i64));
+    assert_eq!(problems(source), vec!["11:5: addition of [10, 2147483647] and 1 can overflow"]);
+}
+
+#[test]
+fn nested_loops_stay_quick() {
+    let mut source = String::from("x = 0;\n");
+    for depth in 0..6 {
+        source.push_str(&format!("{}REPEAT (60) {{\n", "    ".repeat(depth)));
+    }
+    source.push_str(&format!("{}x = x + 1;\n", "    ".repeat(6)));
+    for depth in (0..6).rev() {
+        source.push_str(&format!("{}}}\n", "    ".repeat(depth)));
+    }
+
+    // Following every level exactly would take hours
+    let start = std::time::Instant::now();
+    let found = intervals(&parser::parse_source(&source).unwrap());
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+    // Once the budget is spent the inner levels are widened, so x ends up
+    // with only a lower bound
+    assert_eq!(found.before[&Location::new(2, 1)]["x"], Interval::constant(0));
+    assert_eq!(found.before[&Location::new(3, 5)]["x"].lo, 0);
+    assert_eq!(found.after.unwrap()["x"].hi, i32::MAX as i64);
+}
+
+#[test]
+fn arithmetic_that_can_fail() {
+    // Nothing after something that always fails can be reached
+    assert_eq!(problems("x = 2147483647;\nOUTPUT x - 1;\nOUTPUT x * 0 - x * x;\nOUTPUT x + 1;"), vec![
+        "3:1: multiplication of 2147483647 and 2147483647 can overflow"
+    ]);
+
+    // Conditions narrow what each arm sees
+    let source = "k = 0;
+REPEAT (100) {
+    IF (k < 7) {
+        k = k + 1;
+    }
+}
+IF (k > 3) {
+    OUTPUT 10 / k;
+} ELSEIF (k != 0) {
+    OUTPUT 10 % k;
+} ELSE {
+    OUTPUT 10 / k;
+}
+REPEAT (k - 2) {
+}
+j = k - 1;
+OUTPUT 1 % j;";
+    assert_eq!(problems(source), vec![
+        "12:5: division by 0, which can be zero",
+        "14:1: REPEAT count [-1, 2147483645] can be negative",
+        "17:1: remainder by [0, 2147483646], which can be zero"
+    ]);
+
+    // The smallest value divided by -1 doesn't fit
+    let source = "m = 0 - 2147483647 - 1;
+n = m + 1;
+d = 0 - 3;
+REPEAT (2) {
+    d = d + 1;
+    OUTPUT n / d;
+    OUTPUT m % d;
+}
+OUTPUT m / d;";
+    assert_eq!(problems(source), vec!["7:5: remainder of -2147483648 and -1 can overflow"]);
+    assert_eq!(problems("m = 0 - 2147483647 - 1;\nd = 0 - 1;\nOUTPUT m / d;"), vec!["3:1: division of -2147483648 and -1 can overflow"]);
+}
+
+/// Every way the interpreter fails on arithmetic has to have been reported
+#[test]
+fn reports_what_the_interpreter_hits() {
+    assert!(problems(include_str!("../../../examples/fibbonachi.xa")).is_empty());
+    assert!(problems(include_str!("../../../examples/moreops.xa")).is_empty());
+
+    for source in [
+        "x = 1;\nREPEAT (40) {\n    x = x * 2;\n}",
+        "x = 3;\nREPEAT (5) {\n    IF (x > 0) {\n        x = x - 1;\n    }\n}\nOUTPUT 1 / x;",
+        "a = 0 - 1;\nb = 3;\nREPEAT (a * b) {\n}",
+        "m = 0 - 2147483647 - 1;\nd = 0 - 1;\nOUTPUT m % d;"
+    ] {
+        let program = parser::parse_source(source).unwrap();
+        let loc = match ir::run(&ir::lower(&program)) {
+            Err(e) => e.loc,
+            Ok(output) => panic!("{:?}", output)
+        };
+        assert!(intervals(&program).problems.iter().any(|p| p.loc == loc), "{}", source);
+    }
+}
+
+#[test]
+fn assertions_and_tests() {
+    let source = "x = 0;
+REPEAT (1000) {
+    ASSERT (x < 50);
+    x = x + 1;
+}
+OUTPUT x;
+TEST \"starts empty\" {
+    ASSERT_EQ (x, 4);
+    y = x * 2;
+}";
+    let found = intervals(&parser::parse_source(source).unwrap());
+
+    // Only runs where the assertion held get past it, so nothing overflows
+    assert_eq!(found.before[&Location::new(4, 5)]["x"], Interval::new(0, 49));
+    assert!(found.problems.is_empty(), "{:?}", found.problems);
+    assert_eq!(found.after.unwrap()["x"], Interval::new(0, i32::MAX as i64));
+
+    // A test has nothing assigned when it starts
+    assert_eq!(reads(source), vec!["8:5: Variable \"x\" is definitely uninitialised"]);
+    assert!(!found.before.contains_key(&Location::new(9, 5)));
+}
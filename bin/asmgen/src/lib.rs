@@ -0,0 +1,569 @@
+//! Compiles a program to x86-64 assembly in GNU `as` syntax for System V
+//! Linux. The program is a `main` function, so link it with `cc`. Outputs
+//! and runtime errors are written with system calls by a small runtime at
+//! the end of the file, errors as `line:column: message` on stderr followed
+//! by exit status 1.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use language::*;
+
+const RUNTIME: &str = "
+# Writes %edi in decimal and a newline to stdout
+xa_output:
+    subq $40, %rsp
+    leaq 32(%rsp), %r8
+    movb $10, (%r8)
+    movslq %edi, %rax
+    movq %rax, %r9
+    testq %rax, %rax
+    jns 1f
+    negq %rax
+1:
+    movl $10, %ecx
+2:
+    xorl %edx, %edx
+    divq %rcx
+    addb $48, %dl
+    decq %r8
+    movb %dl, (%r8)
+    testq %rax, %rax
+    jnz 2b
+    testq %r9, %r9
+    jns 3f
+    decq %r8
+    movb $45, (%r8)
+3:
+    movl $1, %eax
+    movl $1, %edi
+    movq %r8, %rsi
+    leaq 33(%rsp), %rdx
+    subq %r8, %rdx
+    syscall
+    addq $40, %rsp
+    ret
+
+# Writes the %esi bytes at %rdi to stderr and exits with status 1
+xa_fail:
+    movl %esi, %edx
+    movq %rdi, %rsi
+    movl $2, %edi
+    movl $1, %eax
+    syscall
+    movl $1, %edi
+    movl $60, %eax
+    syscall
+";
+
+/// Callee saved registers variables can live in, by their 64 and 32 bit
+/// names. `xa_output` and `xa_fail` leave them alone
+const REGISTERS: [(&str, &str); 5] = [
+    ("%rbx", "%ebx"),
+    ("%r12", "%r12d"),
+    ("%r13", "%r13d"),
+    ("%r14", "%r14d"),
+    ("%r15", "%r15d")
+];
+
+// How much more a use inside one more REPEAT is worth to the allocator
+const LOOP_WEIGHT: u64 = 8;
+
+/// Name of the hidden counter of the nth REPEAT, which can't clash with a
+/// variable as those are letters only
+fn counter(n: usize) -> String {
+    format!("#{}", n)
+}
+
+/// Adds up how much each variable and REPEAT counter is used, uses in loops
+/// counting for more
+struct Weigher {
+    weights: BTreeMap<String, u64>,
+    repeats: usize
+}
+
+impl Weigher {
+    fn add(&mut self, name: &str, weight: u64) {
+        *self.weights.entry(name.to_string()).or_insert(0) += weight;
+    }
+
+    fn exp(&mut self, exp: &Expression, weight: u64) {
+        match exp {
+            Expression::Val(_) => (),
+            Expression::Var(v) => self.add(v, weight),
+            Expression::BinOp(_, lhs, rhs) => {
+                self.exp(lhs, weight);
+                self.exp(rhs, weight);
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block, weight: u64) {
+        for stmt in block.statements.iter() {
+            match stmt {
+                Statement::AssignStatement { var, exp, .. } => {
+                    self.add(var, weight);
+                    self.exp(exp, weight);
+                },
+                Statement::OutputStatement { to_output, .. } => self.exp(to_output, weight),
+                Statement::AssertStatement { exp, expected, .. } => {
+                    self.exp(exp, weight);
+                    if let Some(expected) = expected {
+                        self.exp(expected, weight);
+                    }
+                },
+                Statement::RepeatStatement { times, body, .. } => {
+                    self.repeats += 1;
+                    let inner = weight.saturating_mul(LOOP_WEIGHT);
+                    // Tested and decremented every time round
+                    self.add(&counter(self.repeats), inner.saturating_mul(2));
+                    self.exp(times, weight);
+                    self.block(body, inner);
+                },
+                Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                    self.exp(condition, weight);
+                    self.block(body, weight);
+                    for (exp, block) in else_if {
+                        self.exp(exp, weight);
+                        self.block(block, weight);
+                    }
+                    self.block(else_body, weight);
+                }
+            }
+        }
+    }
+}
+
+/// Where a variable or counter lives, stack slots are offsets from %rbp
+#[derive(Debug, Clone, PartialEq)]
+enum Home {
+    Register(usize),
+    Stack(i32)
+}
+
+/// Gives the most used variables and counters a register each and the rest
+/// a stack slot. Variables that are read also get a byte saying if they
+/// have been assigned
+struct Allocation {
+    homes: BTreeMap<String, Home>,
+    flags: BTreeMap<String, i32>,
+    /// Registers used, all of them have to be saved
+    saved: usize,
+    /// Bytes below the saved registers
+    frame: i32
+}
+
+impl Allocation {
+    fn new(program: &Program) -> Self {
+        let read = program.program.read_variables();
+
+        let mut weigher = Weigher{weights: BTreeMap::new(), repeats: 0};
+        weigher.block(&program.program, 1);
+
+        // A variable that is only ever written never needs storing
+        let mut wanted: Vec<(String, u64)> = weigher.weights.into_iter()
+            .filter(|(name, _)| name.starts_with('#') || read.contains(name))
+            .collect();
+        wanted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let saved = usize::min(wanted.len(), REGISTERS.len());
+        let mut offset = 8 * saved as i32;
+        let mut homes = BTreeMap::new();
+        for (i, (name, _)) in wanted.into_iter().enumerate() {
+            let home = match i < REGISTERS.len() {
+                true => Home::Register(i),
+                false => {
+                    offset += 4;
+                    Home::Stack(-offset)
+                }
+            };
+            homes.insert(name, home);
+        }
+
+        let mut flags = BTreeMap::new();
+        for var in read {
+            offset += 1;
+            flags.insert(var, -offset);
+        }
+
+        // Keep %rsp 16 byte aligned once the frame is set up
+        let frame = (offset + 15) / 16 * 16 - 8 * saved as i32;
+
+        Self{homes, flags, saved, frame}
+    }
+
+    fn operand(&self, name: &str) -> String {
+        match &self.homes[name] {
+            Home::Register(r) => REGISTERS[*r].1.to_string(),
+            Home::Stack(offset) => format!("{}(%rbp)", offset)
+        }
+    }
+}
+
+fn inverse_jump(op: Op) -> Option<&'static str> {
+    match op {
+        Op::Equal => Some("jne"),
+        Op::NotEqual => Some("je"),
+        Op::LessThan => Some("jge"),
+        Op::LessThanOrEqual => Some("jg"),
+        Op::GreaterThan => Some("jle"),
+        Op::GreaterThanOrEqual => Some("jl"),
+        _ => None
+    }
+}
+
+fn set_instruction(op: Op) -> &'static str {
+    match op {
+        Op::Equal => "sete",
+        Op::NotEqual => "setne",
+        Op::LessThan => "setl",
+        Op::LessThanOrEqual => "setle",
+        Op::GreaterThan => "setg",
+        _ => "setge"
+    }
+}
+
+struct Generator {
+    out: String,
+    allocation: Allocation,
+    labels: usize,
+    repeats: usize,
+    /// Messages for runtime errors with the location already in, the
+    /// label for each is `.Lerror` and its position
+    errors: Vec<String>
+}
+
+impl Generator {
+    fn line(&mut self, text: &str) {
+        self.out.push_str("    ");
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn place(&mut self, label: &str) {
+        writeln!(self.out, "{}:", label).unwrap();
+    }
+
+    /// Label to jump to for the runtime error `message` at `loc`
+    fn error(&mut self, message: &str, loc: Location) -> String {
+        let text = format!("{}: {}\n", loc, message);
+        let index = match self.errors.iter().position(|t| *t == text) {
+            Some(i) => i,
+            None => {
+                self.errors.push(text);
+                self.errors.len() - 1
+            }
+        };
+
+        return format!(".Lerror{}", index);
+    }
+
+    fn check_assigned(&mut self, var: &str, loc: Location) {
+        let flag = self.allocation.flags[var];
+        let error = self.error(&format!("Variable \"{}\" has not been assigned", var), loc);
+        self.line(&format!("cmpb $0, {}(%rbp)", flag));
+        self.line(&format!("je {}", error));
+    }
+
+    /// An operand for `exp` if it needs no instructions to work out
+    fn simple(&mut self, exp: &Expression, loc: Location) -> Option<String> {
+        match exp {
+            Expression::Val(v) => Some(format!("${}", v)),
+            Expression::Var(var) => {
+                self.check_assigned(var, loc);
+                Some(self.allocation.operand(var))
+            },
+            Expression::BinOp(..) => None
+        }
+    }
+
+    /// Puts the value of `exp` in %eax
+    fn exp(&mut self, exp: &Expression, loc: Location) {
+        match exp {
+            Expression::BinOp(op, lhs, rhs) => {
+                let rhs = self.operands(lhs, rhs, loc);
+                self.apply(*op, &rhs, loc);
+            },
+            _ => {
+                let operand = self.simple(exp, loc).unwrap();
+                self.line(&format!("movl {}, %eax", operand));
+            }
+        }
+    }
+
+    /// Puts the value of `lhs` in %eax and returns an operand for `rhs`,
+    /// evaluating them in that order
+    fn operands(&mut self, lhs: &Expression, rhs: &Expression, loc: Location) -> String {
+        self.exp(lhs, loc);
+
+        if let Some(operand) = self.simple(rhs, loc) {
+            return operand;
+        }
+
+        self.line("pushq %rax");
+        self.exp(rhs, loc);
+        self.line("movl %eax, %ecx");
+        self.line("popq %rax");
+        return "%ecx".to_string();
+    }
+
+    fn apply(&mut self, op: Op, rhs: &str, loc: Location) {
+        match op {
+            Op::Add | Op::Sub | Op::Multiply => {
+                let (instr, message) = match op {
+                    Op::Add => ("addl", "addition overflowed"),
+                    Op::Sub => ("subl", "subtraction overflowed"),
+                    _ => ("imull", "multiplication overflowed")
+                };
+                let error = self.error(message, loc);
+                self.line(&format!("{} {}, %eax", instr, rhs));
+                self.line(&format!("jo {}", error));
+            },
+            Op::Divide | Op::Remainder => {
+                let (zero, overflow) = match op {
+                    Op::Divide => ("division by zero", "division overflowed"),
+                    _ => ("remainder by zero", "remainder overflowed")
+                };
+                let zero = self.error(zero, loc);
+                let overflow = self.error(overflow, loc);
+                let ok = self.label();
+
+                if rhs != "%ecx" {
+                    self.line(&format!("movl {}, %ecx", rhs));
+                }
+                self.line("testl %ecx, %ecx");
+                self.line(&format!("je {}", zero));
+                self.line("cmpl $-1, %ecx");
+                self.line(&format!("jne {}", ok));
+                self.line("cmpl $-2147483648, %eax");
+                self.line(&format!("je {}", overflow));
+                self.place(&ok);
+                self.line("cltd");
+                self.line("idivl %ecx");
+                if op == Op::Remainder {
+                    self.line("movl %edx, %eax");
+                }
+            },
+            Op::And | Op::Or => {
+                if rhs != "%ecx" {
+                    self.line(&format!("movl {}, %ecx", rhs));
+                }
+                self.line("testl %eax, %eax");
+                self.line("setne %al");
+                self.line("testl %ecx, %ecx");
+                self.line("setne %cl");
+                self.line(&format!("{} %cl, %al", if op == Op::And { "andb" } else { "orb" }));
+                self.line("movzbl %al, %eax");
+            },
+            _ => {
+                self.line(&format!("cmpl {}, %eax", rhs));
+                self.line(&format!("{} %al", set_instruction(op)));
+                self.line("movzbl %al, %eax");
+            }
+        }
+    }
+
+    /// Jumps to `target` if `exp` is false. Comparisons jump on the flags
+    /// straight away rather than making a 0 or 1 first
+    fn jump_if_false(&mut self, exp: &Expression, target: &str, loc: Location) {
+        if let Expression::BinOp(op, lhs, rhs) = exp {
+            if let Some(jump) = inverse_jump(*op) {
+                // A variable can be compared where it lives, evaluating the
+                // other side can't change it
+                let (lhs, rhs) = match lhs.as_ref() {
+                    Expression::Var(_) => {
+                        let lhs = self.simple(lhs, loc).unwrap();
+                        let rhs = match self.simple(rhs, loc) {
+                            Some(r) if !(r.ends_with("(%rbp)") && lhs.ends_with("(%rbp)")) => r,
+                            Some(r) => {
+                                self.line(&format!("movl {}, %ecx", r));
+                                "%ecx".to_string()
+                            },
+                            None => {
+                                self.exp(rhs, loc);
+                                self.line("movl %eax, %ecx");
+                                "%ecx".to_string()
+                            }
+                        };
+                        (lhs, rhs)
+                    },
+                    _ => {
+                        let rhs = self.operands(lhs, rhs, loc);
+                        ("%eax".to_string(), rhs)
+                    }
+                };
+
+                self.line(&format!("cmpl {}, {}", rhs, lhs));
+                self.line(&format!("{} {}", jump, target));
+                return;
+            }
+        }
+
+        self.exp(exp, loc);
+        self.line("testl %eax, %eax");
+        self.line(&format!("je {}", target));
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        let loc = stmt.location();
+        writeln!(self.out, "    # {}", loc).unwrap();
+
+        match stmt {
+            Statement::AssignStatement { var, exp, .. } => {
+                if !self.allocation.homes.contains_key(var) {
+                    // Never read so only the errors it could cause matter
+                    self.exp(exp, loc);
+                    return;
+                }
+
+                let home = self.allocation.operand(var);
+                match exp {
+                    Expression::Val(v) => self.line(&format!("movl ${}, {}", v, home)),
+                    _ => {
+                        self.exp(exp, loc);
+                        self.line(&format!("movl %eax, {}", home));
+                    }
+                }
+                let flag = self.allocation.flags[var];
+                self.line(&format!("movb $1, {}(%rbp)", flag));
+            },
+            Statement::OutputStatement { to_output, .. } => {
+                self.exp(to_output, loc);
+                self.line("movl %eax, %edi");
+                self.line("call xa_output");
+            },
+            Statement::AssertStatement { exp, expected, .. } => {
+                let failed = self.error("assertion failed", loc);
+                match expected {
+                    Some(expected) => {
+                        let equal = Expression::BinOp(Op::Equal, Box::new(exp.clone()), Box::new(expected.clone()));
+                        self.jump_if_false(&equal, &failed, loc);
+                    },
+                    None => self.jump_if_false(exp, &failed, loc)
+                }
+            },
+            Statement::RepeatStatement { times, body, .. } => {
+                self.repeats += 1;
+                let count = self.allocation.operand(&counter(self.repeats));
+                let negative = self.error("REPEAT count is negative", loc);
+                let (top, end) = (self.label(), self.label());
+
+                self.exp(times, loc);
+                self.line("testl %eax, %eax");
+                self.line(&format!("js {}", negative));
+                self.line(&format!("movl %eax, {}", count));
+                self.place(&top);
+                self.line(&format!("cmpl $0, {}", count));
+                self.line(&format!("je {}", end));
+                self.line(&format!("decl {}", count));
+                self.block(body);
+                self.line(&format!("jmp {}", top));
+                self.place(&end);
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                let end = self.label();
+
+                let mut arms = vec![(condition, body)];
+                arms.extend(else_if.iter().map(|(exp, block)| (exp, block)));
+
+                for (exp, block) in arms {
+                    let next = self.label();
+                    self.jump_if_false(exp, &next, loc);
+                    self.block(block);
+                    self.line(&format!("jmp {}", end));
+                    self.place(&next);
+                }
+
+                self.block(else_body);
+                self.place(&end);
+            }
+        }
+    }
+}
+
+/// Escapes `text` for a `.ascii` directive
+fn ascii(text: &str) -> String {
+    let mut result = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(result, "\\{}", byte as char).unwrap(),
+            0x20..=0x7e => result.push(byte as char),
+            _ => write!(result, "\\{:03o}", byte).unwrap()
+        }
+    }
+    return result;
+}
+
+/// The assembly for `program`
+pub fn generate(program: &Program) -> String {
+    let allocation = Allocation::new(program);
+    let saved = allocation.saved;
+    let frame = allocation.frame;
+    let flags: BTreeSet<i32> = allocation.flags.values().cloned().collect();
+
+    let mut gen = Generator{
+        out: String::new(),
+        allocation,
+        labels: 0,
+        repeats: 0,
+        errors: Vec::new()
+    };
+    gen.block(&program.program);
+
+    let mut result = String::from("# Generated from an .xa program\n    .text\n    .globl main\nmain:\n");
+    writeln!(result, "    pushq %rbp").unwrap();
+    writeln!(result, "    movq %rsp, %rbp").unwrap();
+    for (reg, _) in REGISTERS.iter().take(saved) {
+        writeln!(result, "    pushq {}", reg).unwrap();
+    }
+    if frame > 0 {
+        writeln!(result, "    subq ${}, %rsp", frame).unwrap();
+    }
+    for flag in flags {
+        writeln!(result, "    movb $0, {}(%rbp)", flag).unwrap();
+    }
+
+    result.push_str(&gen.out);
+
+    writeln!(result, "    # end").unwrap();
+    if frame > 0 {
+        writeln!(result, "    addq ${}, %rsp", frame).unwrap();
+    }
+    for (reg, _) in REGISTERS.iter().take(saved).rev() {
+        writeln!(result, "    popq {}", reg).unwrap();
+    }
+    writeln!(result, "    popq %rbp").unwrap();
+    writeln!(result, "    xorl %eax, %eax").unwrap();
+    writeln!(result, "    ret").unwrap();
+
+    for (i, text) in gen.errors.iter().enumerate() {
+        writeln!(result, ".Lerror{}:", i).unwrap();
+        writeln!(result, "    leaq .Lmessage{}(%rip), %rdi", i).unwrap();
+        writeln!(result, "    movl ${}, %esi", text.len()).unwrap();
+        writeln!(result, "    jmp xa_fail").unwrap();
+    }
+
+    result.push_str(RUNTIME);
+
+    writeln!(result, "\n    .section .rodata").unwrap();
+    for (i, text) in gen.errors.iter().enumerate() {
+        writeln!(result, ".Lmessage{}:\n    .ascii \"{}\"", i, ascii(text)).unwrap();
+    }
+    writeln!(result, "\n    .section .note.GNU-stack,\"\",@progbits").unwrap();
+
+    return result;
+}
@@ -0,0 +1,112 @@
+use crate::*;
+
+use conformance::{matches_interpreter, Backend};
+
+/// Assembled and linked by the system compiler
+const ASM: Backend = Backend{
+    name: "asmgen",
+    file: "program.s",
+    generate,
+    suffix: "",
+    compiler: &["cc"],
+    outputs_before_errors: true
+};
+
+#[test]
+fn examples() {
+    matches_interpreter(&ASM, "fibbonachi", include_str!("../../../examples/fibbonachi.xa"));
+    matches_interpreter(&ASM, "moreops", include_str!("../../../examples/moreops.xa"));
+}
+
+#[test]
+fn control_flow() {
+    matches_interpreter(&ASM, "control_flow", "n = 3;
+unused = 7;
+REPEAT (n) {
+    n = n + 1;
+    IF (n == 4) {
+        OUTPUT 40;
+    } ELSEIF (n == 5) {
+        OUTPUT 50;
+    } ELSEIF (n == 6 && 1) {
+        OUTPUT 60;
+    } ELSE {
+        OUTPUT 0;
+    }
+    REPEAT (0) {
+        OUTPUT 1;
+    }
+}
+OUTPUT n;
+OUTPUT 7 - 9 * 2 >= 0 - 11 || 0;");
+}
+
+#[test]
+fn spills_to_the_stack() {
+    // More variables than registers, the ones used in the loop get them
+    matches_interpreter(&ASM, "spills", "a = 1;
+b = 2;
+c = 3;
+d = 4;
+e = 5;
+f = 6;
+g = 0;
+REPEAT (10) {
+    g = g + f * e - d / c % b;
+    IF (g > 20 && a) {
+        OUTPUT g;
+    }
+}
+OUTPUT a + b + c + d + e + f + g;");
+
+    let asm = generate(&parser::parse_source("a = 1;
+b = 2;
+REPEAT (a) {
+    OUTPUT b;
+}
+OUTPUT a;").unwrap());
+    // The counter and b are used most, then a
+    assert!(asm.contains("movl $2, %r12d"), "{}", asm);
+    assert!(asm.contains("movl $1, %r13d"), "{}", asm);
+    assert!(asm.contains("decl %ebx"), "{}", asm);
+}
+
+#[test]
+fn comparisons_jump() {
+    matches_interpreter(&ASM, "comparisons", "x = 5;
+IF (x < 6) { OUTPUT 1; }
+IF (x <= 4) { OUTPUT 2; }
+IF (x > 4) { OUTPUT 3; }
+IF (x >= 6) { OUTPUT 4; }
+IF (x == 5) { OUTPUT 5; }
+IF (x != 5) { OUTPUT 6; }
+OUTPUT x < 6;
+OUTPUT x >= 6;");
+
+    let asm = generate(&parser::parse_source("x = 5;\nIF (x < 6) { OUTPUT 1; }").unwrap());
+    assert!(asm.contains("cmpl $6, %ebx\n    jge .L"), "{}", asm);
+}
+
+#[test]
+fn runtime_errors() {
+    let run = matches_interpreter(&ASM, "negative", "OUTPUT 1;\nx = 0 - 2;\nREPEAT (x) {\n    OUTPUT 2;\n}");
+    assert_eq!(run.outputs, vec![1]);
+    assert_eq!(run.error.as_deref(), Some("3:1: REPEAT count is negative"));
+
+    let run = matches_interpreter(&ASM, "unassigned", "IF (0) {\n    x = 1;\n}\nOUTPUT x;");
+    assert_eq!(run.error.as_deref(), Some("4:1: Variable \"x\" has not been assigned"));
+
+    let run = matches_interpreter(&ASM, "assertion", "x = 2;\nASSERT (x > 1);\nASSERT_EQ (x * 2, 4);\nOUTPUT x;\nASSERT_EQ (x, 3);");
+    assert_eq!(run.outputs, vec![2]);
+    assert_eq!(run.error.as_deref(), Some("5:1: assertion failed"));
+}
+
+#[test]
+fn arithmetic_errors() {
+    let run = matches_interpreter(&ASM, "overflow", "x = 2147483647;\nOUTPUT x;\nOUTPUT x + 1;");
+    assert_eq!(run.error.as_deref(), Some("3:1: addition overflowed"));
+
+    let run = matches_interpreter(&ASM, "divide", "x = 0;\nOUTPUT 5 % 3;\nOUTPUT 1 / x;");
+    assert_eq!(run.outputs, vec![2]);
+    assert_eq!(run.error.as_deref(), Some("3:1: division by zero"));
+}
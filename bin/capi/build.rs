@@ -0,0 +1,4 @@
+fn main() {
+    // The C test compiles for the same target as the library
+    println!("cargo:rustc-env=TARGET={}", std::env::var("TARGET").unwrap());
+}
@@ -0,0 +1,65 @@
+#ifndef XA_H
+#define XA_H
+
+// C interface for running .xa programs, built from bin/capi as libxa.
+//
+// Ownership rules:
+//  - An xa_interpreter is created by xa_interpreter_new and must be released
+//    with exactly one call to xa_interpreter_free.
+//  - Source passed to xa_run_source is copied, the caller keeps ownership.
+//  - Pointers returned by xa_output and xa_error_message belong to the
+//    interpreter. They stay valid until the next call to xa_run_source,
+//    xa_interpreter_reset or xa_interpreter_free on the same interpreter.
+//  - An interpreter must not be used from more than one thread at a time.
+//
+// No function unwinds into the caller. If the library hits a bug, functions
+// returning xa_status give XA_INTERNAL_ERROR and the others NULL or 0.
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct xa_interpreter xa_interpreter;
+
+typedef enum xa_status {
+    XA_OK = 0,
+    XA_PARSE_ERROR = 1,
+    XA_RUNTIME_ERROR = 2,
+    XA_NULL_ARGUMENT = 3,
+    XA_UNKNOWN_VARIABLE = 4,
+    XA_INTERNAL_ERROR = 5
+} xa_status;
+
+// A new interpreter with no variables. Running out of memory aborts the
+// process, as it does anywhere else in the library
+xa_interpreter *xa_interpreter_new(void);
+// Does nothing when given NULL
+void xa_interpreter_free(xa_interpreter *interp);
+// Forgets all variables, outputs and errors
+void xa_interpreter_reset(xa_interpreter *interp);
+
+// Parses and runs len bytes of source. Variables from earlier runs are kept,
+// outputs and errors from earlier runs are cleared.
+xa_status xa_run_source(xa_interpreter *interp, const char *source, size_t len);
+
+// Values produced by OUTPUT statements during the last run
+size_t xa_output_len(const xa_interpreter *interp);
+const int32_t *xa_output(const xa_interpreter *interp);
+
+// Writes the value of a variable to *value
+xa_status xa_get_variable(const xa_interpreter *interp, const char *name, int32_t *value);
+
+// NULL terminated description of the last error, NULL if the last run worked
+const char *xa_error_message(const xa_interpreter *interp);
+// 1 based position of the last error, 0 if there is no error or it has no position
+uint32_t xa_error_line(const xa_interpreter *interp);
+uint32_t xa_error_column(const xa_interpreter *interp);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,214 @@
+//! C interface to the interpreter, declared in `include/xa.h`.
+//! Every function here has to keep the ownership rules written in the header.
+
+#[cfg(test)]
+mod tests;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use interpreter::Interpreter;
+use language::Location;
+use lexer::Lexer;
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum xa_status {
+    XA_OK = 0,
+    XA_PARSE_ERROR = 1,
+    XA_RUNTIME_ERROR = 2,
+    XA_NULL_ARGUMENT = 3,
+    XA_UNKNOWN_VARIABLE = 4,
+    XA_INTERNAL_ERROR = 5
+}
+
+/// Runs `f`, giving `failed` instead if it panics, as a panic must not
+/// unwind into C
+fn guarded<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    return panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(failed);
+}
+
+/// Opaque to C, only ever handed out as a pointer from `xa_interpreter_new`
+#[allow(non_camel_case_types)]
+pub struct xa_interpreter {
+    interpreter: Interpreter,
+    error: Option<CString>,
+    error_location: Option<Location>
+}
+
+impl xa_interpreter {
+    fn new() -> Self {
+        Self{interpreter: Interpreter::new(), error: None, error_location: None}
+    }
+
+    fn set_error(&mut self, message: String, location: Option<Location>) {
+        // Messages never contain a NUL but don't lose the error if one did
+        self.error = Some(CString::new(message.replace('\0', " ")).unwrap());
+        self.error_location = location;
+    }
+
+    fn run_source(&mut self, source: &[u8]) -> xa_status {
+        self.interpreter.output_vec.clear();
+        self.error = None;
+        self.error_location = None;
+
+        let mut context = Lexer::from_source(source);
+        let program = match parser::gen_ast(&mut context) {
+            Ok(p) => p,
+            Err(e) => {
+                // The parse error message already says where it happened
+                self.set_error(e.to_string(), e.location());
+                return xa_status::XA_PARSE_ERROR;
+            }
+        };
+
+        match self.interpreter.run(&program) {
+            Ok(()) => xa_status::XA_OK,
+            Err(e) => {
+                let location = self.interpreter.error_location();
+                let message = match location {
+                    Some(loc) => format!("{}: {}", loc, e),
+                    None => e.to_string()
+                };
+                self.set_error(message, location);
+                xa_status::XA_RUNTIME_ERROR
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xa_interpreter_new() -> *mut xa_interpreter {
+    guarded(ptr::null_mut(), || Box::into_raw(Box::new(xa_interpreter::new())))
+}
+
+/// # Safety
+/// `interp` must be null or a pointer from `xa_interpreter_new` that has not
+/// already been freed
+#[no_mangle]
+pub unsafe extern "C" fn xa_interpreter_free(interp: *mut xa_interpreter) {
+    guarded((), || if !interp.is_null() {
+        drop(Box::from_raw(interp));
+    })
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new`
+#[no_mangle]
+pub unsafe extern "C" fn xa_interpreter_reset(interp: *mut xa_interpreter) {
+    guarded((), || if let Some(interp) = interp.as_mut() {
+        *interp = xa_interpreter::new();
+    })
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new` and
+/// `source` must be null or point to at least `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn xa_run_source(interp: *mut xa_interpreter, source: *const c_char, len: usize) -> xa_status {
+    let interp = match interp.as_mut() {
+        Some(i) => i,
+        None => return xa_status::XA_NULL_ARGUMENT
+    };
+
+    if source.is_null() {
+        return xa_status::XA_NULL_ARGUMENT;
+    }
+
+    let source = std::slice::from_raw_parts(source as *const u8, len);
+    match panic::catch_unwind(AssertUnwindSafe(|| interp.run_source(source))) {
+        Ok(status) => status,
+        Err(payload) => {
+            let message = match payload.downcast_ref::<&str>() {
+                Some(m) => m.to_string(),
+                None => payload.downcast_ref::<String>().cloned().unwrap_or_default()
+            };
+            interp.set_error(format!("internal error: {}", message), None);
+            xa_status::XA_INTERNAL_ERROR
+        }
+    }
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new`
+#[no_mangle]
+pub unsafe extern "C" fn xa_output_len(interp: *const xa_interpreter) -> usize {
+    guarded(0, || match interp.as_ref() {
+        Some(i) => i.interpreter.output_vec.len(),
+        None => 0
+    })
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new`
+#[no_mangle]
+pub unsafe extern "C" fn xa_output(interp: *const xa_interpreter) -> *const i32 {
+    guarded(ptr::null(), || match interp.as_ref() {
+        Some(i) => i.interpreter.output_vec.as_ptr(),
+        None => ptr::null()
+    })
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new`, `name`
+/// must be null or a NUL terminated string and `value` must be null or valid
+/// to write an `int32_t` to
+#[no_mangle]
+pub unsafe extern "C" fn xa_get_variable(interp: *const xa_interpreter, name: *const c_char, value: *mut i32) -> xa_status {
+    guarded(xa_status::XA_INTERNAL_ERROR, || {
+        let interp = match interp.as_ref() {
+            Some(i) => i,
+            None => return xa_status::XA_NULL_ARGUMENT
+        };
+
+        if name.is_null() || value.is_null() {
+            return xa_status::XA_NULL_ARGUMENT;
+        }
+
+        let name = match CStr::from_ptr(name).to_str() {
+            Ok(n) => n,
+            Err(_) => return xa_status::XA_UNKNOWN_VARIABLE
+        };
+
+        match interp.interpreter.memory.mem.get(name) {
+            Some(v) => {
+                *value = *v;
+                xa_status::XA_OK
+            },
+            None => xa_status::XA_UNKNOWN_VARIABLE
+        }
+    })
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new`
+#[no_mangle]
+pub unsafe extern "C" fn xa_error_message(interp: *const xa_interpreter) -> *const c_char {
+    guarded(ptr::null(), || match interp.as_ref().and_then(|i| i.error.as_ref()) {
+        Some(msg) => msg.as_ptr(),
+        None => ptr::null()
+    })
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new`
+#[no_mangle]
+pub unsafe extern "C" fn xa_error_line(interp: *const xa_interpreter) -> u32 {
+    guarded(0, || match interp.as_ref().and_then(|i| i.error_location) {
+        Some(loc) => loc.line,
+        None => 0
+    })
+}
+
+/// # Safety
+/// `interp` must be null or a live pointer from `xa_interpreter_new`
+#[no_mangle]
+pub unsafe extern "C" fn xa_error_column(interp: *const xa_interpreter) -> u32 {
+    guarded(0, || match interp.as_ref().and_then(|i| i.error_location) {
+        Some(loc) => loc.column,
+        None => 0
+    })
+}
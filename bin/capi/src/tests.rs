@@ -0,0 +1,127 @@
+use crate::*;
+
+use std::ffi::CStr;
+use std::fs;
+use std::process::Command;
+
+fn run(interp: *mut xa_interpreter, source: &str) -> xa_status {
+    unsafe {
+        xa_run_source(interp, source.as_ptr() as *const c_char, source.len())
+    }
+}
+
+#[test]
+fn run_and_read_outputs() {
+    let interp = xa_interpreter_new();
+
+    assert_eq!(run(interp, "a = 3;\nb = a * 4;\nOUTPUT b;\nOUTPUT a + 1;"), xa_status::XA_OK);
+
+    unsafe {
+        let outputs = std::slice::from_raw_parts(xa_output(interp), xa_output_len(interp));
+        assert_eq!(outputs, &[12, 4]);
+        assert!(xa_error_message(interp).is_null());
+
+        let mut value = 0;
+        assert_eq!(xa_get_variable(interp, c"b".as_ptr(), &mut value), xa_status::XA_OK);
+        assert_eq!(value, 12);
+        assert_eq!(xa_get_variable(interp, c"c".as_ptr(), &mut value), xa_status::XA_UNKNOWN_VARIABLE);
+
+        xa_interpreter_free(interp);
+    }
+}
+
+#[test]
+fn variables_kept_between_runs() {
+    let interp = xa_interpreter_new();
+
+    assert_eq!(run(interp, "x = 41;"), xa_status::XA_OK);
+    assert_eq!(run(interp, "OUTPUT x + 1;"), xa_status::XA_OK);
+
+    unsafe {
+        assert_eq!(xa_output_len(interp), 1);
+        assert_eq!(*xa_output(interp), 42);
+
+        xa_interpreter_reset(interp);
+    }
+
+    assert_eq!(run(interp, "OUTPUT x;"), xa_status::XA_RUNTIME_ERROR);
+
+    unsafe {
+        xa_interpreter_free(interp);
+    }
+}
+
+#[test]
+fn errors_have_locations() {
+    let interp = xa_interpreter_new();
+
+    assert_eq!(run(interp, "x = 1;\nOUTPUT x +;"), xa_status::XA_PARSE_ERROR);
+    unsafe {
+        assert_eq!((xa_error_line(interp), xa_error_column(interp)), (2, 11));
+        let message = CStr::from_ptr(xa_error_message(interp)).to_str().unwrap();
+        assert_eq!(message, "2:11: unexpected token SC");
+    }
+
+    assert_eq!(run(interp, "x = 1;\nREPEAT (x) {\n    OUTPUT y;\n}"), xa_status::XA_RUNTIME_ERROR);
+    unsafe {
+        assert_eq!((xa_error_line(interp), xa_error_column(interp)), (3, 5));
+        let message = CStr::from_ptr(xa_error_message(interp)).to_str().unwrap();
+        assert_eq!(message, "3:5: Variable \"y\" has not been assigned");
+    }
+
+    // A later successful run clears the error
+    assert_eq!(run(interp, "OUTPUT 1;"), xa_status::XA_OK);
+    unsafe {
+        assert!(xa_error_message(interp).is_null());
+        assert_eq!(xa_error_line(interp), 0);
+
+        xa_interpreter_free(interp);
+    }
+}
+
+#[test]
+fn null_arguments() {
+    unsafe {
+        assert_eq!(xa_run_source(ptr::null_mut(), ptr::null(), 0), xa_status::XA_NULL_ARGUMENT);
+        assert_eq!(xa_output_len(ptr::null()), 0);
+        assert!(xa_error_message(ptr::null()).is_null());
+        xa_interpreter_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn panics_stop_at_the_boundary() {
+    assert_eq!(guarded(xa_status::XA_INTERNAL_ERROR, || panic!("bug")), xa_status::XA_INTERNAL_ERROR);
+    assert_eq!(guarded(0, || 4), 4);
+}
+
+/// Builds test/run.c against the header and the shared library, which cargo
+/// puts next to the test binary
+#[test]
+fn c_program_against_the_header() {
+    let deps = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let exe = std::env::temp_dir().join(format!("xa-c-test-{}", std::process::id()));
+
+    let compiler = cc::Build::new()
+        .target(env!("TARGET"))
+        .host(env!("TARGET"))
+        .opt_level(0)
+        .flag("-Wall")
+        .flag("-Werror")
+        .cargo_metadata(false)
+        .get_compiler();
+    let status = compiler.to_command()
+        .arg("-I").arg(concat!(env!("CARGO_MANIFEST_DIR"), "/include"))
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/test/run.c"))
+        .arg("-o").arg(&exe)
+        .arg("-L").arg(&deps)
+        .arg("-lxa")
+        .arg(format!("-Wl,-rpath,{}", deps.display()))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let run = Command::new(&exe).output().unwrap();
+    fs::remove_file(&exe).unwrap();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+}
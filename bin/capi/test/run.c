@@ -0,0 +1,42 @@
+// Uses the library only through xa.h, exits with the number of checks that failed
+#include <stdio.h>
+#include <string.h>
+
+#include "xa.h"
+
+static int failed = 0;
+
+static void check(int ok, const char *what) {
+    if (!ok) {
+        fprintf(stderr, "failed: %s\n", what);
+        failed++;
+    }
+}
+
+int main(void) {
+    xa_interpreter *interp = xa_interpreter_new();
+    check(interp != NULL, "xa_interpreter_new");
+
+    const char *source = "a = 3;\nOUTPUT a * 4;\nOUTPUT a + 1;";
+    check(xa_run_source(interp, source, strlen(source)) == XA_OK, "running");
+    check(xa_output_len(interp) == 2, "number of outputs");
+    check(xa_output(interp)[0] == 12 && xa_output(interp)[1] == 4, "outputs");
+    check(xa_error_message(interp) == NULL, "no error");
+
+    int32_t value = 0;
+    check(xa_get_variable(interp, "a", &value) == XA_OK && value == 3, "reading a");
+    check(xa_get_variable(interp, "b", &value) == XA_UNKNOWN_VARIABLE, "reading b");
+
+    const char *failing = "OUTPUT a;\nOUTPUT a / 0;";
+    check(xa_run_source(interp, failing, strlen(failing)) == XA_RUNTIME_ERROR, "failing");
+    check(xa_error_message(interp) != NULL && strcmp(xa_error_message(interp), "2:1: division by zero") == 0, "error message");
+    check(xa_error_line(interp) == 2 && xa_error_column(interp) == 1, "error position");
+
+    xa_interpreter_reset(interp);
+    check(xa_get_variable(interp, "a", &value) == XA_UNKNOWN_VARIABLE, "reset");
+    check(xa_run_source(NULL, source, strlen(source)) == XA_NULL_ARGUMENT, "no interpreter");
+
+    xa_interpreter_free(interp);
+    xa_interpreter_free(NULL);
+    return failed;
+}
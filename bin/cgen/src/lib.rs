@@ -0,0 +1,262 @@
+//! Compiles a program to one standalone C99 file that prints its outputs one
+//! per line. Runtime errors print `line:column: message` to stderr and exit
+//! with status 1, after the outputs so far have been written.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use language::*;
+
+const PRELUDE: &str = "\
+/* Generated from an .xa program */
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+";
+
+/// Only written out when the program can fail, an unused static function
+/// is a warning
+const FAIL: &str = "
+static void xa_fail(int line, int column, const char *message) {
+    fflush(stdout);
+    fprintf(stderr, \"%d:%d: %s\\n\", line, column, message);
+    exit(1);
+}
+";
+
+/// Checked arithmetic, only the ones a program uses are written out. The
+/// interpreter stops with an error on overflow and division by zero so these
+/// stop too
+const HELPERS: [(Op, &str, &str); 5] = [
+    (Op::Add, "xa_add", "
+static int32_t xa_add(int32_t a, int32_t b, int line, int column) {
+    if ((b > 0 && a > INT32_MAX - b) || (b < 0 && a < INT32_MIN - b)) {
+        xa_fail(line, column, \"addition overflowed\");
+    }
+    return a + b;
+}
+"),
+    (Op::Sub, "xa_sub", "
+static int32_t xa_sub(int32_t a, int32_t b, int line, int column) {
+    if ((b < 0 && a > INT32_MAX + b) || (b > 0 && a < INT32_MIN + b)) {
+        xa_fail(line, column, \"subtraction overflowed\");
+    }
+    return a - b;
+}
+"),
+    (Op::Multiply, "xa_mul", "
+static int32_t xa_mul(int32_t a, int32_t b, int line, int column) {
+    int64_t r = (int64_t)a * (int64_t)b;
+    if (r > INT32_MAX || r < INT32_MIN) {
+        xa_fail(line, column, \"multiplication overflowed\");
+    }
+    return (int32_t)r;
+}
+"),
+    (Op::Divide, "xa_div", "
+static int32_t xa_div(int32_t a, int32_t b, int line, int column) {
+    if (b == 0) {
+        xa_fail(line, column, \"division by zero\");
+    }
+    if (a == INT32_MIN && b == -1) {
+        xa_fail(line, column, \"division overflowed\");
+    }
+    return a / b;
+}
+"),
+    (Op::Remainder, "xa_rem", "
+static int32_t xa_rem(int32_t a, int32_t b, int line, int column) {
+    if (b == 0) {
+        xa_fail(line, column, \"remainder by zero\");
+    }
+    if (a == INT32_MIN && b == -1) {
+        xa_fail(line, column, \"remainder overflowed\");
+    }
+    return a % b;
+}
+")
+];
+
+/// C name of an xa variable, prefixed so it can't clash with a keyword. An
+/// imported `lib.x` becomes `v_lib_x`, plain names never have underscores
+fn c_var(var: &str) -> String {
+    format!("v_{}", var.replace('.', "_"))
+}
+
+struct Generator {
+    out: String,
+    vars: BTreeSet<String>,
+    indent: usize,
+    temps: usize,
+    used: BTreeSet<usize>,
+    /// Whether anything calls `xa_fail`
+    fails: bool
+}
+
+impl Generator {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Stops the program with `message` if `condition` holds. The message is
+    /// put in a C string as it is
+    fn fail_if(&mut self, condition: &str, loc: Location, message: &str) {
+        self.fails = true;
+        self.line(&format!("if ({}) xa_fail({}, {}, \"{}\");", condition, loc.line, loc.column, message));
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("t{}", self.temps)
+    }
+
+    /// Writes whatever has to run before the value of `exp` is known and
+    /// returns a C expression for it. Sub-expressions go into temporaries
+    /// because C leaves the order arguments are evaluated in unspecified
+    fn exp(&mut self, exp: &Expression, loc: Location) -> String {
+        match exp {
+            Expression::Val(v) if *v == i32::MIN => return "INT32_MIN".to_string(),
+            Expression::Val(v) => return v.to_string(),
+            Expression::Var(var) => {
+                self.fail_if(&format!("!{}_set", c_var(var)), loc, &format!("Variable \\\"{}\\\" has not been assigned", var));
+                return c_var(var);
+            },
+            Expression::BinOp(op, lhs, rhs) => {
+                let lhs = self.exp(lhs, loc);
+                let rhs = self.exp(rhs, loc);
+
+                let value = match op {
+                    Op::And => format!("({} != 0 && {} != 0)", lhs, rhs),
+                    Op::Or => format!("({} != 0 || {} != 0)", lhs, rhs),
+                    Op::Equal => format!("({} == {})", lhs, rhs),
+                    Op::NotEqual => format!("({} != {})", lhs, rhs),
+                    Op::LessThan => format!("({} < {})", lhs, rhs),
+                    Op::LessThanOrEqual => format!("({} <= {})", lhs, rhs),
+                    Op::GreaterThan => format!("({} > {})", lhs, rhs),
+                    Op::GreaterThanOrEqual => format!("({} >= {})", lhs, rhs),
+                    _ => {
+                        let helper = HELPERS.iter().position(|(o, _, _)| o == op).unwrap();
+                        self.used.insert(helper);
+                        self.fails = true;
+                        format!("{}({}, {}, {}, {})", HELPERS[helper].1, lhs, rhs, loc.line, loc.column)
+                    }
+                };
+
+                let temp = self.temp();
+                self.line(&format!("int32_t {} = {};", temp, value));
+                return temp;
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        let loc = stmt.location();
+
+        match stmt {
+            Statement::AssignStatement { var, exp, .. } => {
+                let value = self.exp(exp, loc);
+                if self.vars.contains(var) {
+                    self.line(&format!("{} = {};", c_var(var), value));
+                    self.line(&format!("{}_set = 1;", c_var(var)));
+                } else {
+                    self.line(&format!("(void){};", value));
+                }
+            },
+            Statement::OutputStatement { to_output, .. } => {
+                let value = self.exp(to_output, loc);
+                self.line(&format!("printf(\"%\" PRId32 \"\\n\", {});", value));
+            },
+            Statement::AssertStatement { exp, expected, .. } => {
+                let value = self.exp(exp, loc);
+                let holds = match expected {
+                    Some(expected) => format!("{} == {}", value, self.exp(expected, loc)),
+                    None => format!("{} != 0", value)
+                };
+                self.fail_if(&format!("!({})", holds), loc, "assertion failed");
+            },
+            Statement::RepeatStatement { times, body, .. } => {
+                self.line("{");
+                self.indent += 1;
+
+                // The count is only worked out once even if the body changes
+                // the variables in it
+                let value = self.exp(times, loc);
+                let times = self.temp();
+                self.line(&format!("int32_t {} = {};", times, value));
+                self.fail_if(&format!("{} < 0", times), loc, "REPEAT count is negative");
+
+                let counter = self.temp();
+                self.line(&format!("for (int32_t {0} = 0; {0} < {1}; {0}++) {{", counter, times));
+                self.indent += 1;
+                self.block(body);
+                self.indent -= 1;
+                self.line("}");
+
+                self.indent -= 1;
+                self.line("}");
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                // Each ELSEIF condition only runs once the ones before it are
+                // false, so it goes inside the else of the previous arm
+                let mut arms = vec![(condition, body)];
+                arms.extend(else_if.iter().map(|(exp, block)| (exp, block)));
+
+                for (exp, block) in arms.iter() {
+                    let value = self.exp(exp, loc);
+                    self.line(&format!("if ({} != 0) {{", value));
+                    self.indent += 1;
+                    self.block(block);
+                    self.indent -= 1;
+                    self.line("} else {");
+                    self.indent += 1;
+                }
+
+                self.block(else_body);
+
+                for _ in arms.iter() {
+                    self.indent -= 1;
+                    self.line("}");
+                }
+            }
+        }
+    }
+}
+
+/// The C source for `program`
+pub fn generate(program: &Program) -> String {
+    // Variables that are never read don't need storing
+    let vars = program.program.read_variables();
+
+    let mut gen = Generator{out: String::new(), vars, indent: 1, temps: 0, used: BTreeSet::new(), fails: false};
+    for var in gen.vars.clone().iter() {
+        gen.line(&format!("int32_t {} = 0;", c_var(var)));
+        gen.line(&format!("int {}_set = 0;", c_var(var)));
+    }
+    gen.block(&program.program);
+
+    let mut result = PRELUDE.to_string();
+    if gen.fails {
+        result.push_str(FAIL);
+    }
+    for helper in gen.used.iter() {
+        result.push_str(HELPERS[*helper].2);
+    }
+
+    write!(result, "\nint main(void) {{\n{}    return 0;\n}}\n", gen.out).unwrap();
+
+    return result;
+}
@@ -0,0 +1,66 @@
+use crate::*;
+
+use conformance::{matches_interpreter, Backend};
+
+/// Compiled by the system compiler, which has to have nothing to say
+const C: Backend = Backend{
+    name: "cgen",
+    file: "program.c",
+    generate,
+    suffix: "",
+    compiler: &["cc", "-std=c99", "-Wall", "-Wextra", "-Werror"],
+    outputs_before_errors: true
+};
+
+#[test]
+fn examples() {
+    matches_interpreter(&C, "fibbonachi", include_str!("../../../examples/fibbonachi.xa"));
+    matches_interpreter(&C, "moreops", include_str!("../../../examples/moreops.xa"));
+}
+
+#[test]
+fn control_flow() {
+    matches_interpreter(&C, "control_flow", "n = 3;
+unused = 7;
+REPEAT (n) {
+    n = n + 1;
+    IF (n == 4) {
+        OUTPUT 40;
+    } ELSEIF (n == 5) {
+        OUTPUT 50;
+    } ELSEIF (n == 6 && 1) {
+        OUTPUT 60;
+    } ELSE {
+        OUTPUT 0;
+    }
+    REPEAT (0) {
+        OUTPUT 1;
+    }
+}
+OUTPUT n;
+OUTPUT 7 - 9 * 2 >= 0 - 11 || 0;");
+}
+
+#[test]
+fn runtime_errors() {
+    let run = matches_interpreter(&C, "negative", "OUTPUT 1;\nx = 0 - 2;\nREPEAT (x) {\n    OUTPUT 2;\n}");
+    assert_eq!(run.outputs, vec![1]);
+    assert_eq!(run.error.as_deref(), Some("3:1: REPEAT count is negative"));
+
+    let run = matches_interpreter(&C, "unassigned", "IF (0) {\n    x = 1;\n}\nOUTPUT x;");
+    assert_eq!(run.error.as_deref(), Some("4:1: Variable \"x\" has not been assigned"));
+
+    let run = matches_interpreter(&C, "assertion", "x = 2;\nASSERT (x > 1);\nASSERT_EQ (x * 2, 4);\nOUTPUT x;\nASSERT_EQ (x, 3);");
+    assert_eq!(run.outputs, vec![2]);
+    assert_eq!(run.error.as_deref(), Some("5:1: assertion failed"));
+}
+
+#[test]
+fn arithmetic_errors() {
+    let run = matches_interpreter(&C, "overflow", "x = 2147483647;\nOUTPUT x;\nOUTPUT x + 1;");
+    assert_eq!(run.error.as_deref(), Some("3:1: addition overflowed"));
+
+    let run = matches_interpreter(&C, "divide", "x = 0;\nOUTPUT 5 % 3;\nOUTPUT 1 / x;");
+    assert_eq!(run.outputs, vec![2]);
+    assert_eq!(run.error.as_deref(), Some("3:1: division by zero"));
+}
@@ -0,0 +1,111 @@
+//! Checks that a compiling backend's programs do what the interpreter
+//! does, both as written and optimised. Each backend's tests describe how to
+//! build what it generates, then compare runs of the same source here.
+
+use std::fs;
+use std::process::Command;
+
+use language::Program;
+
+/// How to turn a program into something that runs
+pub struct Backend {
+    /// Used to name the directory it is built in
+    pub name: &'static str,
+    /// What the generated code is saved as
+    pub file: &'static str,
+    pub generate: fn(&Program) -> String,
+    /// Added to the end of the generated code, to make it a whole program
+    pub suffix: &'static str,
+    /// The compiler and its options, it is then given `-o` the executable
+    /// and the file
+    pub compiler: &'static [&'static str],
+    /// Whether what was output before a runtime error is still seen
+    pub outputs_before_errors: bool
+}
+
+/// What a run gave
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub outputs: Vec<i32>,
+    /// The runtime error as `line:column: message`
+    pub error: Option<String>
+}
+
+/// Runs `source` in the interpreter. Compiled code only says an assertion
+/// failed, not the values, so neither does this
+pub fn interpret(source: &str) -> Run {
+    let program = parser::parse_source(source).unwrap();
+    let mut interpreter = interpreter::Interpreter::new();
+    let result = interpreter.run(&program);
+
+    let error = result.err().map(|e| {
+        let message = match e {
+            interpreter::ErrorType::AssertionFailed{..} => "assertion failed".to_string(),
+            e => e.to_string()
+        };
+        format!("{}: {}", interpreter.error_location().unwrap_or_default(), message)
+    });
+    return Run{outputs: interpreter.output_vec, error};
+}
+
+/// The error in what a failed program wrote to stderr. A Rust panic says
+/// where it panicked first, the message is on the line after
+fn error_text(stderr: &str) -> String {
+    match stderr.lines().skip_while(|l| !l.starts_with("thread 'main'")).nth(1) {
+        Some(message) => return message.to_string(),
+        None => return stderr.trim().to_string()
+    }
+}
+
+/// Builds what `backend` generates for `program` and runs it
+pub fn compile_and_run(backend: &Backend, name: &str, program: &Program) -> Run {
+    let code = (backend.generate)(program) + backend.suffix;
+
+    let dir = std::env::temp_dir().join(format!("{}-{}-{}", backend.name, std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join(backend.file);
+    let exe = dir.join("program");
+    fs::write(&file, &code).unwrap();
+
+    let compiler = Command::new(backend.compiler[0])
+        .args(&backend.compiler[1..])
+        .arg("-o")
+        .arg(&exe)
+        .arg(&file)
+        .output()
+        .unwrap_or_else(|e| panic!("Could not run {}: {}", backend.compiler[0], e));
+    assert!(compiler.status.success(), "{} failed:\n{}\n{}", backend.compiler[0], code, String::from_utf8_lossy(&compiler.stderr));
+
+    let run = Command::new(&exe).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let outputs = String::from_utf8(run.stdout).unwrap()
+        .lines()
+        .map(|l| l.parse().unwrap())
+        .collect();
+    let error = match run.status.success() {
+        true => None,
+        false => Some(error_text(&String::from_utf8(run.stderr).unwrap()))
+    };
+
+    return Run{outputs, error};
+}
+
+/// Runs `source` compiled, then compiled after optimising, and checks both
+/// do what the interpreter does, failing the same way if it fails. Gives the
+/// run to check further
+pub fn matches_interpreter(backend: &Backend, name: &str, source: &str) -> Run {
+    let program = parser::parse_source(source).unwrap();
+    let mut expected = interpret(source);
+    if !backend.outputs_before_errors && expected.error.is_some() {
+        expected.outputs.clear();
+    }
+
+    let run = compile_and_run(backend, name, &program);
+    assert_eq!(run, expected, "{}", source);
+
+    let optimised = optimiser::optimise(program).0;
+    assert_eq!(compile_and_run(backend, &format!("{}-O", name), &optimised), expected, "optimised {}", source);
+
+    return run;
+}
@@ -0,0 +1,269 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::marker::PhantomData;
+
+use interpreter::{Branch, ErrorType, Hook, Memory};
+use language::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StatementKind {
+    Assign,
+    If,
+    Repeat,
+    Output,
+    Assert
+}
+
+/// How often one statement ran, and for IF and REPEAT which ways it went
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementCoverage {
+    pub loc: Location,
+    pub kind: StatementKind,
+    pub hits: u64,
+    /// IF statements: times each arm was taken, the IF arm first, then each
+    /// ELSEIF, then the ELSE arm which is taken even when it has no body
+    pub arms: Vec<u64>,
+    /// REPEAT statements: times the body started running
+    pub body_runs: u64,
+    /// REPEAT statements: times the body was skipped by a count of zero
+    pub empty_repeats: u64
+}
+
+impl StatementCoverage {
+    /// The ways this statement can go with how often each was taken. An IF
+    /// has one per arm, a REPEAT either runs its body or does not
+    pub fn branches(&self) -> Vec<(String, u64)> {
+        match self.kind {
+            StatementKind::If => self.arms.iter().enumerate().map(|(i, taken)| {
+                let name = match i {
+                    0 => "IF".to_string(),
+                    _ if i == self.arms.len() - 1 => "ELSE".to_string(),
+                    _ => format!("ELSEIF {}", i)
+                };
+                (name, *taken)
+            }).collect(),
+            StatementKind::Repeat => vec![
+                ("body".to_string(), self.body_runs),
+                ("no iterations".to_string(), self.empty_repeats)
+            ],
+            _ => Vec::new()
+        }
+    }
+}
+
+/// Hook that records which statements and branches of one program ran.
+/// Statements are found by address so it has to be used to run the same
+/// program it was made from
+pub struct Coverage<'p> {
+    statements: Vec<StatementCoverage>,
+    index: HashMap<*const Statement, usize>,
+    _program: PhantomData<&'p Program>
+}
+
+impl<'p> Coverage<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        let mut coverage = Self{
+            statements: Vec::new(),
+            index: HashMap::new(),
+            _program: PhantomData
+        };
+
+        coverage.add_block(&program.program);
+        coverage
+    }
+
+    fn add_block(&mut self, block: &Block) {
+        for stmt in block.statements.iter() {
+            let (kind, arms) = match stmt {
+                Statement::AssignStatement { .. } => (StatementKind::Assign, 0),
+                Statement::IfStatement { else_if, .. } => (StatementKind::If, else_if.len() + 2),
+                Statement::RepeatStatement { .. } => (StatementKind::Repeat, 0),
+                Statement::OutputStatement { .. } => (StatementKind::Output, 0),
+                Statement::AssertStatement { .. } => (StatementKind::Assert, 0)
+            };
+
+            self.index.insert(stmt as *const Statement, self.statements.len());
+            self.statements.push(StatementCoverage{
+                loc: stmt.location(),
+                kind,
+                hits: 0,
+                arms: vec![0; arms],
+                body_runs: 0,
+                empty_repeats: 0
+            });
+
+            match stmt {
+                Statement::RepeatStatement { body, .. } => self.add_block(body),
+                Statement::IfStatement { body, else_if, else_body, .. } => {
+                    self.add_block(body);
+                    for (_, block) in else_if {
+                        self.add_block(block);
+                    }
+                    self.add_block(else_body);
+                },
+                _ => ()
+            }
+        }
+    }
+
+    fn get(&mut self, stmt: &Statement) -> &mut StatementCoverage {
+        let id = *self.index.get(&(stmt as *const Statement))
+                            .expect("Statement is not from the program given to Coverage::new");
+        &mut self.statements[id]
+    }
+
+    pub fn finish(self) -> Report {
+        let mut statements = self.statements;
+        statements.sort_by_key(|s| s.loc);
+        Report{statements}
+    }
+}
+
+impl<'p> Hook for Coverage<'p> {
+    fn before_statement(&mut self, stmt: &Statement, _depth: usize, _memory: &mut Memory) -> Result<(), ErrorType> {
+        self.get(stmt).hits += 1;
+        Ok(())
+    }
+
+    fn after_branch(&mut self, stmt: &Statement, taken: Branch) {
+        let coverage = self.get(stmt);
+        let arm = match taken {
+            Branch::If => 0,
+            Branch::ElseIf(i) => i + 1,
+            Branch::Else => coverage.arms.len() - 1
+        };
+        coverage.arms[arm] += 1;
+    }
+
+    fn before_repeat(&mut self, stmt: &Statement, times: i32) {
+        if times == 0 {
+            self.get(stmt).empty_repeats += 1;
+        }
+    }
+
+    // A negative count fails before the body starts, so the body is counted
+    // when it does
+    fn before_iteration(&mut self, stmt: &Statement, iteration: i32) {
+        if iteration == 1 {
+            self.get(stmt).body_runs += 1;
+        }
+    }
+}
+
+fn percent(hit: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 100.0;
+    }
+    return hit as f64 * 100.0 / total as f64;
+}
+
+/// What a run covered, statements in source order
+pub struct Report {
+    pub statements: Vec<StatementCoverage>
+}
+
+impl Report {
+    /// Statements that ran and how many there are
+    pub fn statements_hit(&self) -> (usize, usize) {
+        let hit = self.statements.iter().filter(|s| s.hits > 0).count();
+        return (hit, self.statements.len());
+    }
+
+    /// Branches that were taken and how many there are
+    pub fn branches_hit(&self) -> (usize, usize) {
+        let branches: Vec<u64> = self.statements.iter()
+            .flat_map(|s| s.branches())
+            .map(|(_, taken)| taken)
+            .collect();
+        let hit = branches.iter().filter(|taken| **taken > 0).count();
+        return (hit, branches.len());
+    }
+
+    /// Hits per file and line that has statements starting on it, the most
+    /// any of them ran
+    fn lines(&self) -> BTreeMap<(FileId, u32), u64> {
+        let mut lines = BTreeMap::new();
+        for stmt in self.statements.iter() {
+            let hits = lines.entry((stmt.loc.file, stmt.loc.line)).or_insert(0);
+            *hits = u64::max(*hits, stmt.hits);
+        }
+        return lines;
+    }
+
+    /// The source of `file` with how often each line ran, `#####` on lines
+    /// that never did, and how often each branch on the line was taken, then
+    /// the totals for the file
+    pub fn annotate(&self, file: FileId, source: &str) -> String {
+        let lines = self.lines();
+
+        let mut result = format!("{:>9} | source\n", "hits");
+        for (i, text) in source.lines().enumerate() {
+            let line = i as u32 + 1;
+            match lines.get(&(file, line)) {
+                Some(0) => write!(result, "{:>9} | {}", "#####", text),
+                Some(hits) => write!(result, "{:>9} | {}", hits, text),
+                None => write!(result, "{:>9} | {}", "", text)
+            }.unwrap();
+
+            let branches: Vec<String> = self.statements.iter()
+                .filter(|s| s.loc.file == file && s.loc.line == line)
+                .flat_map(|s| s.branches())
+                .map(|(name, taken)| format!("{} {}", name, taken))
+                .collect();
+            if !branches.is_empty() {
+                write!(result, "  [{}]", branches.join(", ")).unwrap();
+            }
+
+            result.push('\n');
+        }
+
+        let own = Report{statements: self.statements.iter().filter(|s| s.loc.file == file).cloned().collect()};
+        let (hit, total) = own.statements_hit();
+        writeln!(result, "statements: {} of {} run ({:.1}%)", hit, total, percent(hit, total)).unwrap();
+        let (hit, total) = own.branches_hit();
+        writeln!(result, "branches: {} of {} taken ({:.1}%)", hit, total, percent(hit, total)).unwrap();
+
+        return result;
+    }
+
+    /// The LCOV record for `file`, which is at `path`. Each IF or REPEAT is
+    /// its own block on its line, numbered from 0 in source order
+    pub fn lcov(&self, file: FileId, path: &str) -> String {
+        let mut result = format!("TN:\nSF:{}\n", path);
+
+        let (mut found, mut hit) = (0, 0);
+        let branching = self.statements.iter().filter(|s| s.loc.file == file && !s.branches().is_empty());
+        for (block, stmt) in branching.enumerate() {
+            for (branch, (_, taken)) in stmt.branches().iter().enumerate() {
+                // LCOV tells a branch that was never reached from one that
+                // was reached and not taken
+                let taken = match stmt.hits {
+                    0 => "-".to_string(),
+                    _ => taken.to_string()
+                };
+                writeln!(result, "BRDA:{},{},{},{}", stmt.loc.line, block, branch, taken).unwrap();
+
+                found += 1;
+                if taken != "-" && taken != "0" {
+                    hit += 1;
+                }
+            }
+        }
+        writeln!(result, "BRF:{}\nBRH:{}", found, hit).unwrap();
+
+        let lines: Vec<(u32, u64)> = self.lines().into_iter()
+            .filter(|((f, _), _)| *f == file)
+            .map(|((_, line), hits)| (line, hits))
+            .collect();
+        for (line, hits) in lines.iter() {
+            writeln!(result, "DA:{},{}", line, hits).unwrap();
+        }
+        let lines_hit = lines.iter().filter(|(_, h)| *h > 0).count();
+        writeln!(result, "LF:{}\nLH:{}\nend_of_record", lines.len(), lines_hit).unwrap();
+
+        return result;
+    }
+}
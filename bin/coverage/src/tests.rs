@@ -0,0 +1,156 @@
+use crate::*;
+
+use interpreter::Interpreter;
+
+const SOURCE: &str = "x = 0;
+n = 0;
+REPEAT (n) {
+    x = 1;
+}
+REPEAT (2) {
+    IF (x == 0) {
+        OUTPUT 1;
+    } ELSEIF (x == 5) {
+        OUTPUT 2;
+    }
+    x = x + 1;
+}";
+
+fn cover(source: &str) -> Report {
+    let program = parser::parse_source(source).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Coverage::new(&program));
+    interpreter.run(&program).unwrap();
+
+    interpreter.hook.finish()
+}
+
+fn statement(report: &Report, line: u32) -> &StatementCoverage {
+    report.statements.iter().find(|s| s.loc.line == line).unwrap()
+}
+
+#[test]
+fn statements_and_branches() {
+    let report = cover(SOURCE);
+
+    let hits: Vec<(u32, u64)> = report.statements.iter().map(|s| (s.loc.line, s.hits)).collect();
+    assert_eq!(hits, vec![(1, 1), (2, 1), (3, 1), (4, 0), (6, 1), (7, 2), (8, 1), (10, 0), (12, 2)]);
+
+    // The IF arm once, the ELSEIF never and the missing ELSE once
+    assert_eq!(statement(&report, 7).arms, vec![1, 0, 1]);
+
+    assert_eq!(statement(&report, 3).kind, StatementKind::Repeat);
+    assert_eq!(statement(&report, 3).empty_repeats, 1);
+    assert_eq!(statement(&report, 6).empty_repeats, 0);
+    assert_eq!(statement(&report, 6).body_runs, 1);
+
+    assert_eq!(report.statements_hit(), (7, 9));
+    assert_eq!(report.branches_hit(), (4, 7));
+}
+
+#[test]
+fn negative_repeat_takes_no_branch() {
+    let source = "n = 0 - 1;\nREPEAT (n) {\n    OUTPUT 1;\n}";
+    let program = parser::parse_source(source).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Coverage::new(&program));
+    assert_eq!(interpreter.run(&program), Err(ErrorType::NegativeRepeateError));
+    let report = interpreter.hook.finish();
+
+    assert_eq!(statement(&report, 2).hits, 1);
+    assert_eq!(statement(&report, 2).branches(), vec![("body".to_string(), 0), ("no iterations".to_string(), 0)]);
+}
+
+#[test]
+fn annotated_source() {
+    let report = cover(SOURCE);
+    let annotated = report.annotate(0, SOURCE);
+    let lines: Vec<&str> = annotated.lines().collect();
+
+    assert_eq!(lines[3], "        1 | REPEAT (n) {  [body 0, no iterations 1]");
+    assert_eq!(lines[4], "    ##### |     x = 1;");
+    assert_eq!(lines[5], "          | }");
+    assert_eq!(lines[7], "        2 |     IF (x == 0) {  [IF 1, ELSEIF 1 0, ELSE 1]");
+    assert_eq!(lines[14], "statements: 7 of 9 run (77.8%)");
+    assert_eq!(lines[15], "branches: 4 of 7 taken (57.1%)");
+}
+
+#[test]
+fn lcov_record() {
+    let report = cover(SOURCE);
+
+    assert_eq!(report.lcov(0, "test.xa"), "TN:
+SF:test.xa
+BRDA:3,0,0,0
+BRDA:3,0,1,1
+BRDA:6,1,0,1
+BRDA:6,1,1,0
+BRDA:7,2,0,1
+BRDA:7,2,1,0
+BRDA:7,2,2,1
+BRF:7
+BRH:4
+DA:1,1
+DA:2,1
+DA:3,1
+DA:4,0
+DA:6,1
+DA:7,2
+DA:8,1
+DA:10,0
+DA:12,2
+LF:9
+LH:7
+end_of_record
+");
+}
+
+#[test]
+fn unreached_branches() {
+    let report = cover("REPEAT (0) {
+    IF (1) {
+        OUTPUT 1;
+    } ELSE {
+        OUTPUT 2;
+    }
+}");
+
+    let lcov = report.lcov(0, "unreached.xa");
+    assert!(lcov.contains("BRDA:2,1,0,-\nBRDA:2,1,1,-\n"), "{}", lcov);
+    assert_eq!(report.branches_hit(), (1, 4));
+}
+
+#[test]
+fn imported_files_have_their_own_lines() {
+    let dir = std::env::temp_dir().join(format!("coverage-imports-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lib = "x = 4;\nIF (x) {\n    y = 1;\n}\n";
+    std::fs::write(dir.join("lib.xa"), lib).unwrap();
+    let source = "IMPORT \"lib.xa\";\nOUTPUT lib.x;";
+    let program = parser::Loader::new().load_source(source, &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Coverage::new(&program));
+    interpreter.run(&program).unwrap();
+    let report = interpreter.hook.finish();
+
+    // The IF is on line 2 of lib.xa, not of the main file
+    assert_eq!(report.lcov(0, "main.xa"), "TN:\nSF:main.xa\nBRF:0\nBRH:0\nDA:2,1\nLF:1\nLH:1\nend_of_record\n");
+    assert_eq!(report.lcov(1, "lib.xa"), "TN:
+SF:lib.xa
+BRDA:2,0,0,1
+BRDA:2,0,1,0
+BRF:2
+BRH:1
+DA:1,1
+DA:2,1
+DA:3,1
+LF:3
+LH:3
+end_of_record
+");
+    let annotated = report.annotate(0, source);
+    assert_eq!(annotated.lines().nth(2), Some("        1 | OUTPUT lib.x;"));
+    assert_eq!(report.annotate(1, lib).lines().nth(2), Some("        1 | IF (x) {  [IF 1, ELSE 0]"));
+    assert!(annotated.ends_with("statements: 1 of 1 run (100.0%)\nbranches: 0 of 0 taken (100.0%)\n"), "{}", annotated);
+}
@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use interpreter::{ErrorType, Hook, Memory};
+use language::*;
+
+const HELP: &str = "\
+step, s           run until the next statement, going into IF and REPEAT bodies
+next, n           run until the next statement in this block or an outer one
+finish, f         run until the next statement in an outer block
+continue, c       run until a breakpoint
+break, b LINE     stop at statements on LINE of the main file
+delete, d LINE    remove the breakpoint on LINE
+print, p VAR      show the value of VAR
+set VAR = VALUE   change the value of VAR
+vars              show every variable
+quit, q           stop the program";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Mode {
+    /// Stop at the next statement whatever its depth
+    Step,
+    /// Stop at the next statement nested no deeper than this
+    Next(usize),
+    /// Only stop at breakpoints
+    Continue,
+    /// Never stop again, used once there are no more commands to read
+    Detached
+}
+
+/// Interactive debugger that reads commands from `input` whenever the
+/// program stops and writes what it has to say to `output`
+pub struct Debugger<R: BufRead, W: Write> {
+    sources: Sources,
+    breakpoints: BTreeSet<u32>,
+    mode: Mode,
+    input: R,
+    output: W
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// Starts stopped at the first statement. `sources` are only used to
+    /// show the line of the statement being stopped at
+    pub fn new(sources: Sources, input: R, output: W) -> Self {
+        Self{
+            sources,
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            input,
+            output
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, line: u32) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    /// Reads and runs commands until one of them resumes the program.
+    /// Returns false if the program should stop
+    fn prompt(&mut self, loc: Location, depth: usize, memory: &mut Memory) -> io::Result<bool> {
+        writeln!(self.output, "stopped at {}: {}", self.sources.locate(loc), self.sources.line(loc))?;
+
+        loop {
+            write!(self.output, "(xa) ")?;
+            self.output.flush()?;
+
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                // Nobody left to ask so let the program finish
+                writeln!(self.output)?;
+                self.mode = Mode::Detached;
+                return Ok(true);
+            }
+
+            let words: Vec<&str> = command.split_whitespace().collect();
+
+            match words.as_slice() {
+                [] => (),
+                ["step" | "s"] => {
+                    self.mode = Mode::Step;
+                    return Ok(true);
+                },
+                ["next" | "n"] => {
+                    self.mode = Mode::Next(depth);
+                    return Ok(true);
+                },
+                ["finish" | "f"] => {
+                    self.mode = Mode::Next(depth - 1);
+                    return Ok(true);
+                },
+                ["continue" | "c"] => {
+                    self.mode = Mode::Continue;
+                    return Ok(true);
+                },
+                ["quit" | "q"] => return Ok(false),
+                ["break" | "b", line] => match line.parse::<u32>() {
+                    Ok(l) => {
+                        self.breakpoints.insert(l);
+                        writeln!(self.output, "breakpoint on line {}", l)?;
+                    },
+                    Err(_) => writeln!(self.output, "{:?} is not a line number", line)?
+                },
+                ["delete" | "d", line] => match line.parse::<u32>() {
+                    Ok(l) if self.breakpoints.remove(&l) => writeln!(self.output, "removed breakpoint on line {}", l)?,
+                    _ => writeln!(self.output, "no breakpoint on line {:?}", line)?
+                },
+                ["print" | "p", var] => match memory.mem.get(*var) {
+                    Some(val) => writeln!(self.output, "{} = {}", var, val)?,
+                    None => writeln!(self.output, "{} has not been assigned", var)?
+                },
+                ["set", var, "=", val] |
+                ["set", var, val] => match val.parse::<i32>() {
+                    Ok(v) => {
+                        memory.mem.insert(var.to_string(), v);
+                        writeln!(self.output, "{} = {}", var, v)?;
+                    },
+                    Err(_) => writeln!(self.output, "{:?} is not a 32 bit integer", val)?
+                },
+                ["vars"] => {
+                    let mut vars: Vec<(&String, &i32)> = memory.mem.iter().collect();
+                    vars.sort();
+                    for (var, val) in vars {
+                        writeln!(self.output, "{} = {}", var, val)?;
+                    }
+                },
+                ["help" | "h"] => writeln!(self.output, "{}", HELP)?,
+                _ => writeln!(self.output, "unknown command {:?}, try help", command.trim())?
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before_statement(&mut self, stmt: &Statement, depth: usize, memory: &mut Memory) -> Result<(), ErrorType> {
+        let loc = stmt.location();
+
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(d) => depth <= d,
+            Mode::Continue => false,
+            Mode::Detached => return Ok(())
+        } || (loc.file == 0 && self.breakpoints.contains(&loc.line));
+
+        if !stop {
+            return Ok(());
+        }
+
+        match self.prompt(loc, depth, memory) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ErrorType::Quit(loc)),
+            // Can't talk to the user any more so get out of the way
+            Err(_) => {
+                self.mode = Mode::Detached;
+                Ok(())
+            }
+        }
+    }
+}
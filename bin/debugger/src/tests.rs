@@ -0,0 +1,119 @@
+use crate::*;
+
+use interpreter::Interpreter;
+
+const SOURCE: &str = "x = 1;
+REPEAT (2) {
+    x = x + 1;
+    IF (x == 3) {
+        OUTPUT x;
+    }
+}
+OUTPUT x;";
+
+/// Runs SOURCE under the debugger, giving back the result, the outputs and
+/// everything the debugger said
+fn debug(commands: &str, breakpoints: &[u32]) -> (Result<(), ErrorType>, Vec<i32>, String) {
+    let program = parser::parse_source(SOURCE).unwrap();
+
+    let mut debugger = Debugger::new(Sources::new(SOURCE), commands.as_bytes(), Vec::new());
+    for line in breakpoints {
+        debugger.add_breakpoint(*line);
+    }
+
+    let mut interpreter = Interpreter::with_hook(debugger);
+    let result = interpreter.run(&program);
+    let said = String::from_utf8(interpreter.hook.into_output()).unwrap();
+
+    (result, interpreter.output_vec, said)
+}
+
+/// Locations the debugger stopped at
+fn stops(said: &str) -> Vec<&str> {
+    said.lines()
+        .filter_map(|l| l.split("stopped at ").nth(1))
+        .map(|l| &l[..l.find(": ").unwrap()])
+        .collect()
+}
+
+#[test]
+fn step_goes_into_blocks() {
+    let (result, outputs, said) = debug("s\ns\ns\ns\ns\nc\n", &[]);
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(outputs, vec![3, 3]);
+    assert_eq!(stops(&said), vec!["1:1", "2:1", "3:5", "4:5", "3:5", "4:5"]);
+    assert!(said.starts_with("stopped at 1:1: x = 1;\n(xa) "));
+}
+
+#[test]
+fn next_steps_over_blocks() {
+    let (result, _, said) = debug("n\nn\nc\n", &[]);
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(stops(&said), vec!["1:1", "2:1", "8:1"]);
+
+    // finish leaves the loop body
+    let (_, _, said) = debug("s\ns\nf\nc\n", &[]);
+    assert_eq!(stops(&said), vec!["1:1", "2:1", "3:5", "8:1"]);
+}
+
+#[test]
+fn breakpoints_and_variables() {
+    let (result, outputs, said) = debug("c\np x\nset x = 10\nvars\nc\n", &[5]);
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(stops(&said), vec!["1:1", "5:9"]);
+    assert!(said.contains("(xa) x = 3\n"));
+    assert!(said.contains("(xa) x = 10\n(xa) x = 10\n"));
+    assert_eq!(outputs, vec![10, 10]);
+
+    // Breakpoints can be added and removed while stopped
+    let (_, _, said) = debug("b 3\nc\nd 3\nc\n", &[]);
+    assert_eq!(stops(&said), vec!["1:1", "3:5"]);
+    assert!(said.contains("breakpoint on line 3"));
+}
+
+#[test]
+fn quit_and_end_of_input() {
+    let (result, outputs, _) = debug("s\nq\n", &[]);
+    assert_eq!(result, Err(ErrorType::Quit(Location::new(2, 1))));
+    assert!(outputs.is_empty());
+
+    // Running out of commands lets the program finish
+    let (result, outputs, said) = debug("s\n", &[5]);
+    assert_eq!(result, Ok(()));
+    assert_eq!(outputs, vec![3, 3]);
+    assert_eq!(stops(&said), vec!["1:1", "2:1"]);
+}
+
+#[test]
+fn statements_without_a_line() {
+    // Made up statements have no line to show
+    let mut debugger = Debugger::new(Sources::new(SOURCE), "c\n".as_bytes(), Vec::new());
+    assert!(debugger.prompt(Location::default(), 1, &mut Memory::new()).unwrap());
+    assert!(String::from_utf8(debugger.into_output()).unwrap().starts_with("stopped at 0:0: \n"));
+}
+
+#[test]
+fn stops_in_imported_files() {
+    let dir = std::env::temp_dir().join(format!("debugger-imports-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lib = "x = 4;\ny = 1;\n";
+    std::fs::write(dir.join("lib.xa"), lib).unwrap();
+    let source = "IMPORT \"lib.xa\";\nz = lib.x + 1;\nOUTPUT z;";
+    let program = parser::Loader::new().load_source(source, &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut sources = Sources::new(source);
+    sources.add(1, "lib.xa", lib);
+    // Breakpoints are on lines of the main file
+    let mut debugger = Debugger::new(sources, "c\nc\n".as_bytes(), Vec::new());
+    debugger.add_breakpoint(2);
+
+    let mut interpreter = Interpreter::with_hook(debugger);
+    assert_eq!(interpreter.run(&program), Ok(()));
+    let said = String::from_utf8(interpreter.hook.into_output()).unwrap();
+    assert_eq!(stops(&said), vec!["lib.xa:1:1", "2:1"]);
+    assert!(said.starts_with("stopped at lib.xa:1:1: x = 4;\n"));
+}
@@ -0,0 +1,248 @@
+//! Checks `.xa` programs against the results they are known to give. What a
+//! program gives is a line per value it outputs, then a last line if it
+//! fails, either `parse error: ...` or `error: ...`, both with where.
+//!
+//! The expected lines come from a `.out` file next to the program, or if
+//! there isn't one from `// expect:` comments in it, in order:
+//!
+//! ```text
+//! OUTPUT 1; // expect: 1
+//! OUTPUT 1 / 0;
+//! // expect: error: 2:1: division by zero
+//! ```
+//!
+//! The program's `TEST` blocks are run too, each on its own, and all of
+//! them have to pass. A program with tests needs nothing else expected of it.
+
+#[cfg(test)]
+mod tests;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use language::Program;
+
+const ANNOTATION: &str = "// expect:";
+
+/// What running `source` gives, a line for each value output and then one
+/// for the error if it fails. It can't import, `check` runs files that do
+pub fn run(source: &str) -> Vec<String> {
+    return results(&parser::Loader::new(), &parser::parse_source(source));
+}
+
+/// The lines a program gives, errors in imported files say which file
+fn results(loader: &parser::Loader, parsed: &Result<Program, parser::ParseError>) -> Vec<String> {
+    let program = match parsed {
+        Ok(program) => program,
+        Err(e) => return vec![format!("parse error: {}", loader.describe(e))]
+    };
+
+    let mut interpreter = interpreter::Interpreter::new();
+    let result = interpreter.run(program);
+
+    let mut lines: Vec<String> = interpreter.output_vec.iter().map(|value| value.to_string()).collect();
+    if let Err(e) = result {
+        lines.push(format!("error: {}: {}", loader.locate(interpreter.error_location().unwrap_or_default()), e));
+    }
+    return lines;
+}
+
+/// How one `TEST` block went
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutcome {
+    pub name: String,
+    /// Where and why it failed, if it did
+    pub failure: Option<String>
+}
+
+/// Runs each of the `TEST` blocks in `source`, none if it doesn't parse
+pub fn run_tests(source: &str) -> Vec<TestOutcome> {
+    return test_results(&parser::Loader::new(), &parser::parse_source(source));
+}
+
+fn test_results(loader: &parser::Loader, parsed: &Result<Program, parser::ParseError>) -> Vec<TestOutcome> {
+    let Ok(program) = parsed else {
+        return Vec::new();
+    };
+
+    return interpreter::run_tests(program).into_iter()
+        .map(|result| TestOutcome{
+            name: result.name,
+            failure: result.error.map(|(e, loc)| format!("{}: {}", loader.locate(loc), e))
+        })
+        .collect();
+}
+
+/// The `// expect:` lines in `source`, in order
+pub fn annotations(source: &str) -> Vec<String> {
+    return source.lines()
+        .filter_map(|line| line.split_once(ANNOTATION))
+        .map(|(_, expected)| expected.trim().to_string())
+        .collect();
+}
+
+/// Where a program's expected results come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    File(PathBuf),
+    Annotations,
+    Nothing
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub path: PathBuf,
+    pub expected_from: Expected,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+    pub tests: Vec<TestOutcome>
+}
+
+impl Outcome {
+    /// Whether the program gave what was expected of it
+    pub fn output_passed(&self) -> bool {
+        self.expected_from != Expected::Nothing && self.expected == self.actual
+    }
+
+    pub fn tests_passed(&self) -> bool {
+        self.tests.iter().all(|test| test.failure.is_none())
+    }
+
+    pub fn passed(&self) -> bool {
+        let output = match self.expected_from {
+            Expected::Nothing => !self.tests.is_empty(),
+            _ => self.output_passed()
+        };
+        output && self.tests_passed()
+    }
+}
+
+/// The `.out` file for a program
+pub fn out_file(path: &Path) -> PathBuf {
+    return path.with_extension("out");
+}
+
+/// Runs the program at `path` and compares it with what is expected
+pub fn check(path: &Path) -> io::Result<Outcome> {
+    let source = fs::read_to_string(path)?;
+    let out = out_file(path);
+
+    let (expected_from, expected) = if out.is_file() {
+        let expected = fs::read_to_string(&out)?.lines().map(str::to_string).collect();
+        (Expected::File(out), expected)
+    } else if source.contains(ANNOTATION) {
+        (Expected::Annotations, annotations(&source))
+    } else {
+        (Expected::Nothing, Vec::new())
+    };
+
+    // Imports are relative to the program
+    let mut loader = parser::Loader::new();
+    let parsed = loader.load_source(&source, path.parent().unwrap_or(Path::new("")));
+    let (actual, tests) = (results(&loader, &parsed), test_results(&loader, &parsed));
+
+    return Ok(Outcome{path: path.to_path_buf(), expected_from, expected, actual, tests});
+}
+
+/// Every `.xa` file in `paths`, looking through directories, in order
+pub fn find(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)?.map(|entry| entry.map(|e| e.path())).collect::<Result<_, _>>()?;
+            entries.retain(|entry| entry.is_dir() || entry.extension().is_some_and(|e| e == "xa"));
+            entries.sort();
+            found.extend(find(&entries)?);
+        } else {
+            found.push(path.clone());
+        }
+    }
+
+    return Ok(found);
+}
+
+/// The lines of `expected` and `actual`, marked `-` if only expected, `+`
+/// if only actual, or a space if in both
+pub fn diff(expected: &[String], actual: &[String]) -> Vec<String> {
+    // The longest common subsequence of what is left of each
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = match expected[i] == actual[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!(" {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("-{}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}", actual[j]));
+            j += 1;
+        }
+    }
+
+    return lines;
+}
+
+/// `source` with its `// expect:` comments made to say `actual`. They are
+/// changed where they are if there are as many as needed, otherwise they
+/// are taken out and new ones put at the end
+pub fn rewrite_annotations(source: &str, actual: &[String]) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut rewritten = Vec::new();
+    let in_place = annotations(source).len() == actual.len();
+
+    if in_place {
+        let mut actual = actual.iter();
+        for line in lines {
+            match line.split_once(ANNOTATION) {
+                Some((code, _)) => rewritten.push(format!("{}{} {}", code, ANNOTATION, actual.next().unwrap())),
+                None => rewritten.push(line.to_string())
+            }
+        }
+    } else {
+        for line in lines {
+            match line.split_once(ANNOTATION) {
+                Some((code, _)) if code.trim().is_empty() => (),
+                Some((code, _)) => rewritten.push(code.trim_end().to_string()),
+                None => rewritten.push(line.to_string())
+            }
+        }
+        rewritten.extend(actual.iter().map(|line| format!("{} {}", ANNOTATION, line)));
+    }
+
+    let mut text = rewritten.join("\n");
+    if source.ends_with('\n') || !in_place {
+        text.push('\n');
+    }
+    return text;
+}
+
+/// Makes what is expected of the program what it actually gives. Programs
+/// with `// expect:` comments have them rewritten, any others get a `.out`
+pub fn bless(outcome: &Outcome) -> io::Result<()> {
+    match &outcome.expected_from {
+        Expected::Annotations => {
+            let source = fs::read_to_string(&outcome.path)?;
+            return fs::write(&outcome.path, rewrite_annotations(&source, &outcome.actual));
+        },
+        Expected::File(out) => return fs::write(out, lines_text(&outcome.actual)),
+        Expected::Nothing => return fs::write(out_file(&outcome.path), lines_text(&outcome.actual))
+    }
+}
+
+fn lines_text(lines: &[String]) -> String {
+    return lines.iter().map(|line| format!("{}\n", line)).collect();
+}
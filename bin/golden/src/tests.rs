@@ -0,0 +1,123 @@
+use crate::*;
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn runs_to_lines() {
+    assert_eq!(run("OUTPUT 1;\nOUTPUT 2 * 3;"), lines("1\n6"));
+    assert_eq!(run("OUTPUT 1;\nx = 2147483647;\nOUTPUT x + 1;"), lines("1\nerror: 3:1: addition overflowed"));
+    assert_eq!(run("REPEAT (0 - 1) {\n}"), lines("error: 1:1: REPEAT count is negative"));
+    assert_eq!(run("OUTPUT 1 +;"), lines("parse error: 1:11: unexpected token SC"));
+}
+
+#[test]
+fn reads_annotations() {
+    let source = "OUTPUT 1; // expect: 1\n// expect:   2  \nOUTPUT 2;\nOUTPUT 1 / 0; // note\n// expect: error: 4:1: division by zero\n";
+    assert_eq!(annotations(source), lines("1\n2\nerror: 4:1: division by zero"));
+    assert_eq!(annotations(source), run(source));
+}
+
+#[test]
+fn runs_test_blocks() {
+    let source = "OUTPUT 1;\nTEST \"holds\" {\n    ASSERT_EQ (2 * 2, 4);\n}\nTEST \"fails\" {\n    x = 3;\n    ASSERT_EQ (x, 4);\n}";
+    assert_eq!(run(source), lines("1"));
+    assert_eq!(run_tests(source), vec![
+        TestOutcome{name: "holds".to_string(), failure: None},
+        TestOutcome{name: "fails".to_string(), failure: Some("7:5: assertion failed: 3 != 4".to_string())}
+    ]);
+    assert_eq!(run_tests("TEST \"x\" {"), vec![]);
+
+    // Tests are enough to check a program by, but all have to pass
+    let outcome = |tests: Vec<TestOutcome>| Outcome{
+        path: PathBuf::from("t.xa"), expected_from: Expected::Nothing, expected: Vec::new(), actual: lines("1"), tests
+    };
+    assert!(!outcome(Vec::new()).passed());
+    assert!(outcome(run_tests(source)[..1].to_vec()).passed());
+    assert!(!outcome(run_tests(source)).passed());
+}
+
+#[test]
+fn diffs_lines() {
+    assert_eq!(diff(&lines("1\n2\n3\n4"), &lines("1\n3\n5\n4\n6")), lines(" 1\n-2\n 3\n+5\n 4\n+6"));
+    assert_eq!(diff(&[], &lines("1")), lines("+1"));
+    assert_eq!(diff(&lines("1\n2"), &lines("1\n2")), lines(" 1\n 2"));
+}
+
+#[test]
+fn rewrites_annotations() {
+    // As many as needed are changed where they are
+    assert_eq!(
+        rewrite_annotations("OUTPUT 1; // expect: 2\nOUTPUT 3;\n// expect: 4\n", &lines("1\n3")),
+        "OUTPUT 1; // expect: 1\nOUTPUT 3;\n// expect: 3\n"
+    );
+    // Otherwise they all go to the end
+    assert_eq!(
+        rewrite_annotations("OUTPUT 1; // expect: 1\n// expect: 2\nOUTPUT 1 / 0;", &lines("1\n2\nerror: 3:1: division by zero")),
+        "OUTPUT 1;\nOUTPUT 1 / 0;\n// expect: 1\n// expect: 2\n// expect: error: 3:1: division by zero\n"
+    );
+}
+
+/// The examples have their results checked in, and blessing a copy of one
+/// whose results are wrong puts them right
+#[test]
+fn checks_and_blesses_files() {
+    let examples = find(&[PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../examples"))]).unwrap();
+    assert!(!examples.is_empty());
+    for example in examples.iter() {
+        let outcome = check(example).unwrap();
+        assert!(outcome.passed(), "{:?}\n{}", example, diff(&outcome.expected, &outcome.actual).join("\n"));
+        assert!(matches!(outcome.expected_from, Expected::File(_)));
+    }
+
+    let dir = std::env::temp_dir().join(format!("golden-test-{}", std::process::id()));
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    let program = dir.join("nested/wrong.xa");
+    fs::write(&program, "OUTPUT 5;\n").unwrap();
+    fs::write(out_file(&program), "6\n").unwrap();
+    fs::write(dir.join("notes.txt"), "not a program").unwrap();
+
+    assert_eq!(find(std::slice::from_ref(&dir)).unwrap(), vec![program.clone()]);
+    let outcome = check(&program).unwrap();
+    assert!(!outcome.passed());
+    bless(&outcome).unwrap();
+    assert!(check(&program).unwrap().passed());
+    assert_eq!(fs::read_to_string(out_file(&program)).unwrap(), "5\n");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Imports are found next to the program, and errors in them say where
+#[test]
+fn checks_programs_with_imports() {
+    let dir = std::env::temp_dir().join(format!("golden-imports-{}", std::process::id()));
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/limits.xa"), "top = 10;\nzero = 0;\n").unwrap();
+    let program = dir.join("main.xa");
+    fs::write(&program, "IMPORT \"lib/limits.xa\" EXPOSING (top);\nOUTPUT top; // expect: 10\nOUTPUT top / limits.zero;\n// expect: error: 3:1: division by zero\n").unwrap();
+    assert!(check(&program).unwrap().passed());
+
+    fs::write(dir.join("lib/limits.xa"), "top = 10\n").unwrap();
+    let outcome = check(&program).unwrap();
+    let broken = dir.join("lib").join("limits.xa");
+    assert_eq!(outcome.actual, vec![format!("parse error: {}:2:1: unexpected token EOF_TOK", broken.display())]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Tests start from the imported modules, as the program does
+#[test]
+fn tests_read_imports() {
+    let dir = std::env::temp_dir().join(format!("golden-test-imports-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lib.xa"), "y = 7;\n").unwrap();
+    let program = dir.join("main.xa");
+    fs::write(&program, "IMPORT \"lib.xa\" EXPOSING (y);\nTEST \"t\" {\n    ASSERT_EQ (lib.y, 7);\n    ASSERT_EQ (y, 7);\n}\n").unwrap();
+
+    let outcome = check(&program).unwrap();
+    assert_eq!(outcome.tests.len(), 1);
+    assert!(outcome.passed(), "{:?}", outcome.tests);
+
+    fs::remove_dir_all(dir).unwrap();
+}
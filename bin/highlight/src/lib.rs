@@ -0,0 +1,287 @@
+//! Syntax highlighting for `.xa` source, built on the lexer so it reads the
+//! source the same way the compiler does. Source can be rendered as HTML or
+//! coloured for a terminal, and a TextMate grammar is generated for editors.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use lexer::{Lexer, KEYWORDS, TOKEN_TYPE};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Class {
+    Keyword,
+    /// Where a variable is first assigned
+    Definition,
+    /// Any other use of a variable
+    Identifier,
+    Number,
+    String,
+    Operator,
+    Punctuation,
+    Comment
+}
+
+impl Class {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Class::Keyword => "keyword",
+            Class::Definition => "definition",
+            Class::Identifier => "identifier",
+            Class::Number => "number",
+            Class::String => "string",
+            Class::Operator => "operator",
+            Class::Punctuation => "punctuation",
+            Class::Comment => "comment"
+        }
+    }
+
+    /// The SGR parameters it is drawn with in a terminal, if any
+    fn ansi(&self) -> Option<&'static str> {
+        match self {
+            Class::Keyword => Some("1;35"),
+            Class::Definition => Some("1;34"),
+            Class::Identifier => Some("34"),
+            Class::Number => Some("36"),
+            Class::String => Some("32"),
+            Class::Operator => Some("33"),
+            Class::Punctuation => None,
+            Class::Comment => Some("2;3")
+        }
+    }
+}
+
+const OPERATORS: [TOKEN_TYPE; 14] = [
+    TOKEN_TYPE::ASSIGN, TOKEN_TYPE::AND, TOKEN_TYPE::OR, TOKEN_TYPE::PLUS, TOKEN_TYPE::MINUS, TOKEN_TYPE::ASTERIX,
+    TOKEN_TYPE::DIV, TOKEN_TYPE::MOD, TOKEN_TYPE::EQ, TOKEN_TYPE::NEQ, TOKEN_TYPE::LE, TOKEN_TYPE::LT,
+    TOKEN_TYPE::GE, TOKEN_TYPE::GT
+];
+
+const PUNCTUATION: [TOKEN_TYPE; 7] = [
+    TOKEN_TYPE::LBRA, TOKEN_TYPE::RBRA, TOKEN_TYPE::LPAR, TOKEN_TYPE::RPAR, TOKEN_TYPE::SC, TOKEN_TYPE::COMMA, TOKEN_TYPE::DOT
+];
+
+/// A classified part of the source, as byte offsets. Space and anything the
+/// lexer doesn't know is left out
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub class: Class,
+    pub start: usize,
+    pub end: usize
+}
+
+/// How long the token starting `rest` is, reading it as lexer.c does
+fn token_len(tok_type: TOKEN_TYPE, rest: &str) -> usize {
+    if let Some(spelling) = tok_type.spelling() {
+        return spelling.len();
+    }
+
+    let letters = |s: &str| s.bytes().take_while(u8::is_ascii_alphabetic).count();
+    match tok_type {
+        // Numbers are cut at 20 digits, and letters straight after are skipped
+        TOKEN_TYPE::INT_LIT => {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count().min(20);
+            return digits + letters(&rest[digits..]);
+        },
+        // Up to and including the closing quote, which is on the same line
+        TOKEN_TYPE::STR_LIT => return rest[1..].find('"').map_or(rest.len(), |i| i + 2),
+        _ => return letters(rest)
+    }
+}
+
+/// Every token as its type and byte offsets. The lexer stops at something
+/// it doesn't know, so lexing starts again just after it
+fn tokens(source: &str) -> Vec<(TOKEN_TYPE, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut base = 0;
+
+    'lexing: loop {
+        let rest = &source[base..];
+        let line_starts: Vec<usize> = std::iter::once(0).chain(rest.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        let mut lexer = Lexer::from_source(rest.as_bytes());
+
+        loop {
+            let tok = lexer.next_token();
+            let start = base + line_starts[tok.line as usize - 1] + tok.column as usize - 1;
+
+            match tok.tok_type {
+                TOKEN_TYPE::EOF_TOK => break 'lexing,
+                TOKEN_TYPE::INVALID => {
+                    base = start + source[start..].chars().next().map_or(1, char::len_utf8);
+                    continue 'lexing;
+                },
+                tok_type => tokens.push((tok_type, start, start + token_len(tok_type, &source[start..])))
+            }
+        }
+    }
+
+    return tokens;
+}
+
+/// Comments in a stretch of source with no tokens in it
+fn comments(source: &str, start: usize, end: usize, spans: &mut Vec<Span>) {
+    let mut pos = start;
+
+    while let Some(found) = source[pos..end].find("//") {
+        let comment_start = pos + found;
+        let line_end = source[comment_start..end].find('\n').map_or(end, |i| comment_start + i);
+        let comment_end = comment_start + source[comment_start..line_end].trim_end().len();
+
+        spans.push(Span{class: Class::Comment, start: comment_start, end: comment_end});
+        pos = line_end;
+    }
+}
+
+/// Labels each part of `source`, in order
+pub fn classify(source: &str) -> Vec<Span> {
+    let tokens = tokens(source);
+    let mut spans = Vec::new();
+    let mut defined = BTreeSet::new();
+    let mut pos = 0;
+
+    for (i, &(tok_type, start, end)) in tokens.iter().enumerate() {
+        comments(source, pos, start, &mut spans);
+        pos = end;
+
+        let class = match tok_type {
+            TOKEN_TYPE::VAR => {
+                let assigned = tokens.get(i + 1).is_some_and(|(next, _, _)| *next == TOKEN_TYPE::ASSIGN);
+                match assigned && defined.insert(&source[start..end]) {
+                    true => Class::Definition,
+                    false => Class::Identifier
+                }
+            },
+            TOKEN_TYPE::INT_LIT => Class::Number,
+            TOKEN_TYPE::STR_LIT => Class::String,
+            tok_type if OPERATORS.contains(&tok_type) => Class::Operator,
+            tok_type if PUNCTUATION.contains(&tok_type) => Class::Punctuation,
+            _ => Class::Keyword
+        };
+        spans.push(Span{class, start, end});
+    }
+    comments(source, pos, source.len(), &mut spans);
+
+    return spans;
+}
+
+fn escape_html(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c)
+        }
+    }
+}
+
+/// A stylesheet for `html`, the classes are `xa-` and the class name
+pub const STYLESHEET: &str = "pre.xa .xa-keyword { color: #a626a4; font-weight: bold; }
+pre.xa .xa-definition { color: #4078f2; font-weight: bold; }
+pre.xa .xa-identifier { color: #4078f2; }
+pre.xa .xa-number { color: #0184bc; }
+pre.xa .xa-string { color: #50a14f; }
+pre.xa .xa-operator { color: #c18401; }
+pre.xa .xa-punctuation { color: #383a42; }
+pre.xa .xa-comment { color: #a0a1a7; font-style: italic; }
+";
+
+/// `source` as a `<pre>` block, each span in a `<span>` with its class
+pub fn html(source: &str) -> String {
+    let mut out = String::from("<pre class=\"xa\"><code>");
+    let mut pos = 0;
+
+    for span in classify(source) {
+        escape_html(&mut out, &source[pos..span.start]);
+        write!(out, "<span class=\"xa-{}\">", span.class.name()).unwrap();
+        escape_html(&mut out, &source[span.start..span.end]);
+        out.push_str("</span>");
+        pos = span.end;
+    }
+    escape_html(&mut out, &source[pos..]);
+
+    out.push_str("</code></pre>\n");
+    return out;
+}
+
+/// `source` coloured with ANSI escape codes
+pub fn ansi(source: &str) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+
+    for span in classify(source) {
+        let Some(colour) = span.class.ansi() else {
+            continue;
+        };
+        out.push_str(&source[pos..span.start]);
+        write!(out, "\x1b[{}m{}\x1b[0m", colour, &source[span.start..span.end]).unwrap();
+        pos = span.end;
+    }
+    out.push_str(&source[pos..]);
+
+    return out;
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// An alternation matching any of the tokens, longest first so `<=` isn't
+/// read as `<`
+fn alternation(tok_types: &[TOKEN_TYPE]) -> String {
+    let mut spellings: Vec<&str> = tok_types.iter().filter_map(|t| t.spelling()).collect();
+    spellings.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+    let escaped: Vec<String> = spellings.iter()
+        .map(|s| s.chars().map(|c| if "\\^$.|?*+()[]{}".contains(c) { format!("\\{}", c) } else { c.to_string() }).collect())
+        .collect();
+    return escaped.join("|");
+}
+
+/// A TextMate grammar for `.xa` files, made from the lexer's keywords and
+/// tokens so it reads source the same way. Editors can't tell the first
+/// assignment from later ones, so every assignment is scoped as one
+pub fn textmate_grammar() -> String {
+    let mut keywords: Vec<&str> = KEYWORDS.iter().map(|(word, _)| *word).collect();
+    keywords.sort_by_key(|k| std::cmp::Reverse(k.len()));
+
+    // Names are letters only and keywords can also have underscores, so
+    // digits can touch them
+    let word = |pattern: &str| format!("(?<![A-Za-z_]){}(?![A-Za-z_])", pattern);
+    let patterns = [
+        ("comment.line.double-slash.xa", "//.*$".to_string()),
+        ("keyword.control.xa", word(&format!("({})", keywords.join("|")))),
+        ("constant.numeric.integer.xa", "[0-9]+".to_string()),
+        ("string.quoted.double.xa", "\"[^\"]*\"".to_string()),
+        ("variable.other.assignment.xa", format!("{}(?=\\s*=(?!=))", word("[A-Za-z]+"))),
+        ("variable.other.xa", word("[A-Za-z]+")),
+        ("keyword.operator.xa", alternation(&OPERATORS)),
+        ("punctuation.xa", alternation(&PUNCTUATION))
+    ];
+
+    let mut out = String::from("{\n");
+    out.push_str("    \"$schema\": \"https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json\",\n");
+    out.push_str("    \"name\": \"xa\",\n");
+    out.push_str("    \"scopeName\": \"source.xa\",\n");
+    out.push_str("    \"fileTypes\": [\"xa\"],\n");
+    out.push_str("    \"patterns\": [\n");
+    for (i, (name, pattern)) in patterns.iter().enumerate() {
+        let comma = if i + 1 < patterns.len() { "," } else { "" };
+        writeln!(out, "        {{ \"name\": {}, \"match\": {} }}{}", json_string(name), json_string(pattern), comma).unwrap();
+    }
+    out.push_str("    ]\n}\n");
+
+    return out;
+}
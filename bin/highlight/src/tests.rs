@@ -0,0 +1,78 @@
+use crate::*;
+
+fn classes(source: &str) -> Vec<(&str, &'static str)> {
+    classify(source).iter().map(|s| (&source[s.start..s.end], s.class.name())).collect()
+}
+
+#[test]
+fn labels_every_token() {
+    assert_eq!(classes("total = 0; // start\nREPEAT (10) {\n    total = total + 12;\n}\nOUTPUT total >= 5;"), vec![
+        ("total", "definition"), ("=", "operator"), ("0", "number"), (";", "punctuation"), ("// start", "comment"),
+        ("REPEAT", "keyword"), ("(", "punctuation"), ("10", "number"), (")", "punctuation"), ("{", "punctuation"),
+        ("total", "identifier"), ("=", "operator"), ("total", "identifier"), ("+", "operator"), ("12", "number"), (";", "punctuation"),
+        ("}", "punctuation"),
+        ("OUTPUT", "keyword"), ("total", "identifier"), (">=", "operator"), ("5", "number"), (";", "punctuation")
+    ]);
+
+    // Reading or comparing isn't assigning, and keywords need a word to themselves
+    assert_eq!(classes("IF (a == 1) {\n}\nELSEIFx = 2;"), vec![
+        ("IF", "keyword"), ("(", "punctuation"), ("a", "identifier"), ("==", "operator"), ("1", "number"), (")", "punctuation"),
+        ("{", "punctuation"), ("}", "punctuation"), ("ELSEIFx", "definition"), ("=", "operator"), ("2", "number"), (";", "punctuation")
+    ]);
+}
+
+#[test]
+fn labels_tests_and_assertions() {
+    assert_eq!(classes("TEST \"a // b\" {\n    ASSERT_EQ (x, 2); // two\n}"), vec![
+        ("TEST", "keyword"), ("\"a // b\"", "string"), ("{", "punctuation"),
+        ("ASSERT_EQ", "keyword"), ("(", "punctuation"), ("x", "identifier"), (",", "punctuation"), ("2", "number"),
+        (")", "punctuation"), (";", "punctuation"), ("// two", "comment"), ("}", "punctuation")
+    ]);
+    assert_eq!(classes("IMPORT \"lib.xa\" EXPOSING (y);\nlib.x = y;"), vec![
+        ("IMPORT", "keyword"), ("\"lib.xa\"", "string"), ("EXPOSING", "keyword"), ("(", "punctuation"), ("y", "identifier"),
+        (")", "punctuation"), (";", "punctuation"), ("lib", "identifier"), (".", "punctuation"), ("x", "definition"),
+        ("=", "operator"), ("y", "identifier"), (";", "punctuation")
+    ]);
+}
+
+#[test]
+fn carries_on_past_what_the_lexer_does_not_know() {
+    assert_eq!(classes("x = 1 ! 2; // é\n  // only\ny = @x;"), vec![
+        ("x", "definition"), ("=", "operator"), ("1", "number"), ("2", "number"), (";", "punctuation"), ("// é", "comment"),
+        ("// only", "comment"), ("y", "definition"), ("=", "operator"), ("x", "identifier"), (";", "punctuation")
+    ]);
+
+    // Numbers are cut where the lexer cuts them
+    assert_eq!(classes("123456789012345678901234 5abc"), vec![
+        ("12345678901234567890", "number"), ("1234", "number"), ("5abc", "number")
+    ]);
+}
+
+#[test]
+fn renders_html_and_ansi() {
+    assert_eq!(
+        html("IF (a < b) {\n}"),
+        "<pre class=\"xa\"><code><span class=\"xa-keyword\">IF</span> <span class=\"xa-punctuation\">(</span>\
+<span class=\"xa-identifier\">a</span> <span class=\"xa-operator\">&lt;</span> <span class=\"xa-identifier\">b</span>\
+<span class=\"xa-punctuation\">)</span> <span class=\"xa-punctuation\">{</span>\n<span class=\"xa-punctuation\">}</span></code></pre>\n"
+    );
+    assert_eq!(ansi("x = 1; // one"), "\x1b[1;34mx\x1b[0m \x1b[33m=\x1b[0m \x1b[36m1\x1b[0m; \x1b[2;3m// one\x1b[0m");
+
+    let source = include_str!("../../../examples/fibbonachi.xa");
+    let plain = ansi(source).replace("\x1b[0m", "").split("\x1b[").enumerate()
+        .map(|(i, part)| if i == 0 { part } else { &part[part.find('m').unwrap() + 1..] })
+        .collect::<String>();
+    assert_eq!(plain, source);
+}
+
+#[test]
+fn grammar_follows_the_lexer() {
+    let grammar = textmate_grammar();
+    for (word, _) in KEYWORDS {
+        assert!(grammar.contains(word), "{}", word);
+    }
+    assert!(grammar.contains(r#""match": "(?<![A-Za-z_])(ASSERT_EQ|EXPOSING|ELSEIF|REPEAT|OUTPUT|ASSERT|IMPORT|ELSE|TEST|IF)(?![A-Za-z_])""#), "{}", grammar);
+    assert!(grammar.contains(r#""match": "\\{|\\}|\\(|\\)|;|,|\\.""#), "{}", grammar);
+    assert!(grammar.contains(r#""match": "&&|\\|\\||==|!=|<=|>=|=|\\+|-|\\*|/|%|<|>""#), "{}", grammar);
+    assert!(grammar.contains(r#""scopeName": "source.xa""#));
+}
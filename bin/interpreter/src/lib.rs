@@ -3,6 +3,10 @@ mod tests;
 
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use ::language::*;
 
@@ -11,9 +15,50 @@ use ::language::*;
 pub enum ErrorType {
     NegativeRepeateError,
     UninitialisedMemory(String),
-    NotImplimented
+    NotImplimented,
+    BudgetExhausted(Exhausted, Location),
+    /// A hook stopped the run, as the debugger does when told to quit
+    Quit(Location),
+    TooManyOutputs,
+    OutputTooLarge,
+    TooManyVariables,
+    NestedTooDeep,
+    /// Overflow or dividing by zero, with what went wrong
+    ArithmeticError(&'static str),
+    /// An `ASSERT` whose value was 0, or an `ASSERT_EQ` whose values differ
+    AssertionFailed{value: i32, expected: Option<i32>, loc: Location}
 }
 
+/// Which part of the `Budget` ran out
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Exhausted {
+    Fuel,
+    Deadline,
+    Cancelled
+}
+
+impl fmt::Display for ErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorType::NegativeRepeateError => write!(f, "REPEAT count is negative"),
+            ErrorType::UninitialisedMemory(msg) => write!(f, "{}", msg),
+            ErrorType::NotImplimented => write!(f, "not implemented"),
+            ErrorType::BudgetExhausted(Exhausted::Fuel, _) => write!(f, "ran out of fuel"),
+            ErrorType::BudgetExhausted(Exhausted::Deadline, _) => write!(f, "deadline passed"),
+            ErrorType::BudgetExhausted(Exhausted::Cancelled, _) => write!(f, "run was cancelled"),
+            ErrorType::Quit(_) => write!(f, "quit"),
+            ErrorType::TooManyOutputs => write!(f, "too many values output"),
+            ErrorType::OutputTooLarge => write!(f, "output is too large"),
+            ErrorType::TooManyVariables => write!(f, "too many variables"),
+            ErrorType::NestedTooDeep => write!(f, "blocks nested too deeply"),
+            ErrorType::ArithmeticError(msg) => write!(f, "{}", msg),
+            ErrorType::AssertionFailed{expected: None, ..} => write!(f, "assertion failed"),
+            ErrorType::AssertionFailed{value, expected: Some(expected), ..} => write!(f, "assertion failed: {} != {}", value, expected)
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct Memory {
     pub mem: HashMap<String, i32>
 }
@@ -24,88 +69,423 @@ impl Memory {
     }
 }
 
-pub fn interpret(program: Program) -> Result<Vec<i32>, ErrorType> {
-    // Initialise some memory for variables
-    let mut memory = Memory::new();
+/// How much work a single run is allowed to do, nothing is limited by default.
+/// One step is charged for every statement, every expression evaluated and
+/// every time round a REPEAT, so even an empty loop body costs something
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    pub fuel: Option<u64>,
+    pub deadline: Option<Instant>,
+    /// Set to true from anywhere to stop the run
+    pub cancel: Option<Arc<AtomicBool>>
+}
+
+/// Caps on what a run can make the interpreter hold, nothing is capped by
+/// default. Outputs are counted per run, variables are counted over the
+/// whole of `Memory` as it outlives runs
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub max_outputs: Option<usize>,
+    /// Bytes the outputs take when printed one per line
+    pub max_output_bytes: Option<usize>,
+    pub max_variables: Option<usize>,
+    /// The program's own block counts as depth 1
+    pub max_depth: Option<usize>
+}
+
+// Reading the clock on every step would be most of the cost of a step
+const CLOCK_CHECK_INTERVAL: u64 = 256;
+
+/// Lets tools watch a program as it runs. Every method does nothing by
+/// default, and returning an error from one stops the run with that error
+pub trait Hook {
+    /// Called before each statement runs. `depth` is how deeply the block
+    /// holding the statement is nested, the program's own block is depth 1
+    fn before_statement(&mut self, _stmt: &Statement, _depth: usize, _memory: &mut Memory) -> Result<(), ErrorType> {
+        Ok(())
+    }
+
+    /// Called once a statement, and everything nested in it, has finished
+    /// without an error
+    fn after_statement(&mut self, _stmt: &Statement) {}
+
+    /// Called before both sides of a binary operation are evaluated
+    fn before_bin_op(&mut self, _exp: &Expression) {}
 
-    // Initialise output buffer
-    let mut output_vec = Vec::new();
+    /// Called once a binary operation has been evaluated without an error
+    fn after_bin_op(&mut self, _exp: &Expression, _val: i32) {}
 
-    run_block(&program.program, &mut memory, &mut output_vec)?;
+    /// Called once an assignment has been stored
+    fn after_assign(&mut self, _stmt: &Statement, _var: &str, _val: i32) {}
 
-    return Ok(output_vec);
+    /// Called once an IF statement has picked an arm, before the arm runs
+    fn after_branch(&mut self, _stmt: &Statement, _taken: Branch) {}
+
+    /// Called once a REPEAT statement knows how many times it will run, even
+    /// when the count is negative and the run is about to stop
+    fn before_repeat(&mut self, _stmt: &Statement, _times: i32) {}
+
+    /// Called before each time round a REPEAT, counting from 1
+    fn before_iteration(&mut self, _stmt: &Statement, _iteration: i32) {}
+
+    /// Called once a value has been output
+    fn after_output(&mut self, _stmt: &Statement, _val: i32) {}
+
+    /// Called once an ASSERT or ASSERT_EQ has its values, before the run
+    /// stops if it doesn't hold
+    fn after_assert(&mut self, _stmt: &Statement, _value: i32, _expected: Option<i32>) {}
 }
 
-#[cfg(test)]
-pub fn pub_run_block(block: &Block, memory: &mut Memory, output_vec: &mut Vec<i32>) -> Result<(), ErrorType> {
-    run_block(block, memory, output_vec)
-}
-
-fn run_block(block: &Block, memory: &mut Memory, output_vec: &mut Vec<i32>) -> Result<(), ErrorType> {
-    // Loop through the program statement by statement
-    for stmt in (*block).statements.as_slice() {
-        match stmt {
-            // If the statement is an assign statment
-            Statement::AssignStatement{
-                var, 
-                exp
-            } => {
-                let eval = eval_exp(exp, memory)?;
-                assign(&var, eval, memory);
-            },
-
-            Statement::IfStatement{
-                condition, 
-                body, 
-                else_if, 
-                else_body
-            } => {
-                let eval = eval_exp(condition, memory)?;
-                let mut has_run: bool = false;
-
-                if eval != 0 {
-                    run_block(body, memory, output_vec)?;
-                    has_run = true;
-                } else {
-                    for (exp, block) in else_if {
-                        let eval = eval_exp(exp, memory)?;
-
-                        if eval != 0 {
-                            run_block(block, memory, output_vec)?;
-                            has_run = true;
-                            break;
-                        }
-                    }
+/// Which arm of an IF statement ran. `ElseIf` holds the position of the arm
+/// in `else_if`, and `Else` is taken even when there is no ELSE body
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Branch {
+    If,
+    ElseIf(usize),
+    Else
+}
+
+/// The hook used when nothing is watching
+#[derive(Default)]
+pub struct NoHook;
+
+impl Hook for NoHook {}
+
+/// Runs programs against memory that outlives any single run, so variables
+/// can be looked at or reused once a program has finished
+pub struct Interpreter<H: Hook = NoHook> {
+    pub memory: Memory,
+    pub output_vec: Vec<i32>,
+    pub budget: Budget,
+    pub limits: Limits,
+    steps: u64,
+    depth: usize,
+    outputs: usize,
+    output_bytes: usize,
+    // Location of the statement currently being run
+    location: Option<Location>,
+    pub hook: H
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_hook(NoHook)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Hook> Interpreter<H> {
+    pub fn with_hook(hook: H) -> Self {
+        Self{
+            memory: Memory::new(), 
+            output_vec: Vec::new(), 
+            budget: Budget::default(), 
+            limits: Limits::default(),
+            steps: 0, 
+            depth: 0,
+            outputs: 0,
+            output_bytes: 0,
+            location: None,
+            hook
+        }
+    }
+
+    /// Runs the program, appending to `output_vec`. On error the output up to
+    /// the failing statement is kept and `error_location` says where it failed
+    pub fn run(&mut self, program: &Program) -> Result<(), ErrorType> {
+        self.location = None;
+        self.steps = 0;
+        self.depth = 0;
+        self.outputs = 0;
+        self.output_bytes = 0;
+        self.run_block(&program.program)
+    }
+
+    /// Runs a `TEST` block as if it were a whole program, in fresh memory
+    pub fn run_test(&mut self, test: &Test) -> Result<(), ErrorType> {
+        self.memory = Memory::new();
+        self.run(&Program{program: test.body.clone(), tests: Vec::new()})
+    }
+
+    /// Location of the last statement to start running, which is the
+    /// statement that failed if `run` returned an error
+    pub fn error_location(&self) -> Option<Location> {
+        self.location
+    }
+
+    /// Steps charged against the budget by the last run
+    pub fn steps_used(&self) -> u64 {
+        self.steps
+    }
+
+    fn charge(&mut self) -> Result<(), ErrorType> {
+        self.steps += 1;
+
+        if let Some(fuel) = self.budget.fuel {
+            if self.steps > fuel {
+                return Err(self.exhausted(Exhausted::Fuel));
+            }
+        }
+
+        // Check on the first step so an already cancelled run does nothing
+        if self.steps % CLOCK_CHECK_INTERVAL == 1 {
+            if let Some(cancel) = &self.budget.cancel {
+                if cancel.load(Ordering::Relaxed) {
+                    return Err(self.exhausted(Exhausted::Cancelled));
                 }
+            }
 
-                if !has_run {
-                    run_block(else_body, memory, output_vec)?;
+            if let Some(deadline) = self.budget.deadline {
+                if Instant::now() >= deadline {
+                    return Err(self.exhausted(Exhausted::Deadline));
                 }
-            },
-
-            Statement::RepeatStatement{
-                times, 
-                body
-            } => {
-                let eval = eval_exp(&times, memory)?;
-
-                if eval < 0 {
-                    return Err(ErrorType::NegativeRepeateError)
-                } else {
-                    for _i in 0..eval {
-                        run_block(&body, memory, output_vec)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn exhausted(&self, what: Exhausted) -> ErrorType {
+        ErrorType::BudgetExhausted(what, self.location.unwrap_or_default())
+    }
+
+    fn output(&mut self, val: i32) -> Result<(), ErrorType> {
+        if let Some(max) = self.limits.max_outputs {
+            if self.outputs >= max {
+                return Err(ErrorType::TooManyOutputs);
+            }
+        }
+
+        // Printed as the number followed by a newline
+        let bytes = val.to_string().len() + 1;
+        if let Some(max) = self.limits.max_output_bytes {
+            if self.output_bytes + bytes > max {
+                return Err(ErrorType::OutputTooLarge);
+            }
+        }
+
+        self.outputs += 1;
+        self.output_bytes += bytes;
+        self.output_vec.push(val);
+
+        return Ok(());
+    }
+
+    fn check_variable_limit(&self, var: &str) -> Result<(), ErrorType> {
+        if let Some(max) = self.limits.max_variables {
+            if self.memory.mem.len() >= max && !self.memory.mem.contains_key(var) {
+                return Err(ErrorType::TooManyVariables);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn run_block(&mut self, block: &Block) -> Result<(), ErrorType> {
+        if let Some(max) = self.limits.max_depth {
+            if self.depth >= max {
+                return Err(ErrorType::NestedTooDeep);
+            }
+        }
+
+        self.depth += 1;
+        let result = self.run_statements(block);
+        self.depth -= 1;
+
+        return result;
+    }
+
+    fn run_statements(&mut self, block: &Block) -> Result<(), ErrorType> {
+        // Loop through the program statement by statement
+        for stmt in block.statements.as_slice() {
+            self.location = Some(stmt.location());
+            self.charge()?;
+            self.hook.before_statement(stmt, self.depth, &mut self.memory)?;
+
+            match stmt {
+                // If the statement is an assign statment
+                Statement::AssignStatement{
+                    var, 
+                    exp,
+                    ..
+                } => {
+                    let eval = self.eval_exp(exp)?;
+                    self.check_variable_limit(var)?;
+                    assign(var, eval, &mut self.memory);
+                    self.hook.after_assign(stmt, var, eval);
+                },
+
+                Statement::IfStatement{
+                    condition, 
+                    body, 
+                    else_if, 
+                    else_body,
+                    ..
+                } => {
+                    let eval = self.eval_exp(condition)?;
+                    let mut has_run: bool = false;
+
+                    if eval != 0 {
+                        self.hook.after_branch(stmt, Branch::If);
+                        self.run_block(body)?;
+                        has_run = true;
+                    } else {
+                        for (i, (exp, block)) in else_if.iter().enumerate() {
+                            let eval = self.eval_exp(exp)?;
+
+                            if eval != 0 {
+                                self.hook.after_branch(stmt, Branch::ElseIf(i));
+                                self.run_block(block)?;
+                                has_run = true;
+                                break;
+                            }
+                        }
+                    }
+
+                    if !has_run {
+                        self.hook.after_branch(stmt, Branch::Else);
+                        self.run_block(else_body)?;
+                    }
+                },
+
+                Statement::RepeatStatement{
+                    times, 
+                    body,
+                    ..
+                } => {
+                    let eval = self.eval_exp(times)?;
+                    self.hook.before_repeat(stmt, eval);
+
+                    if eval < 0 {
+                        return Err(ErrorType::NegativeRepeateError)
+                    } else {
+                        for i in 0..eval {
+                            self.charge()?;
+                            self.hook.before_iteration(stmt, i + 1);
+                            self.run_block(body)?;
+                        }
+                    }
+                },
+
+                Statement::OutputStatement { to_output, .. } => {
+                    let eval = self.eval_exp(to_output)?;
+                    self.output(eval)?;
+                    self.hook.after_output(stmt, eval);
+                },
+
+                Statement::AssertStatement { exp, expected, loc } => {
+                    let value = self.eval_exp(exp)?;
+                    let expected = match expected {
+                        Some(expected) => Some(self.eval_exp(expected)?),
+                        None => None
+                    };
+                    self.hook.after_assert(stmt, value, expected);
+
+                    let holds = match expected {
+                        Some(expected) => value == expected,
+                        None => value != 0
+                    };
+                    if !holds {
+                        return Err(ErrorType::AssertionFailed{value, expected, loc: *loc});
                     }
                 }
-            },
+            };
 
-            Statement::OutputStatement { to_output } => {
-                let eval = eval_exp(to_output, memory)?;
-                output_vec.push(eval);
+            self.hook.after_statement(stmt);
+        }
+
+        return Ok(());
+    }
+
+    fn eval_exp(&mut self, exp: &Expression) -> Result<i32, ErrorType> {
+        self.charge()?;
+
+        match exp {
+            Expression::Val(num) => return Ok(*num),
+            Expression::Var(var) => return access(var, &mut self.memory),
+            Expression::BinOp(
+                op,
+                lhs,
+                rhs
+            ) => {
+                self.hook.before_bin_op(exp);
+                let val = self.eval_bin_op(op, lhs, rhs)?;
+                self.hook.after_bin_op(exp, val);
+                return Ok(val);
             }
-        };
+        }
     }
 
-    return Ok(());
+    fn eval_bin_op(&mut self, op: &Op, lhs: &Expression, rhs: &Expression) -> Result<i32, ErrorType> {
+        let lhs_eval = self.eval_exp(lhs)?;
+
+        let rhs_eval = self.eval_exp(rhs)?;
+
+        return op.apply(lhs_eval, rhs_eval).map_err(ErrorType::ArithmeticError);
+    }
+}
+
+pub fn interpret(program: Program) -> Result<Vec<i32>, ErrorType> {
+    let mut interpreter = Interpreter::new();
+
+    interpreter.run(&program)?;
+
+    return Ok(interpreter.output_vec);
+}
+
+/// How one `TEST` block went
+#[derive(Debug, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub loc: Location,
+    pub output: Vec<i32>,
+    /// Why it failed and where, if it did
+    pub error: Option<(ErrorType, Location)>
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs each of the program's `TEST` blocks on its own, leaving out the
+/// rest of the program
+pub fn run_tests(program: &Program) -> Vec<TestResult> {
+    let mut results = Vec::new();
+
+    for test in program.tests.iter() {
+        let mut interpreter = Interpreter::new();
+        let error = interpreter.run_test(test).err().map(|e| (e, interpreter.error_location().unwrap_or(test.loc)));
+        results.push(TestResult{name: test.name.clone(), loc: test.loc, output: interpreter.output_vec, error});
+    }
+
+    return results;
+}
+
+/// Runs `f` on an interpreter using the given memory, for testing the parts
+/// of the interpreter that work on a single block or expression
+#[cfg(test)]
+fn with_memory<T>(memory: &mut Memory, f: impl FnOnce(&mut Interpreter) -> T) -> T {
+    let mut interpreter = Interpreter::new();
+    std::mem::swap(&mut interpreter.memory, memory);
+    let result = f(&mut interpreter);
+    std::mem::swap(&mut interpreter.memory, memory);
+    result
+}
+
+#[cfg(test)]
+pub fn pub_run_block(block: &Block, memory: &mut Memory, output_vec: &mut Vec<i32>) -> Result<(), ErrorType> {
+    with_memory(memory, |interpreter| {
+        let result = interpreter.run_block(block);
+        output_vec.append(&mut interpreter.output_vec);
+        result
+    })
 }
 
 #[cfg(test)]
@@ -142,50 +522,10 @@ fn access(var: &str, memory: &mut Memory) -> Result<i32, ErrorType> {
 
 #[cfg(test)]
 pub fn pub_eval_test(exp: &Expression, memory: &mut Memory) -> Result<i32, ErrorType> {
-    eval_exp(exp, memory)
-}
-
-fn eval_exp(exp: &Expression, memory: &mut Memory) -> Result<i32, ErrorType> {
-    match exp {
-        Expression::Val(num) => return Ok(*num),
-        Expression::Var(var) => return access(&var, memory),
-        Expression::BinOp(
-            op,
-            lhs,
-            rhs
-        ) => return eval_bin_op(op, &*lhs, &*rhs, memory)
-    }
+    with_memory(memory, |interpreter| interpreter.eval_exp(exp))
 }
 
 #[cfg(test)]
 pub fn pub_eval_binop_test(op: &Op, lhs: &Expression, rhs: &Expression, memory: &mut Memory) -> Result<i32, ErrorType> {
-    eval_bin_op(op, lhs, rhs, memory)
-}
-
-fn eval_bin_op(op: &Op, lhs: &Expression, rhs: &Expression, memory: &mut Memory) -> Result<i32, ErrorType> {
-    let lhs_eval = eval_exp(&lhs, memory)?;
-
-    let rhs_eval = eval_exp(&rhs, memory)?;
-
-    // At this point the errors should be dealt with so the evals will be i32
-    // which means they can simply be unwrapped
-
-    //TODO overflow add etc.
-    #[allow(unreachable_patterns)]
-    match op {
-        Op::Add => Ok(lhs_eval + rhs_eval),
-        Op::Sub => Ok(lhs_eval - rhs_eval),
-        Op::Multiply => Ok(lhs_eval * rhs_eval),
-        Op::Divide => Ok(lhs_eval / rhs_eval),
-        Op::Remainder => Ok(lhs_eval % rhs_eval),
-        Op::And => Ok((!(lhs_eval == 0) && !(rhs_eval == 0)) as i32),
-        Op::Or => Ok((!(lhs_eval == 0) || !(rhs_eval == 0)) as i32),
-        Op::Equal => Ok((lhs_eval == rhs_eval) as i32),
-        Op::NotEqual => Ok((lhs_eval != rhs_eval) as i32),
-        Op::LessThan => Ok((lhs_eval < rhs_eval) as i32),
-        Op::LessThanOrEqual => Ok((lhs_eval <= rhs_eval) as i32),
-        Op::GreaterThan => Ok((lhs_eval > rhs_eval) as i32),
-        Op::GreaterThanOrEqual => Ok((lhs_eval >= rhs_eval) as i32),
-        _ => Err(ErrorType::NotImplimented)
-    }
+    with_memory(memory, |interpreter| interpreter.eval_bin_op(op, lhs, rhs))
 }
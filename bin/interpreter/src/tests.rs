@@ -2,6 +2,8 @@ use crate::*;
 
 use ::language::*;
 
+use std::time::Duration;
+
 #[test]
 fn it_works() {
     let result = 2 + 2;
@@ -116,7 +118,8 @@ fn test_for_loop() {
                     Op::Add, 
                     Box::new(Expression::Var(String::from("x"))), 
                     Box::new(Expression::Val(1))
-                ) 
+                ),
+                loc: Location::default() 
             }
         ]
     };
@@ -125,18 +128,19 @@ fn test_for_loop() {
         statements: vec![
             Statement::AssignStatement { 
                 var: String::from("x"), 
-                exp: Expression::Val(0) 
+                exp: Expression::Val(0),
+                loc: Location::default() 
             },
             Statement::RepeatStatement { 
                 times: Expression::Val(10), 
-                body: loop_code
+                body: loop_code,
+                loc: Location::default()
             }
         ]
     };
 
-    match pub_run_block(&code, &mut test_memory,&mut output_vec) {
-        Err(e) => panic!("Error during interpret: {:?}", e),
-        Ok(()) => ()
+    if let Err(e) = pub_run_block(&code, &mut test_memory,&mut output_vec) {
+        panic!("Error during interpret: {:?}", e);
     }
 
     assert_eq!(pub_access_test("x", &mut test_memory), Ok(10))
@@ -159,7 +163,8 @@ fn test_if_stmt() {
                     statements:vec![
                         Statement::AssignStatement { 
                             var: String::from("result"), 
-                            exp: Expression::Val(0) 
+                            exp: Expression::Val(0),
+                            loc: Location::default() 
                         }
                     ]
                 }, 
@@ -173,7 +178,8 @@ fn test_if_stmt() {
                         statements:vec![
                             Statement::AssignStatement { 
                                 var: String::from("result"), 
-                                exp: Expression::Val(1) 
+                                exp: Expression::Val(1),
+                                loc: Location::default() 
                             }
                         ]
                     }),
@@ -186,7 +192,8 @@ fn test_if_stmt() {
                         statements:vec![
                         Statement::AssignStatement { 
                             var: String::from("result"), 
-                            exp: Expression::Val(2) 
+                            exp: Expression::Val(2),
+                            loc: Location::default() 
                         }
                     ]
                     })
@@ -195,19 +202,20 @@ fn test_if_stmt() {
                     statements:vec![
                         Statement::AssignStatement { 
                             var: String::from("result"), 
-                            exp: Expression::Val(4) 
+                            exp: Expression::Val(4),
+                            loc: Location::default() 
                         }
                     ]
                 }, 
+                loc: Location::default()
             }
         ]
     };
 
     // raw if
     pub_assign_test("x", 0, &mut test_memory);
-    match pub_run_block(&code, &mut test_memory, &mut output_vec) {
-        Err(e) => panic!("Error during interpret: {:?}", e),
-        Ok(()) => ()
+    if let Err(e) = pub_run_block(&code, &mut test_memory, &mut output_vec) {
+        panic!("Error during interpret: {:?}", e);
     }
     assert_eq!(pub_access_test("result", &mut test_memory), Ok(0));
     assert_ne!(pub_access_test("result", &mut test_memory), Ok(1));
@@ -216,29 +224,320 @@ fn test_if_stmt() {
     pub_assign_test("x", 1, &mut test_memory);
     pub_assign_test("x2", 2, &mut test_memory);
     // First and not second
-    match pub_run_block(&code, &mut test_memory, &mut output_vec) {
-        Err(e) => panic!("Error during interpret: {:?}", e),
-        Ok(()) => ()
+    if let Err(e) = pub_run_block(&code, &mut test_memory, &mut output_vec) {
+        panic!("Error during interpret: {:?}", e);
     }
     assert_eq!(pub_access_test("result", &mut test_memory), Ok(1));
 
     // Second
     pub_assign_test("x", 5, &mut test_memory);
-    match pub_run_block(&code, &mut test_memory, &mut output_vec) {
-        Err(e) => panic!("Error during interpret: {:?}", e),
-        Ok(()) => ()
+    if let Err(e) = pub_run_block(&code, &mut test_memory, &mut output_vec) {
+        panic!("Error during interpret: {:?}", e);
     }
     assert_eq!(pub_access_test("result", &mut test_memory), Ok(2));
 
     // Else
     pub_assign_test("x2", 5, &mut test_memory);
-    match pub_run_block(&code, &mut test_memory, &mut output_vec) {
-        Err(e) => panic!("Error during interpret: {:?}", e),
-        Ok(()) => ()
+    if let Err(e) = pub_run_block(&code, &mut test_memory, &mut output_vec) {
+        panic!("Error during interpret: {:?}", e);
     }
     assert_eq!(pub_access_test("x", &mut test_memory), Ok(5));
     assert_eq!(pub_access_test("x2", &mut test_memory), Ok(5));
     assert_eq!(pub_access_test("result", &mut test_memory), Ok(4));
 
 
-}
\ No newline at end of file
+}
+#[test]
+fn test_error_location() {
+    let program = Program{
+        program: Block{
+            statements: vec![
+                Statement::OutputStatement { 
+                    to_output: Expression::Val(1), 
+                    loc: Location::new(1, 1) 
+                },
+                Statement::RepeatStatement { 
+                    times: Expression::Val(2), 
+                    body: Block{
+                        statements: vec![
+                            Statement::OutputStatement { 
+                                to_output: Expression::Var(String::from("y")), 
+                                loc: Location::new(3, 5) 
+                            }
+                        ]
+                    },
+                    loc: Location::new(2, 1)
+                }
+            ]
+        },
+        tests: Vec::new()
+    };
+
+    let mut interpreter = Interpreter::new();
+    assert!(interpreter.run(&program).is_err());
+    assert_eq!(interpreter.error_location(), Some(Location::new(3, 5)));
+    // Output from before the error is kept
+    assert_eq!(interpreter.output_vec, vec![1]);
+
+    // Memory outlives the run so the program works once y exists
+    pub_assign_test("y", 7, &mut interpreter.memory);
+    interpreter.output_vec.clear();
+    assert_eq!(interpreter.run(&program), Ok(()));
+    assert_eq!(interpreter.output_vec, vec![1, 7, 7]);
+}
+
+fn endless_loop() -> Program {
+    // x = 0; REPEAT (2147483647) { x = x + 1; }
+    Program{
+        program: Block{
+            statements: vec![
+                Statement::AssignStatement { 
+                    var: String::from("x"), 
+                    exp: Expression::Val(0),
+                    loc: Location::new(1, 1) 
+                },
+                Statement::RepeatStatement { 
+                    times: Expression::Val(i32::MAX), 
+                    body: Block{
+                        statements: vec![
+                            Statement::AssignStatement { 
+                                var: String::from("x"), 
+                                exp: Expression::BinOp(
+                                    Op::Add, 
+                                    Box::new(Expression::Var(String::from("x"))), 
+                                    Box::new(Expression::Val(1))
+                                ),
+                                loc: Location::new(3, 5) 
+                            }
+                        ]
+                    },
+                    loc: Location::new(2, 1)
+                }
+            ]
+        },
+        tests: Vec::new()
+    }
+}
+
+#[test]
+fn test_fuel() {
+    let mut interpreter = Interpreter::new();
+    interpreter.budget.fuel = Some(1000);
+
+    assert_eq!(
+        interpreter.run(&endless_loop()),
+        Err(ErrorType::BudgetExhausted(Exhausted::Fuel, Location::new(3, 5)))
+    );
+    assert_eq!(interpreter.steps_used(), 1001);
+
+    // Setting up the loop costs 4 steps, then each time round costs the
+    // iteration, the statement and 3 expressions
+    assert_eq!(pub_access_test("x", &mut interpreter.memory), Ok(199));
+
+    // The fuel is per run
+    interpreter.budget.fuel = Some(2);
+    let short = Program{
+        program: Block{
+            statements: vec![
+                Statement::OutputStatement { 
+                    to_output: Expression::Val(1), 
+                    loc: Location::new(1, 1) 
+                }
+            ]
+        },
+        tests: Vec::new()
+    };
+    assert_eq!(interpreter.run(&short), Ok(()));
+    assert_eq!(interpreter.steps_used(), 2);
+}
+
+#[test]
+fn test_empty_loop_uses_fuel() {
+    let program = Program{
+        program: Block{
+            statements: vec![
+                Statement::RepeatStatement { 
+                    times: Expression::Val(i32::MAX), 
+                    body: Block{statements: vec![]},
+                    loc: Location::new(1, 1)
+                }
+            ]
+        },
+        tests: Vec::new()
+    };
+
+    let mut interpreter = Interpreter::new();
+    interpreter.budget.fuel = Some(100);
+    assert_eq!(
+        interpreter.run(&program),
+        Err(ErrorType::BudgetExhausted(Exhausted::Fuel, Location::new(1, 1)))
+    );
+}
+
+#[test]
+fn test_deadline_and_cancel() {
+    let mut interpreter = Interpreter::new();
+    interpreter.budget.deadline = Some(Instant::now() + Duration::from_millis(20));
+
+    match interpreter.run(&endless_loop()) {
+        Err(ErrorType::BudgetExhausted(Exhausted::Deadline, loc)) => assert!(loc.line == 2 || loc.line == 3),
+        other => panic!("Expected the deadline to pass, got {:?}", other)
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let mut interpreter = Interpreter::new();
+    interpreter.budget.cancel = Some(cancel.clone());
+
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        cancel.store(true, Ordering::Relaxed);
+    });
+
+    match interpreter.run(&endless_loop()) {
+        Err(ErrorType::BudgetExhausted(Exhausted::Cancelled, _)) => (),
+        other => panic!("Expected the run to be cancelled, got {:?}", other)
+    }
+    canceller.join().unwrap();
+
+    // A flag that is already set stops the run before anything happens
+    assert!(interpreter.run(&endless_loop()).is_err());
+    assert_eq!(interpreter.steps_used(), 1);
+}
+
+fn output_loop(times: i32, value: i32) -> Program {
+    Program{
+        program: Block{
+            statements: vec![
+                Statement::RepeatStatement { 
+                    times: Expression::Val(times), 
+                    body: Block{
+                        statements: vec![
+                            Statement::OutputStatement { 
+                                to_output: Expression::Val(value), 
+                                loc: Location::new(2, 5) 
+                            }
+                        ]
+                    },
+                    loc: Location::new(1, 1)
+                }
+            ]
+        },
+        tests: Vec::new()
+    }
+}
+
+#[test]
+fn test_output_limits() {
+    let mut interpreter = Interpreter::new();
+    interpreter.limits.max_outputs = Some(10);
+
+    assert_eq!(interpreter.run(&output_loop(1_000_000_000, 1)), Err(ErrorType::TooManyOutputs));
+    assert_eq!(interpreter.output_vec.len(), 10);
+    assert_eq!(interpreter.error_location(), Some(Location::new(2, 5)));
+
+    // Counted per run
+    interpreter.output_vec.clear();
+    assert_eq!(interpreter.run(&output_loop(10, 1)), Ok(()));
+
+    // "-123\n" is 5 bytes
+    let mut interpreter = Interpreter::new();
+    interpreter.limits.max_output_bytes = Some(12);
+    assert_eq!(interpreter.run(&output_loop(3, -123)), Err(ErrorType::OutputTooLarge));
+    assert_eq!(interpreter.output_vec, vec![-123, -123]);
+}
+
+#[test]
+fn test_variable_limit() {
+    let assign = |var: &str| Statement::AssignStatement { 
+        var: String::from(var), 
+        exp: Expression::Val(1),
+        loc: Location::default() 
+    };
+
+    let mut interpreter = Interpreter::new();
+    interpreter.limits.max_variables = Some(2);
+
+    let program = Program{program: Block{statements: vec![assign("a"), assign("b"), assign("a")]}, tests: Vec::new()};
+    assert_eq!(interpreter.run(&program), Ok(()));
+
+    // The limit covers variables left over from earlier runs
+    let program = Program{program: Block{statements: vec![assign("c")]}, tests: Vec::new()};
+    assert_eq!(interpreter.run(&program), Err(ErrorType::TooManyVariables));
+    assert!(pub_access_test("c", &mut interpreter.memory).is_err());
+}
+
+#[test]
+fn test_depth_limit() {
+    // Blocks nested `depth` deep including the program itself
+    fn nested(depth: usize) -> Block {
+        let mut block = Block{statements: vec![
+            Statement::OutputStatement { to_output: Expression::Val(1), loc: Location::default() }
+        ]};
+
+        for _ in 1..depth {
+            block = Block{statements: vec![
+                Statement::RepeatStatement { 
+                    times: Expression::Val(1), 
+                    body: block, 
+                    loc: Location::default() 
+                }
+            ]};
+        }
+
+        block
+    }
+
+    let mut interpreter = Interpreter::new();
+    interpreter.limits.max_depth = Some(5);
+
+    assert_eq!(interpreter.run(&Program{program: nested(5), tests: Vec::new()}), Ok(()));
+    assert_eq!(interpreter.run(&Program{program: nested(6), tests: Vec::new()}), Err(ErrorType::NestedTooDeep));
+    assert_eq!(interpreter.output_vec, vec![1]);
+}
+
+#[test]
+fn test_arithmetic_errors() {
+    let output = |exp: Expression| Program{program: Block{statements: vec![
+        Statement::OutputStatement { to_output: exp, loc: Location::new(1, 1) }
+    ]}, tests: Vec::new()};
+    let bin_op = |op, lhs, rhs| Expression::BinOp(op, Box::new(Expression::Val(lhs)), Box::new(Expression::Val(rhs)));
+
+    assert_eq!(interpret(output(bin_op(Op::Divide, 1, 0))), Err(ErrorType::ArithmeticError("division by zero")));
+    assert_eq!(interpret(output(bin_op(Op::Add, i32::MAX, 1))), Err(ErrorType::ArithmeticError("addition overflowed")));
+    assert_eq!(interpret(output(bin_op(Op::Remainder, i32::MIN, -1))), Err(ErrorType::ArithmeticError("remainder overflowed")));
+    assert_eq!(interpret(output(bin_op(Op::Multiply, -3, 7))), Ok(vec![-21]));
+}
+
+#[test]
+fn test_assertions_and_tests() {
+    let assert = |exp: Expression, expected: Option<Expression>, line| Statement::AssertStatement { exp, expected, loc: Location::new(line, 1) };
+    let assign = |var: &str, value| Statement::AssignStatement { var: String::from(var), exp: Expression::Val(value), loc: Location::new(1, 1) };
+    let var = |name: &str| Expression::Var(String::from(name));
+
+    let program = Program{
+        program: Block{statements: vec![assign("x", 3), assert(var("x"), None, 2), assert(var("x"), Some(Expression::Val(3)), 3)]},
+        tests: vec![
+            Test{name: String::from("passes"), body: Block{statements: vec![assign("y", 0), assert(var("y"), Some(Expression::Val(0)), 5)]}, loc: Location::new(4, 1)},
+            // Each test starts with nothing assigned
+            Test{name: String::from("fresh"), body: Block{statements: vec![assert(var("x"), None, 7)]}, loc: Location::new(6, 1)},
+            Test{name: String::from("differs"), body: Block{statements: vec![assign("x", 4), assert(var("x"), Some(Expression::Val(3)), 9)]}, loc: Location::new(8, 1)},
+            Test{name: String::from("zero"), body: Block{statements: vec![assert(Expression::Val(0), None, 11)]}, loc: Location::new(10, 1)}
+        ]
+    };
+
+    // A normal run leaves the tests out
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.run(&program), Ok(()));
+    assert_eq!(interpreter.memory.mem.len(), 1);
+
+    let results = run_tests(&program);
+    assert_eq!(results.iter().map(|r| (r.name.as_str(), r.passed())).collect::<Vec<_>>(), vec![
+        ("passes", true), ("fresh", false), ("differs", false), ("zero", false)
+    ]);
+    assert!(matches!(&results[1].error, Some((ErrorType::UninitialisedMemory(_), loc)) if *loc == Location::new(7, 1)));
+
+    let (error, loc) = results[2].error.as_ref().unwrap();
+    assert_eq!(*error, ErrorType::AssertionFailed{value: 4, expected: Some(3), loc: Location::new(9, 1)});
+    assert_eq!((error.to_string(), *loc), (String::from("assertion failed: 4 != 3"), Location::new(9, 1)));
+    assert_eq!(results[3].error.as_ref().unwrap().0.to_string(), "assertion failed");
+}
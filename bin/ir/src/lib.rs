@@ -0,0 +1,441 @@
+//! A three-address IR for programs, made of basic blocks joined into a
+//! control-flow graph. Each IF arm and each REPEAT gets its own blocks with
+//! explicit jumps between them, so passes and backends work on a flat list
+//! rather than walking nested `Block`s.
+//!
+//! Temporaries (`%n`) are given a value once and only used later in the same
+//! block, variables are read and written with `load` and `store`. A REPEAT
+//! counts down a hidden variable named `#n`, which can't clash with a
+//! program's own variables as those are letters only.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use language::*;
+
+pub type Temp = usize;
+pub type BlockId = usize;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    Const(i32),
+    Temp(Temp)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// Fails if `var` has not been assigned
+    Load { dest: Temp, var: String, loc: Location },
+    Store { var: String, src: Operand },
+    /// Fails if the arithmetic does
+    BinOp { dest: Temp, op: Op, lhs: Operand, rhs: Operand, loc: Location },
+    Output { src: Operand },
+    /// Fails if `count` is negative
+    CheckRepeat { count: Operand, loc: Location },
+    /// Fails if `value` is 0 or, when there is one, isn't `expected`
+    Assert { value: Operand, expected: Option<Operand>, loc: Location }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Goes to `then` if `cond` is not 0, otherwise to `otherwise`
+    Branch { cond: Operand, then: BlockId, otherwise: BlockId },
+    Return
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub instrs: Vec<Instr>,
+    pub terminator: Terminator
+}
+
+/// A whole program, starting at block 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub blocks: Vec<BasicBlock>,
+    /// How many temporaries are used, they are numbered from 0
+    pub temps: usize
+}
+
+impl Instr {
+    /// The temporary the instruction gives a value to
+    pub fn dest(&self) -> Option<Temp> {
+        match self {
+            Instr::Load { dest, .. } | Instr::BinOp { dest, .. } => Some(*dest),
+            _ => None
+        }
+    }
+
+    /// The operands the instruction uses
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Instr::Load { .. } => vec![],
+            Instr::Store { src, .. } | Instr::Output { src } => vec![*src],
+            Instr::BinOp { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Instr::CheckRepeat { count, .. } => vec![*count],
+            Instr::Assert { value, expected, .. } => std::iter::once(*value).chain(*expected).collect()
+        }
+    }
+}
+
+impl Terminator {
+    /// Blocks control can go to next
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then, otherwise, .. } => vec![*then, *otherwise],
+            Terminator::Return => vec![]
+        }
+    }
+}
+
+fn op_name(op: Op) -> &'static str {
+    match op {
+        Op::Add => "add",
+        Op::Sub => "sub",
+        Op::Multiply => "mul",
+        Op::Divide => "div",
+        Op::Remainder => "rem",
+        Op::And => "and",
+        Op::Or => "or",
+        Op::Equal => "eq",
+        Op::NotEqual => "ne",
+        Op::LessThanOrEqual => "le",
+        Op::LessThan => "lt",
+        Op::GreaterThanOrEqual => "ge",
+        Op::GreaterThan => "gt"
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Const(value) => write!(f, "{}", value),
+            Operand::Temp(temp) => write!(f, "%{}", temp)
+        }
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instr::Load { dest, var, loc } => write!(f, "%{} = load {}  @{}", dest, var, loc),
+            Instr::Store { var, src } => write!(f, "store {}, {}", var, src),
+            Instr::BinOp { dest, op, lhs, rhs, loc } => write!(f, "%{} = {} {}, {}  @{}", dest, op_name(*op), lhs, rhs, loc),
+            Instr::Output { src } => write!(f, "output {}", src),
+            Instr::CheckRepeat { count, loc } => write!(f, "check_repeat {}  @{}", count, loc),
+            Instr::Assert { value, expected: None, loc } => write!(f, "assert {}  @{}", value, loc),
+            Instr::Assert { value, expected: Some(expected), loc } => write!(f, "assert_eq {}, {}  @{}", value, expected, loc)
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump b{}", target),
+            Terminator::Branch { cond, then, otherwise } => write!(f, "branch {}, b{}, b{}", cond, then, otherwise),
+            Terminator::Return => write!(f, "return")
+        }
+    }
+}
+
+/// One block after another, each instruction on its own line. Instructions
+/// that can fail end with the location of the statement they came from
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", id)?;
+            for instr in block.instrs.iter() {
+                writeln!(f, "  {}", instr)?;
+            }
+            writeln!(f, "  {}", block.terminator)?;
+        }
+
+        return Ok(());
+    }
+}
+
+struct Lowerer {
+    blocks: Vec<BasicBlock>,
+    current: BlockId,
+    temps: usize,
+    counters: usize
+}
+
+impl Lowerer {
+    /// A new empty block ending in a return until it is given a terminator
+    fn block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock{instrs: Vec::new(), terminator: Terminator::Return});
+        self.blocks.len() - 1
+    }
+
+    fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        self.temps - 1
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.blocks[self.current].instrs.push(instr);
+    }
+
+    /// Ends the current block and carries on in `next`
+    fn finish(&mut self, terminator: Terminator, next: BlockId) {
+        self.blocks[self.current].terminator = terminator;
+        self.current = next;
+    }
+
+    fn exp(&mut self, exp: &Expression, loc: Location) -> Operand {
+        match exp {
+            Expression::Val(value) => Operand::Const(*value),
+            Expression::Var(var) => {
+                let dest = self.temp();
+                self.emit(Instr::Load{dest, var: var.clone(), loc});
+                Operand::Temp(dest)
+            },
+            Expression::BinOp(op, lhs, rhs) => {
+                let lhs = self.exp(lhs, loc);
+                let rhs = self.exp(rhs, loc);
+                let dest = self.temp();
+                self.emit(Instr::BinOp{dest, op: *op, lhs, rhs, loc});
+                Operand::Temp(dest)
+            }
+        }
+    }
+
+    fn statements(&mut self, block: &Block) {
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        let loc = stmt.location();
+
+        match stmt {
+            Statement::AssignStatement { var, exp, .. } => {
+                let src = self.exp(exp, loc);
+                self.emit(Instr::Store{var: var.clone(), src});
+            },
+            Statement::OutputStatement { to_output, .. } => {
+                let src = self.exp(to_output, loc);
+                self.emit(Instr::Output{src});
+            },
+            Statement::AssertStatement { exp, expected, .. } => {
+                let value = self.exp(exp, loc);
+                let expected = expected.as_ref().map(|expected| self.exp(expected, loc));
+                self.emit(Instr::Assert{value, expected, loc});
+            },
+            Statement::RepeatStatement { times, body, .. } => {
+                self.counters += 1;
+                let counter = format!("#{}", self.counters);
+
+                let count = self.exp(times, loc);
+                self.emit(Instr::CheckRepeat{count, loc});
+                self.emit(Instr::Store{var: counter.clone(), src: count});
+
+                let header = self.block();
+                self.finish(Terminator::Jump(header), header);
+
+                let left = self.temp();
+                self.emit(Instr::Load{dest: left, var: counter.clone(), loc});
+                let more = self.temp();
+                self.emit(Instr::BinOp{dest: more, op: Op::GreaterThan, lhs: Operand::Temp(left), rhs: Operand::Const(0), loc});
+                let body_block = self.block();
+                // Where to go once the count runs out is filled in after the body
+                self.finish(Terminator::Branch{cond: Operand::Temp(more), then: body_block, otherwise: body_block}, body_block);
+
+                self.statements(body);
+                let left = self.temp();
+                self.emit(Instr::Load{dest: left, var: counter.clone(), loc});
+                let next = self.temp();
+                self.emit(Instr::BinOp{dest: next, op: Op::Sub, lhs: Operand::Temp(left), rhs: Operand::Const(1), loc});
+                self.emit(Instr::Store{var: counter, src: Operand::Temp(next)});
+
+                let exit = self.block();
+                if let Terminator::Branch { otherwise, .. } = &mut self.blocks[header].terminator {
+                    *otherwise = exit;
+                }
+                self.finish(Terminator::Jump(header), exit);
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                let arms = std::iter::once((condition, body)).chain(else_if.iter().map(|(exp, block)| (exp, block)));
+                // Blocks that finish an arm, they jump to the end once it exists
+                let mut finished = Vec::new();
+
+                for (condition, block) in arms {
+                    let cond = self.exp(condition, loc);
+                    let then = self.block();
+                    let test = self.current;
+                    self.finish(Terminator::Branch{cond, then, otherwise: then}, then);
+                    self.statements(block);
+                    finished.push(self.current);
+
+                    let otherwise = self.block();
+                    if let Terminator::Branch { otherwise: target, .. } = &mut self.blocks[test].terminator {
+                        *target = otherwise;
+                    }
+                    self.current = otherwise;
+                }
+
+                // The block for the last condition being false is the ELSE
+                self.statements(else_body);
+                let end = self.block();
+                self.finish(Terminator::Jump(end), end);
+                for block in finished {
+                    self.blocks[block].terminator = Terminator::Jump(end);
+                }
+            }
+        }
+    }
+}
+
+/// Turns `program` into blocks
+pub fn lower(program: &Program) -> Function {
+    let mut lowerer = Lowerer{blocks: Vec::new(), current: 0, temps: 0, counters: 0};
+    lowerer.block();
+    lowerer.statements(&program.program);
+
+    return Function{blocks: lowerer.blocks, temps: lowerer.temps};
+}
+
+/// Something wrong with a `Function`, in block `block`
+#[derive(Debug, PartialEq)]
+pub struct VerifyError {
+    pub block: BlockId,
+    pub message: String
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b{}: {}", self.block, self.message)
+    }
+}
+
+/// Checks everything the rest of the crate relies on: there is an entry
+/// block, jumps go to blocks that exist, and every temporary is in range,
+/// defined once and only used after its definition in the same block
+pub fn verify(function: &Function) -> Result<(), VerifyError> {
+    if function.blocks.is_empty() {
+        return Err(VerifyError{block: 0, message: "there is no entry block".to_string()});
+    }
+
+    let mut defined_in: Vec<Option<BlockId>> = vec![None; function.temps];
+
+    for (id, block) in function.blocks.iter().enumerate() {
+        let error = |message: String| VerifyError{block: id, message};
+        let check_use = |operand: Operand, defined_in: &Vec<Option<BlockId>>| {
+            if let Operand::Temp(temp) = operand {
+                if defined_in.get(temp).copied().flatten() != Some(id) {
+                    return Err(error(format!("%{} is used before it is defined in the block", temp)));
+                }
+            }
+            return Ok(());
+        };
+
+        for instr in block.instrs.iter() {
+            for operand in instr.operands() {
+                check_use(operand, &defined_in)?;
+            }
+
+            if let Some(dest) = instr.dest() {
+                match defined_in.get(dest) {
+                    None => return Err(error(format!("%{} is past the {} temporaries", dest, function.temps))),
+                    Some(Some(_)) => return Err(error(format!("%{} is defined twice", dest))),
+                    Some(None) => defined_in[dest] = Some(id)
+                }
+            }
+        }
+
+        if let Terminator::Branch { cond, .. } = block.terminator {
+            check_use(cond, &defined_in)?;
+        }
+        for target in block.terminator.successors() {
+            if target >= function.blocks.len() {
+                return Err(error(format!("jumps to b{} which doesn't exist", target)));
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// A runtime error, at the location of the statement that failed
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub loc: Location,
+    pub message: String,
+    /// What was output before it failed
+    pub outputs: Vec<i32>
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.loc, self.message)
+    }
+}
+
+/// Runs `function`, returning what it outputs the same way
+/// `interpreter::interpret` does. Arithmetic that the interpreter stops on
+/// is an error here too
+pub fn run(function: &Function) -> Result<Vec<i32>, RuntimeError> {
+    let mut outputs = Vec::new();
+    match execute(function, &mut outputs) {
+        Ok(()) => return Ok(outputs),
+        Err((loc, message)) => return Err(RuntimeError{loc, message, outputs})
+    }
+}
+
+/// Runs `function`, giving where and why it failed if it did
+fn execute(function: &Function, outputs: &mut Vec<i32>) -> Result<(), (Location, String)> {
+    let mut vars: HashMap<&str, i32> = HashMap::new();
+    let mut temps = vec![0; function.temps];
+    let mut current = 0;
+
+    loop {
+        let block = &function.blocks[current];
+        let value = |operand: Operand, temps: &Vec<i32>| match operand {
+            Operand::Const(value) => value,
+            Operand::Temp(temp) => temps[temp]
+        };
+
+        for instr in block.instrs.iter() {
+            match instr {
+                Instr::Load { dest, var, loc } => match vars.get(var.as_str()) {
+                    Some(v) => temps[*dest] = *v,
+                    None => return Err((*loc, format!("Variable {:?} has not been assigned", var)))
+                },
+                Instr::Store { var, src } => { vars.insert(var, value(*src, &temps)); },
+                Instr::BinOp { dest, op, lhs, rhs, loc } => {
+                    temps[*dest] = op.apply(value(*lhs, &temps), value(*rhs, &temps))
+                        .map_err(|message| (*loc, message.to_string()))?;
+                },
+                Instr::Output { src } => outputs.push(value(*src, &temps)),
+                Instr::CheckRepeat { count, loc } => {
+                    if value(*count, &temps) < 0 {
+                        return Err((*loc, "REPEAT count is negative".to_string()));
+                    }
+                },
+                Instr::Assert { value: v, expected, loc } => {
+                    let v = value(*v, &temps);
+                    let message = match expected.map(|e| value(e, &temps)) {
+                        Some(expected) if v != expected => format!("assertion failed: {} != {}", v, expected),
+                        None if v == 0 => "assertion failed".to_string(),
+                        _ => continue
+                    };
+                    return Err((*loc, message));
+                }
+            }
+        }
+
+        current = match block.terminator {
+            Terminator::Jump(target) => target,
+            Terminator::Branch { cond, then, otherwise } => if value(cond, &temps) != 0 { then } else { otherwise },
+            Terminator::Return => return Ok(())
+        };
+    }
+}
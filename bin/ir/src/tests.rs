@@ -0,0 +1,114 @@
+use crate::*;
+
+const CONTROL_FLOW: &str = "n = 3;
+REPEAT (n) {
+    n = n + 1;
+    IF (n == 4) {
+        late = 1;
+        OUTPUT 40;
+    } ELSEIF (n == 5) {
+        OUTPUT late;
+    } ELSEIF (n == 6 && 1) {
+        OUTPUT 60;
+    } ELSE {
+        OUTPUT 0;
+    }
+    REPEAT (n - 4) {
+        OUTPUT 1;
+    }
+}
+IF (n > 2) {
+    OUTPUT 2;
+}
+OUTPUT 7 - 9 * 2 >= 0 - 11 || 0;";
+
+fn lower_source(source: &str) -> Function {
+    let function = lower(&parser::parse_source(source).unwrap());
+    verify(&function).unwrap_or_else(|e| panic!("{}\n{}", e, function));
+    function
+}
+
+#[test]
+fn dump() {
+    let function = lower_source("x = 2;\nREPEAT (x) {\n    IF (x < 3) {\n        OUTPUT x * 2;\n    } ELSE {\n        x = 1;\n    }\n}");
+
+    assert_eq!(function.to_string(), "b0:
+  store x, 2
+  %0 = load x  @2:1
+  check_repeat %0  @2:1
+  store #1, %0
+  jump b1
+b1:
+  %1 = load #1  @2:1
+  %2 = gt %1, 0  @2:1
+  branch %2, b2, b6
+b2:
+  %3 = load x  @3:5
+  %4 = lt %3, 3  @3:5
+  branch %4, b3, b4
+b3:
+  %5 = load x  @4:9
+  %6 = mul %5, 2  @4:9
+  output %6
+  jump b5
+b4:
+  store x, 1
+  jump b5
+b5:
+  %7 = load #1  @2:1
+  %8 = sub %7, 1  @2:1
+  store #1, %8
+  jump b1
+b6:
+  return
+");
+}
+
+#[test]
+fn verifier_catches_broken_functions() {
+    let mut function = lower_source("x = 1;\nIF (x) {\n    OUTPUT x + 1;\n}");
+
+    let mut broken = function.clone();
+    broken.blocks[1].terminator = Terminator::Jump(40);
+    assert_eq!(verify(&broken), Err(VerifyError{block: 1, message: "jumps to b40 which doesn't exist".to_string()}));
+
+    // %0 is only defined in b0
+    let mut broken = function.clone();
+    broken.blocks[1].instrs.push(Instr::Output{src: Operand::Temp(0)});
+    assert_eq!(verify(&broken).unwrap_err().message, "%0 is used before it is defined in the block");
+
+    let mut broken = function.clone();
+    let load = broken.blocks[0].instrs[1].clone();
+    broken.blocks[1].instrs.insert(0, load);
+    assert_eq!(verify(&broken).unwrap_err().message, "%0 is defined twice");
+
+    function.temps = 1;
+    assert_eq!(verify(&function).unwrap_err().message, "%1 is past the 1 temporaries");
+}
+
+#[test]
+fn runs_like_the_interpreter() {
+    for source in [include_str!("../../../examples/fibbonachi.xa"), include_str!("../../../examples/moreops.xa"), CONTROL_FLOW] {
+        let expected = interpreter::interpret(parser::parse_source(source).unwrap()).unwrap();
+        assert_eq!(run(&lower_source(source)), Ok(expected));
+    }
+}
+
+#[test]
+fn runtime_errors() {
+    for source in ["OUTPUT 1;\nx = 0 - 2;\nREPEAT (x) {\n    OUTPUT 2;\n}", "IF (0) {\n    x = 1;\n} ELSEIF (x) {\n}", "x = 2;\nASSERT (x > 1);\nASSERT_EQ (x * 2, 4);\nOUTPUT x;\nASSERT_EQ (x, 3);", "ASSERT (1 < 0);"] {
+        let program = parser::parse_source(source).unwrap();
+        let mut interpreter = interpreter::Interpreter::new();
+        let error = interpreter.run(&program).unwrap_err();
+
+        let expected = format!("{}: {}", interpreter.error_location().unwrap(), error);
+        let error = run(&lower(&program)).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+        assert_eq!(error.outputs, interpreter.output_vec, "{}", source);
+    }
+
+    let error = run(&lower_source("x = 0;\nOUTPUT 5;\nOUTPUT 1 / x;")).unwrap_err();
+    assert_eq!(error.to_string(), "3:1: division by zero");
+    assert_eq!(error.outputs, vec![5]);
+    assert_eq!(run(&lower_source("x = 2147483647;\nOUTPUT x + 1;")).unwrap_err().to_string(), "2:1: addition overflowed");
+}
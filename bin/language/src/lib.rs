@@ -1,37 +1,216 @@
-#[derive(Debug, PartialEq)]
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Program{
-    pub program: Block
+    pub program: Block,
+    /// `TEST` blocks, in order. A normal run leaves them out
+    pub tests: Vec<Test>
 }
 
 impl Program {
     pub fn new() -> Self {
-        Self{program: Block{statements: Vec::new()}}
+        Self{program: Block{statements: Vec::new()}, tests: Vec::new()}
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A `TEST "name" { ... }` block, run on its own by the test mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct Test {
+    pub name: String,
+    pub body: Block,
+    pub loc: Location
+}
+
+/// Which source file something is in. The file a program is parsed from is
+/// 0 and the files it imports are numbered in the order they are read
+pub type FileId = u32;
+
+/// Where something starts in the source, both line and column count from 1
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub file: FileId,
+    pub line: u32,
+    pub column: u32
+}
+
+impl Location {
+    /// A place in the main file
+    pub fn new(line: u32, column: u32) -> Self {
+        Self{file: 0, line, column}
+    }
+
+    /// The same place, in `file`
+    pub fn in_file(self, file: FileId) -> Self {
+        Self{file, ..self}
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The text of the files a program was read from, to show what is at a
+/// location
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    /// The name and lines of each file, imports that weren't added have none
+    files: Vec<Option<(String, Vec<String>)>>
+}
+
+impl Sources {
+    /// Just the main file, whose locations are shown without a name
+    pub fn new(source: &str) -> Self {
+        Self{files: vec![Some((String::new(), source.lines().map(String::from).collect()))]}
+    }
+
+    /// Adds the imported `file`, shown as `name`
+    pub fn add(&mut self, file: FileId, name: &str, source: &str) {
+        if self.files.len() <= file as usize {
+            self.files.resize(file as usize + 1, None);
+        }
+        self.files[file as usize] = Some((name.to_string(), source.lines().map(String::from).collect()));
+    }
+
+    /// The line `loc` is on without the space around it, empty if it isn't
+    /// known
+    pub fn line(&self, loc: Location) -> &str {
+        let Some(Some((_, lines))) = self.files.get(loc.file as usize) else {
+            return "";
+        };
+        return (loc.line as usize).checked_sub(1).and_then(|i| lines.get(i)).map_or("", |l| l.trim());
+    }
+
+    /// `loc` with the file it is in, if that isn't the main file
+    pub fn locate(&self, loc: Location) -> String {
+        if loc.file == 0 {
+            return loc.to_string();
+        }
+        match self.files.get(loc.file as usize) {
+            Some(Some((name, _))) => return format!("{}:{}", name, loc),
+            _ => return format!("file {}, {}", loc.file, loc)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Block{
     pub statements: Vec<Statement>
 }
 
-#[derive(Debug, PartialEq)]
+impl Block {
+    /// Every variable whose value is used somewhere in the block, including
+    /// nested blocks
+    pub fn read_variables(&self) -> BTreeSet<String> {
+        let mut vars = BTreeSet::new();
+        self.add_read_variables(&mut vars);
+        return vars;
+    }
+
+    /// Every variable assigned somewhere in the block, including nested
+    /// blocks
+    pub fn assigned_variables(&self) -> BTreeSet<String> {
+        let mut vars = BTreeSet::new();
+        self.add_assigned_variables(&mut vars);
+        return vars;
+    }
+
+    fn add_assigned_variables(&self, vars: &mut BTreeSet<String>) {
+        for stmt in self.statements.iter() {
+            match stmt {
+                Statement::AssignStatement { var, .. } => { vars.insert(var.clone()); },
+                Statement::OutputStatement { .. } |
+                Statement::AssertStatement { .. } => (),
+                Statement::RepeatStatement { body, .. } => body.add_assigned_variables(vars),
+                Statement::IfStatement { body, else_if, else_body, .. } => {
+                    body.add_assigned_variables(vars);
+                    for (_, block) in else_if {
+                        block.add_assigned_variables(vars);
+                    }
+                    else_body.add_assigned_variables(vars);
+                }
+            }
+        }
+    }
+
+    fn add_read_variables(&self, vars: &mut BTreeSet<String>) {
+        for stmt in self.statements.iter() {
+            match stmt {
+                Statement::AssignStatement { exp, .. } => exp.add_variables(vars),
+                Statement::OutputStatement { to_output, .. } => to_output.add_variables(vars),
+                Statement::AssertStatement { exp, expected, .. } => {
+                    exp.add_variables(vars);
+                    if let Some(expected) = expected {
+                        expected.add_variables(vars);
+                    }
+                },
+                Statement::RepeatStatement { times, body, .. } => {
+                    times.add_variables(vars);
+                    body.add_read_variables(vars);
+                },
+                Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                    condition.add_variables(vars);
+                    body.add_read_variables(vars);
+                    for (exp, block) in else_if {
+                        exp.add_variables(vars);
+                        block.add_read_variables(vars);
+                    }
+                    else_body.add_read_variables(vars);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     AssignStatement{
         var: String, 
-        exp: Expression
+        exp: Expression,
+        loc: Location
     },
     IfStatement{
         condition: Expression,
         body: Block,
         else_if: Vec<(Expression,Block)>,
-        else_body: Block
+        else_body: Block,
+        loc: Location
     },
     RepeatStatement{
         times: Expression,
-        body: Block
+        body: Block,
+        loc: Location
     },
     OutputStatement{
-        to_output: Expression
+        to_output: Expression,
+        loc: Location
+    },
+    /// `ASSERT (exp);` fails unless `exp` is non-zero, `ASSERT_EQ (exp,
+    /// expected);` unless the two are equal
+    AssertStatement{
+        exp: Expression,
+        expected: Option<Expression>,
+        loc: Location
+    }
+}
+
+impl Statement {
+    /// Location of the first token of the statement
+    pub fn location(&self) -> Location {
+        match self {
+            Statement::AssignStatement { loc, .. } |
+            Statement::IfStatement { loc, .. } |
+            Statement::RepeatStatement { loc, .. } |
+            Statement::OutputStatement { loc, .. } |
+            Statement::AssertStatement { loc, .. } => *loc
+        }
     }
 }
 
@@ -42,6 +221,19 @@ pub enum Expression {
     BinOp(Op, Box<Expression>, Box<Expression>)
 }
 
+impl Expression {
+    fn add_variables(&self, vars: &mut BTreeSet<String>) {
+        match self {
+            Expression::Val(_) => (),
+            Expression::Var(v) => { vars.insert(v.clone()); },
+            Expression::BinOp(_, lhs, rhs) => {
+                lhs.add_variables(vars);
+                rhs.add_variables(vars);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Op {
     Add,
@@ -58,3 +250,29 @@ pub enum Op {
     GreaterThanOrEqual,
     GreaterThan
 }
+
+impl Op {
+    /// Works out `lhs op rhs`, or says why it can't be done. The messages are
+    /// the ones the compiled backends stop with
+    pub fn apply(self, lhs: i32, rhs: i32) -> Result<i32, &'static str> {
+        let value = match self {
+            Op::Add => lhs.checked_add(rhs).ok_or("addition overflowed")?,
+            Op::Sub => lhs.checked_sub(rhs).ok_or("subtraction overflowed")?,
+            Op::Multiply => lhs.checked_mul(rhs).ok_or("multiplication overflowed")?,
+            Op::Divide if rhs == 0 => return Err("division by zero"),
+            Op::Divide => lhs.checked_div(rhs).ok_or("division overflowed")?,
+            Op::Remainder if rhs == 0 => return Err("remainder by zero"),
+            Op::Remainder => lhs.checked_rem(rhs).ok_or("remainder overflowed")?,
+            Op::And => (lhs != 0 && rhs != 0) as i32,
+            Op::Or => (lhs != 0 || rhs != 0) as i32,
+            Op::Equal => (lhs == rhs) as i32,
+            Op::NotEqual => (lhs != rhs) as i32,
+            Op::LessThanOrEqual => (lhs <= rhs) as i32,
+            Op::LessThan => (lhs < rhs) as i32,
+            Op::GreaterThanOrEqual => (lhs >= rhs) as i32,
+            Op::GreaterThan => (lhs > rhs) as i32
+        };
+
+        return Ok(value);
+    }
+}
@@ -6,35 +6,85 @@
 #include "lexer.h"
 
 struct context {
+    // Exactly one of file_pointer and buffer is set
     FILE *file_pointer;
-    char last_char;
-    char next_char;
+    char *buffer;
+    size_t buffer_len;
+    size_t buffer_pos;
+
+    int last_char;
+    int line;
+    int column;
 };
 
-struct token get_token(void *v_con) {
+static int read_char(struct context *con) {
+    if (con->file_pointer != 0) {
+        return getc(con->file_pointer);
+    }
+
+    if (con->buffer_pos >= con->buffer_len) {
+        return EOF;
+    }
+
+    return (unsigned char) con->buffer[con->buffer_pos++];
+}
+
+// Moves onto the next character, keeping track of where it is in the source
+static void advance(struct context *con) {
+    if (con->last_char == '\n') {
+        con->line++;
+        con->column = 0;
+    }
+
+    con->last_char = read_char(con);
+    con->column++;
+}
+
+struct token get_token(struct context *con) {
     struct token tok;
-    struct context *con = (struct context *) v_con;
 
-    while (isspace(con->last_char)) {
-        con->last_char = getc(con->file_pointer);
+    // Skip space and comments, a comment runs to the end of the line
+    for (;;) {
+        while (isspace(con->last_char)) {
+            advance(con);
+        }
+
+        tok.val[0] = '\0';
+        tok.line = con->line;
+        tok.column = con->column;
+
+        if (con->last_char != '/') {
+            break;
+        }
+
+        advance(con); // Consume the /
+        if (con->last_char != '/') {
+            tok.tok_type = DIV;
+            return tok;
+        }
+
+        while (con->last_char != '\n' && con->last_char != EOF) {
+            advance(con);
+        }
     }
 
     if (isalpha(con->last_char)) {
         char buffer[21];
         int buffer_pointer = 0;
 
-        while (isalpha(con->last_char) && buffer_pointer < 20) {
+        // Underscores are only allowed so ASSERT_EQ can be read
+        while ((isalpha(con->last_char) || con->last_char == '_') && buffer_pointer < 20) {
             buffer[buffer_pointer] = con->last_char;
             buffer_pointer++;
 
-            con->last_char = getc(con->file_pointer);
+            advance(con);
         }
 
         buffer[buffer_pointer] = '\0';
 
-        while (isalpha(con->last_char)) {
-            printf("WARNING, variable name longer than 20 characters\n");
-            con->last_char = getc(con->file_pointer);
+        while (isalpha(con->last_char) || con->last_char == '_') {
+            fprintf(stderr, "WARNING, variable name longer than 20 characters\n");
+            advance(con);
         }
 
         if (strcmp(buffer, "IF") == 0) {
@@ -52,6 +102,24 @@ struct token get_token(void *v_con) {
         } else if (strcmp(buffer, "OUTPUT") == 0) {
             tok.tok_type = OUTPUT;
             return tok;
+        } else if (strcmp(buffer, "ASSERT") == 0) {
+            tok.tok_type = ASSERT;
+            return tok;
+        } else if (strcmp(buffer, "ASSERT_EQ") == 0) {
+            tok.tok_type = ASSERT_EQ;
+            return tok;
+        } else if (strcmp(buffer, "TEST") == 0) {
+            tok.tok_type = TEST;
+            return tok;
+        } else if (strcmp(buffer, "IMPORT") == 0) {
+            tok.tok_type = IMPORT;
+            return tok;
+        } else if (strcmp(buffer, "EXPOSING") == 0) {
+            tok.tok_type = EXPOSING;
+            return tok;
+        } else if (strchr(buffer, '_') != 0) {
+            tok.tok_type = INVALID;
+            return tok;
         } else {
             tok.tok_type = VAR;
             strcpy(tok.val, buffer);
@@ -67,14 +135,14 @@ struct token get_token(void *v_con) {
             buffer[buffer_pointer] = con->last_char;
             buffer_pointer++;
 
-            con->last_char = getc(con->file_pointer);
+            advance(con);
         }
 
         buffer[buffer_pointer] = '\0';
 
         while (isalpha(con->last_char)) {
-            printf("WARNING, digit longer than 20 characters\n");
-            con->last_char = getc(con->file_pointer);
+            fprintf(stderr, "WARNING, digit longer than 20 characters\n");
+            advance(con);
         }
 
         tok.tok_type = INT_LIT;
@@ -83,139 +151,171 @@ struct token get_token(void *v_con) {
     }
     
     if (con->last_char == '=') {
-        con->next_char = getc(con->file_pointer);
-        if (con->next_char == '=') {
-            con->last_char = getc(con->file_pointer); // Consume the second = in ==
+        advance(con);
+        if (con->last_char == '=') {
+            advance(con); // Consume the second = in ==
             tok.tok_type = EQ;
             return tok;
         } else {
-            con->last_char = con->next_char;
             tok.tok_type = ASSIGN;
             return tok;
         }
     }
 
     if (con->last_char == '!') {
-        con->next_char = getc(con->file_pointer);
-        if (con->next_char == '=') {
-            con->last_char = getc(con->file_pointer); // Consume the second = in !=
+        advance(con);
+        if (con->last_char == '=') {
+            advance(con); // Consume the second = in !=
             tok.tok_type = NE;
             return tok;
         } else {
-            con->last_char = con->next_char;
             tok.tok_type = INVALID; // Soon to be NOT
             return tok;
         }
     }
 
     if (con->last_char == '&') {
-        con->next_char = getc(con->file_pointer);
-        if (con->next_char == '&') {
-            con->last_char = getc(con->file_pointer); // Consume the second & in &&
+        advance(con);
+        if (con->last_char == '&') {
+            advance(con); // Consume the second & in &&
             tok.tok_type = AND;
             return tok;
         } else {
-            con->last_char = con->next_char;
             tok.tok_type = INVALID;
             return tok;
         }
     }
 
     if (con->last_char == '|') {
-        con->next_char = getc(con->file_pointer);
-        if (con->next_char == '|') {
-            con->last_char = getc(con->file_pointer); // Consume the second | in ||
+        advance(con);
+        if (con->last_char == '|') {
+            advance(con); // Consume the second | in ||
             tok.tok_type = OR;
             return tok;
         } else {
-            con->last_char = con->next_char;
             tok.tok_type = INVALID;
             return tok;
         }
     }
 
     if (con->last_char == '<') {
-        con->next_char = getc(con->file_pointer);
-        if (con->next_char == '=') {
-            con->last_char = getc(con->file_pointer);
+        advance(con);
+        if (con->last_char == '=') {
+            advance(con);
             tok.tok_type = LE;
             return tok;
         } else {
-            con->last_char = con->next_char;
             tok.tok_type = LT;
             return tok;
         }
     }
 
     if (con->last_char == '>') {
-        con->next_char = getc(con->file_pointer);
-        if (con->next_char == '=') {
-            con->last_char = getc(con->file_pointer);
+        advance(con);
+        if (con->last_char == '=') {
+            advance(con);
             tok.tok_type = GE;
             return tok;
         } else {
-            con->last_char = con->next_char;
             tok.tok_type = GT;
             return tok;
         }
     }
 
     if (con->last_char == '+') {
-        con->last_char = getc(con->file_pointer); // Consume the +
+        advance(con); // Consume the +
         tok.tok_type = PLUS;
         return tok;
     }
 
     if (con->last_char == '-') {
-        con->last_char = getc(con->file_pointer); // Consume the -
+        advance(con); // Consume the -
         tok.tok_type = MINUS;
         return tok;
     }
 
     if (con->last_char == '*') {
-        con->last_char = getc(con->file_pointer); // Consume the *
+        advance(con); // Consume the *
         tok.tok_type = ASTERIX;
         return tok;
     }
 
-    if (con->last_char == '/') {
-        con->last_char = getc(con->file_pointer); // Consume the /
-        tok.tok_type = DIV;
-        return tok;
-    }
-
     if (con->last_char == '%') {
-        con->last_char = getc(con->file_pointer); // Consume the /
+        advance(con); // Consume the /
         tok.tok_type = MOD;
         return tok;
     }
 
     if (con->last_char == '(') {
-        con->last_char = getc(con->file_pointer); // Consume the (
+        advance(con); // Consume the (
         tok.tok_type = LPAR;
         return tok;
     }
 
     if (con->last_char == ')') {
-        con->last_char = getc(con->file_pointer); // Consume the )
+        advance(con); // Consume the )
         tok.tok_type = RPAR;
         return tok;
     }
 
     if (con->last_char == '{') {
-        con->last_char = getc(con->file_pointer); // Consume the {
+        advance(con); // Consume the {
         tok.tok_type = LBRA;
         return tok;
     }
 
     if (con->last_char == '}') {
-        con->last_char = getc(con->file_pointer); // Consume the }
+        advance(con); // Consume the }
         tok.tok_type = RBRA;
         return tok;
     }
 
+    if (con->last_char == ',') {
+        advance(con); // Consume the ,
+        tok.tok_type = COMMA;
+        return tok;
+    }
+
+    if (con->last_char == '.') {
+        advance(con); // Consume the .
+        tok.tok_type = DOT;
+        return tok;
+    }
+
+    if (con->last_char == '"') {
+        int length = 0;
+        advance(con); // Consume the opening "
+
+        while (con->last_char != '"' && con->last_char != '\n' && con->last_char != EOF) {
+            if (length < TOKEN_VAL_SIZE - 1) {
+                tok.val[length] = con->last_char;
+            }
+            length++;
+            advance(con);
+        }
+
+        // Strings have to end on the line they start
+        if (con->last_char != '"') {
+            tok.val[0] = '\0';
+            tok.tok_type = INVALID;
+            return tok;
+        }
+        advance(con); // Consume the closing "
+
+        // and fit in the token
+        if (length >= TOKEN_VAL_SIZE) {
+            tok.val[0] = '\0';
+            tok.tok_type = INVALID;
+            return tok;
+        }
+
+        tok.val[length] = '\0';
+        tok.tok_type = STR_LIT;
+        return tok;
+    }
+
     if (con->last_char == ';') {
-        con->last_char = getc(con->file_pointer); // Consume the ;
+        advance(con); // Consume the ;
         tok.tok_type = SC;
         return tok;
     }
@@ -229,25 +329,70 @@ struct token get_token(void *v_con) {
     return tok;
 }
 
-void * open_file(char *name) {
+static struct context *new_context(void) {
+    struct context *con = malloc(sizeof(struct context));
+
+    if (con != 0) {
+        con->file_pointer = 0;
+        con->buffer = 0;
+        con->buffer_len = 0;
+        con->buffer_pos = 0;
+        con->last_char = ' ';
+        con->line = 1;
+        con->column = 0;
+    }
+
+    return con;
+}
+
+struct context *open_file(const char *name) {
     FILE *file_pointer = fopen(name, "r");
 
     if (file_pointer != 0) {
-        void *v_con = malloc(sizeof(struct context));
+        struct context *con = new_context();
+
+        if (con == 0) {
+            fclose(file_pointer);
+            return 0;
+        }
 
-        struct context *con = (struct context *) v_con;
         con->file_pointer = file_pointer;
-        con->last_char = ' ';
-        con->next_char = ' ';        
 
-        return v_con;
+        return con;
     }
     
     return 0;
 }
 
-void close_file(void *v_con) {
-    struct context *con = (struct context *) v_con;
-    fclose(con->file_pointer);
+struct context *open_buffer(const char *buffer, size_t len) {
+    struct context *con = new_context();
+
+    if (con == 0) {
+        return 0;
+    }
+
+    // Take a copy so the caller is free to release their buffer straight away
+    con->buffer = malloc(len > 0 ? len : 1);
+    if (con->buffer == 0) {
+        free(con);
+        return 0;
+    }
+
+    memcpy(con->buffer, buffer, len);
+    con->buffer_len = len;
+
+    return con;
+}
+
+void close_file(struct context *con) {
+    if (con == 0) {
+        return;
+    }
+
+    if (con->file_pointer != 0) {
+        fclose(con->file_pointer);
+    }
+
+    free(con->buffer);
     free(con);
-}
\ No newline at end of file
+}
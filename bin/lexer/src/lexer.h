@@ -54,6 +54,18 @@ enum TOKEN_TYPE {
   GE,      // greater than or equal to
   GT, // greater than
 
+  // testing
+  ASSERT,    // "ASSERT"
+  ASSERT_EQ, // "ASSERT_EQ"
+  TEST,      // "TEST"
+  COMMA,     // ','
+  STR_LIT,   // "[^"\n]*", only used for test names and imports
+
+  // modules
+  IMPORT,    // "IMPORT"
+  EXPOSING,  // "EXPOSING"
+  DOT,       // '.' between a module and one of its variables
+
   // special tokens
   EOF_TOK, // signal end of file
 
@@ -61,13 +73,23 @@ enum TOKEN_TYPE {
   INVALID // signal invalid token
 };
 
-int char_num = 0;
+#include <stddef.h>
+
+// Opaque lexer state. Only ever created by open_file or open_buffer and only
+// ever released by close_file, the caller owns the pointer in between.
+struct context;
+
+// Room for a string literal and its terminator, names are shorter
+#define TOKEN_VAL_SIZE 128
 
 struct token {
     int tok_type;
-    char val[21];
+    char val[TOKEN_VAL_SIZE];
+    int line;   // 1 based line of the first character of the token
+    int column; // 1 based column of the first character of the token
 };
 
-struct token get_token(void *v_con);
-void * open_file(char *name);
-void close_file(void *v_con);
\ No newline at end of file
+struct token get_token(struct context *con);
+struct context *open_file(const char *name);
+struct context *open_buffer(const char *buffer, size_t len);
+void close_file(struct context *con);
\ No newline at end of file
@@ -2,7 +2,8 @@
 mod tests;
 
 use std::ffi::CString;
-use std::os::raw::{c_char, c_void};
+use std::os::raw::{c_char, c_int};
+use std::ptr::NonNull;
 
 #[repr(C)]
 #[allow(non_camel_case_types)]
@@ -34,52 +35,141 @@ pub enum TOKEN_TYPE {
     LT,
     GE,
     GT, 
+    ASSERT,
+    ASSERT_EQ,
+    TEST,
+    COMMA,
+    STR_LIT,
+    IMPORT,
+    EXPOSING,
+    DOT,
     EOF_TOK,
     INVALID
 }
 
+/// The words read as keywords rather than variables, these have to match
+/// the ones in lexer.c
+pub const KEYWORDS: [(&str, TOKEN_TYPE); 10] = [
+    ("IF", TOKEN_TYPE::IF),
+    ("ELSEIF", TOKEN_TYPE::ELSEIF),
+    ("ELSE", TOKEN_TYPE::ELSE),
+    ("REPEAT", TOKEN_TYPE::REPEAT),
+    ("OUTPUT", TOKEN_TYPE::OUTPUT),
+    ("ASSERT", TOKEN_TYPE::ASSERT),
+    ("ASSERT_EQ", TOKEN_TYPE::ASSERT_EQ),
+    ("TEST", TOKEN_TYPE::TEST),
+    ("IMPORT", TOKEN_TYPE::IMPORT),
+    ("EXPOSING", TOKEN_TYPE::EXPOSING)
+];
+
+/// The size of a token's value, including the NUL at the end. This has to
+/// match lexer.h
+pub const TOKEN_VAL_SIZE: usize = 128;
+
+impl TOKEN_TYPE {
+    /// How the token is written, for every token that is always written the
+    /// same way
+    pub fn spelling(&self) -> Option<&'static str> {
+        if let Some((word, _)) = KEYWORDS.iter().find(|(_, tok_type)| tok_type == self) {
+            return Some(word);
+        }
+
+        let spelling = match self {
+            TOKEN_TYPE::ASSIGN => "=",
+            TOKEN_TYPE::LBRA => "{",
+            TOKEN_TYPE::RBRA => "}",
+            TOKEN_TYPE::LPAR => "(",
+            TOKEN_TYPE::RPAR => ")",
+            TOKEN_TYPE::SC => ";",
+            TOKEN_TYPE::COMMA => ",",
+            TOKEN_TYPE::DOT => ".",
+            TOKEN_TYPE::AND => "&&",
+            TOKEN_TYPE::OR => "||",
+            TOKEN_TYPE::PLUS => "+",
+            TOKEN_TYPE::MINUS => "-",
+            TOKEN_TYPE::ASTERIX => "*",
+            TOKEN_TYPE::DIV => "/",
+            TOKEN_TYPE::MOD => "%",
+            TOKEN_TYPE::EQ => "==",
+            TOKEN_TYPE::NEQ => "!=",
+            TOKEN_TYPE::LE => "<=",
+            TOKEN_TYPE::LT => "<",
+            TOKEN_TYPE::GE => ">=",
+            TOKEN_TYPE::GT => ">",
+            _ => return None
+        };
+        return Some(spelling);
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct token {
     pub tok_type: TOKEN_TYPE,
-    pub val: [c_char; 21],
+    pub val: [c_char; TOKEN_VAL_SIZE],
+    pub line: c_int,
+    pub column: c_int,
+}
+
+/// Opaque lexer state owned by the C side, only ever handled through a pointer
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct context {
+    _private: [u8; 0],
 }
 
 extern "C" {
-    pub fn get_token(con: *const c_void) -> token;
-    pub fn open_file(name: CString) -> *const c_void;
-    pub fn close_file(con: *const c_void);
+    pub fn get_token(con: *mut context) -> token;
+    pub fn open_file(name: *const c_char) -> *mut context;
+    pub fn open_buffer(buffer: *const c_char, len: usize) -> *mut context;
+    pub fn close_file(con: *mut context);
+}
+
+/// Owning handle to a lexer context, the context is closed when this is dropped
+#[derive(Debug)]
+pub struct Lexer {
+    con: NonNull<context>,
 }
 
-pub fn get_token_safe(con: *const c_void) -> token {
-    let tok: token;
-    unsafe {
-        tok = get_token(con);
+impl Lexer {
+    /// Opens a file to be lexed, `None` if it can not be opened
+    pub fn open(filename: &str) -> Option<Self> {
+        let name = CString::new(filename).ok()?;
+        let con;
+        unsafe {
+            con = open_file(name.as_ptr());
+        }
+
+        NonNull::new(con).map(|con| Self{con})
     }
 
-    return tok;
-}
+    /// Lexes source held in memory. The source is copied so it does not need
+    /// to outlive the lexer
+    pub fn from_source(source: &[u8]) -> Self {
+        let con;
+        unsafe {
+            con = open_buffer(source.as_ptr() as *const c_char, source.len());
+        }
 
-pub fn open_file_safe(filename: &str) -> Option<*const c_void> {
-    let worked: *const c_void;
-    unsafe {
-        worked = open_file(CString::new(filename).unwrap());
+        Self{con: NonNull::new(con).expect("Could not allocate lexer context")}
     }
 
-    if worked.is_null() {
-        return None;
-    } else {
-        return Some(worked);
+    pub fn next_token(&mut self) -> token {
+        unsafe {
+            get_token(self.con.as_ptr())
+        }
     }
 }
 
-pub fn close_file_safe(con: *const c_void) {
-    unsafe {
-        close_file(con);
+impl Drop for Lexer {
+    fn drop(&mut self) {
+        unsafe {
+            close_file(self.con.as_ptr());
+        }
     }
 }
 
-pub fn val_to_str(val: &[c_char; 21]) -> Option<String> {
+pub fn val_to_str(val: &[c_char; TOKEN_VAL_SIZE]) -> Option<String> {
     let mut result = String::new();
     for c in val {
         let my_char = *c as u8 as char;
@@ -1,13 +1,12 @@
-use std::any::Any;
-
 use crate::*;
 
 #[test]
 fn token_recognission_test() {
     let mut tok: token;
-    let context = open_file_safe("test/tokentest.xa");
+    let context = Lexer::open("test/tokentest.xa");
     // Test whether it can open the file
-    assert_ne!(context, None);
+    assert!(context.is_some());
+    let mut context = context.unwrap();
 
     // ELSE hello = == IF ELSEIF + - () {} ;
     let tokens = [
@@ -31,22 +30,18 @@ fn token_recognission_test() {
     ];
 
     for tok_type in tokens {
-        tok = get_token_safe(context.unwrap());
+        tok = context.next_token();
         assert_eq!(tok.tok_type, tok_type);
     }
-
-    unsafe{
-        close_file(context.unwrap());
-    }
-    
 }
 
 #[test]
 fn var_lit_val_test() {
     let mut tok: token;
-    let context = open_file_safe("test/varlittest.xa");
+    let context = Lexer::open("test/varlittest.xa");
     // Test whether it can open the file
-    assert_ne!(context, None);
+    assert!(context.is_some());
+    let mut context = context.unwrap();
 
     let vals = [
         "hello",
@@ -56,14 +51,122 @@ fn var_lit_val_test() {
     ];
 
     for val in vals {
-        tok = get_token_safe(context.unwrap());
+        tok = context.next_token();
 
         let tok_val = val_to_str(&tok.val).unwrap();
         
         assert_eq!(tok_val, val);
     }
+}
 
-    unsafe{
-        close_file(context.unwrap());
+#[test]
+fn token_location_test() {
+    let mut context = Lexer::from_source(b"x = 1;\n  OUTPUT x;");
+
+    let expected = [
+        (TOKEN_TYPE::VAR, 1, 1),
+        (TOKEN_TYPE::ASSIGN, 1, 3),
+        (TOKEN_TYPE::INT_LIT, 1, 5),
+        (TOKEN_TYPE::SC, 1, 6),
+        (TOKEN_TYPE::OUTPUT, 2, 3),
+        (TOKEN_TYPE::VAR, 2, 10),
+        (TOKEN_TYPE::SC, 2, 11),
+        (TOKEN_TYPE::EOF_TOK, 2, 12)
+    ];
+
+    for (tok_type, line, column) in expected {
+        let tok = context.next_token();
+        assert_eq!(tok.tok_type, tok_type);
+        assert_eq!((tok.line, tok.column), (line, column));
+    }
+}
+
+#[test]
+fn comments_are_skipped() {
+    let mut context = Lexer::from_source(b"// A comment\nx = 4 / 2; // Another\n//\nOUTPUT x;//");
+
+    let expected = [
+        (TOKEN_TYPE::VAR, 2, 1),
+        (TOKEN_TYPE::ASSIGN, 2, 3),
+        (TOKEN_TYPE::INT_LIT, 2, 5),
+        (TOKEN_TYPE::DIV, 2, 7),
+        (TOKEN_TYPE::INT_LIT, 2, 9),
+        (TOKEN_TYPE::SC, 2, 10),
+        (TOKEN_TYPE::OUTPUT, 4, 1),
+        (TOKEN_TYPE::VAR, 4, 8),
+        (TOKEN_TYPE::SC, 4, 9),
+        (TOKEN_TYPE::EOF_TOK, 4, 12)
+    ];
+
+    for (tok_type, line, column) in expected {
+        let tok = context.next_token();
+        assert_eq!(tok.tok_type, tok_type);
+        assert_eq!((tok.line, tok.column), (line, column));
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn many_comment_lines() {
+    // Comments are skipped in a loop, a line at a time would run out of stack
+    let source = format!("{}OUTPUT 1;", "//\n".repeat(1_000_000));
+    let mut context = Lexer::from_source(source.as_bytes());
+
+    let tok = context.next_token();
+    assert_eq!(tok.tok_type, TOKEN_TYPE::OUTPUT);
+    assert_eq!((tok.line, tok.column), (1_000_001, 1));
+}
+
+/// The keywords and spellings here have to agree with lexer.c
+#[test]
+fn spellings_lex_back() {
+    for (word, tok_type) in KEYWORDS {
+        assert_eq!(Lexer::from_source(word.as_bytes()).next_token().tok_type, tok_type);
+    }
+
+    let mut spelled = 0;
+    for tok_type in [
+        TOKEN_TYPE::VAR, TOKEN_TYPE::ASSIGN, TOKEN_TYPE::LBRA, TOKEN_TYPE::RBRA, TOKEN_TYPE::LPAR, TOKEN_TYPE::RPAR,
+        TOKEN_TYPE::SC, TOKEN_TYPE::REPEAT, TOKEN_TYPE::IF, TOKEN_TYPE::ELSE, TOKEN_TYPE::ELSEIF, TOKEN_TYPE::OUTPUT,
+        TOKEN_TYPE::INT_LIT, TOKEN_TYPE::AND, TOKEN_TYPE::OR, TOKEN_TYPE::PLUS, TOKEN_TYPE::MINUS, TOKEN_TYPE::ASTERIX,
+        TOKEN_TYPE::DIV, TOKEN_TYPE::MOD, TOKEN_TYPE::EQ, TOKEN_TYPE::NEQ, TOKEN_TYPE::LE, TOKEN_TYPE::LT,
+        TOKEN_TYPE::GE, TOKEN_TYPE::GT, TOKEN_TYPE::ASSERT, TOKEN_TYPE::ASSERT_EQ, TOKEN_TYPE::TEST, TOKEN_TYPE::COMMA,
+        TOKEN_TYPE::STR_LIT, TOKEN_TYPE::IMPORT, TOKEN_TYPE::EXPOSING, TOKEN_TYPE::DOT, TOKEN_TYPE::EOF_TOK,
+        TOKEN_TYPE::INVALID
+    ] {
+        if let Some(spelling) = tok_type.spelling() {
+            let mut context = Lexer::from_source(spelling.as_bytes());
+            assert_eq!(context.next_token().tok_type, tok_type, "{}", spelling);
+            assert_eq!(context.next_token().tok_type, TOKEN_TYPE::EOF_TOK, "{}", spelling);
+            spelled += 1;
+        }
+    }
+    assert_eq!(spelled, 31);
+}
+
+#[test]
+fn string_literals() {
+    let long = "n".repeat(TOKEN_VAL_SIZE - 1);
+    let source = format!("TEST \"adds up\" ASSERT_EQ (a, b) \"{}\" x_y \"open\n\"{}n\"", long, long);
+    let mut context = Lexer::from_source(source.as_bytes());
+
+    let mut next = || {
+        let tok = context.next_token();
+        (tok.tok_type, val_to_str(&tok.val).unwrap())
+    };
+    assert_eq!(next(), (TOKEN_TYPE::TEST, String::new()));
+    assert_eq!(next(), (TOKEN_TYPE::STR_LIT, "adds up".to_string()));
+    assert_eq!(next().0, TOKEN_TYPE::ASSERT_EQ);
+    assert_eq!(next().0, TOKEN_TYPE::LPAR);
+    assert_eq!(next().0, TOKEN_TYPE::VAR);
+    assert_eq!(next().0, TOKEN_TYPE::COMMA);
+    assert_eq!(next().0, TOKEN_TYPE::VAR);
+    assert_eq!(next().0, TOKEN_TYPE::RPAR);
+    assert_eq!(next(), (TOKEN_TYPE::STR_LIT, long));
+
+    // Only keywords can have underscores, and strings end on their line and
+    // have to fit
+    assert_eq!(next().0, TOKEN_TYPE::INVALID);
+    assert_eq!(next().0, TOKEN_TYPE::INVALID);
+    assert_eq!(next().0, TOKEN_TYPE::INVALID);
+    assert_eq!(next().0, TOKEN_TYPE::EOF_TOK);
+}
@@ -0,0 +1,300 @@
+//! Warnings for code that runs but is probably wrong. Each rule has a code,
+//! and rules can be turned off for a project in a `.xalint` file or for a
+//! line with a comment:
+//!
+//! ```text
+//! x = x; // lint: disable L002
+//! // lint: disable-next-line L001 L003
+//! ```
+//!
+//! `all` stands for every rule. A `.xalint` file has one `disable RULE` or
+//! `enable RULE` per line, where the rule is a code or a name, and `#`
+//! starts a comment.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use language::*;
+use lexer::{Lexer, TOKEN_TYPE};
+use parser::ParseError;
+
+pub struct Rule {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub description: &'static str
+}
+
+pub const RULES: [Rule; 7] = [
+    Rule{code: "L001", name: "unused-variable", description: "a variable is assigned but never read"},
+    Rule{code: "L002", name: "self-assignment", description: "a variable is assigned to itself"},
+    Rule{code: "L003", name: "constant-condition", description: "an IF or ELSEIF condition is always true or always false"},
+    Rule{code: "L004", name: "negative-repeat", description: "a REPEAT count is a negative constant, so always fails"},
+    Rule{code: "L005", name: "duplicate-condition", description: "an ELSEIF repeats an earlier condition, so can never run"},
+    Rule{code: "L006", name: "literal-overflow", description: "an integer literal does not fit in 32 bits"},
+    Rule{code: "L007", name: "bad-directive", description: "a lint comment can't be understood"}
+];
+
+/// The config file looked for in the directory of a file and those above it
+pub const CONFIG_FILE: &str = ".xalint";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub code: &'static str,
+    pub loc: Location,
+    pub message: String
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: warning[{}]: {}", self.loc, self.code, self.message)
+    }
+}
+
+/// The codes of the rules named in `words`, each a code, a name or `all`
+fn rule_codes<'a>(words: impl Iterator<Item = &'a str>) -> Result<Vec<&'static str>, String> {
+    let mut codes = Vec::new();
+
+    for word in words {
+        if word == "all" {
+            codes.extend(RULES.iter().map(|r| r.code));
+            continue;
+        }
+        match RULES.iter().find(|r| r.code == word || r.name == word) {
+            Some(rule) => codes.push(rule.code),
+            None => return Err(format!("unknown lint rule {:?}", word))
+        }
+    }
+
+    return Ok(codes);
+}
+
+/// Which rules are turned off for a project, every rule is on by default
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    pub disabled: BTreeSet<&'static str>
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+            let error = |e: String| format!("{}:{}: {}", CONFIG_FILE, number + 1, e);
+
+            match words.next() {
+                None => (),
+                Some("disable") => config.disabled.extend(rule_codes(words).map_err(error)?),
+                Some("enable") => {
+                    for code in rule_codes(words).map_err(error)? {
+                        config.disabled.remove(code);
+                    }
+                },
+                Some(other) => return Err(error(format!("expected disable or enable, found {:?}", other)))
+            }
+        }
+
+        return Ok(config);
+    }
+
+    /// The config for the project `file` is in, from the nearest `.xalint`
+    pub fn find(file: &Path) -> Result<Self, String> {
+        let start = file.parent().unwrap_or(Path::new("."));
+
+        for dir in start.ancestors() {
+            let path = dir.join(CONFIG_FILE);
+            if path.is_file() {
+                let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                return Config::parse(&text);
+            }
+        }
+
+        return Ok(Config::default());
+    }
+}
+
+/// Rules turned off by `// lint:` comments, by line, and warnings for the
+/// comments that can't be understood
+fn line_disables(source: &str) -> (Vec<BTreeSet<&'static str>>, Vec<Warning>) {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut disabled = vec![BTreeSet::new(); lines.len() + 1];
+    let mut warnings = Vec::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        let Some((code, comment)) = line.split_once("//") else {
+            continue;
+        };
+        let Some(directive) = comment.trim().strip_prefix("lint:") else {
+            continue;
+        };
+
+        let loc = Location::new(index as u32 + 1, code.len() as u32 + 1);
+        let mut words = directive.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty());
+        let target = match words.next() {
+            Some("disable") => index,
+            Some("disable-next-line") => index + 1,
+            other => {
+                let message = format!("expected disable or disable-next-line, found {:?}", other.unwrap_or(""));
+                warnings.push(Warning{code: "L007", loc, message});
+                continue;
+            }
+        };
+
+        match rule_codes(words) {
+            Ok(codes) => disabled[target].extend(codes),
+            Err(message) => warnings.push(Warning{code: "L007", loc, message})
+        }
+    }
+
+    return (disabled, warnings);
+}
+
+/// Literals too big for an i32, which the parser stops at
+fn literal_overflows(source: &str) -> Vec<Warning> {
+    let mut lexer = Lexer::from_source(source.as_bytes());
+    let mut warnings = Vec::new();
+
+    loop {
+        let tok = lexer.next_token();
+        match tok.tok_type {
+            // The lexer doesn't move past something it doesn't know, and the
+            // parser will fail on it anyway
+            TOKEN_TYPE::EOF_TOK | TOKEN_TYPE::INVALID => break,
+            TOKEN_TYPE::INT_LIT => {
+                let text = lexer::val_to_str(&tok.val).unwrap_or_default();
+                if text.parse::<i32>().is_err() {
+                    warnings.push(Warning{
+                        code: "L006",
+                        loc: Location::new(tok.line as u32, tok.column as u32),
+                        message: format!("{} does not fit in 32 bits, the largest literal is {}", text, i32::MAX)
+                    });
+                }
+            },
+            _ => ()
+        }
+    }
+
+    return warnings;
+}
+
+/// The value of `exp` if it has no variables and can be worked out
+fn constant(exp: &Expression) -> Option<i32> {
+    match exp {
+        Expression::Val(value) => Some(*value),
+        Expression::Var(_) => None,
+        Expression::BinOp(op, lhs, rhs) => op.apply(constant(lhs)?, constant(rhs)?).ok()
+    }
+}
+
+fn first_assignment(block: &Block, var: &str) -> Option<Location> {
+    for stmt in block.statements.iter() {
+        let loc = match stmt {
+            Statement::AssignStatement { var: assigned, loc, .. } if assigned == var => Some(*loc),
+            Statement::AssignStatement { .. } | Statement::OutputStatement { .. } | Statement::AssertStatement { .. } => None,
+            Statement::RepeatStatement { body, .. } => first_assignment(body, var),
+            Statement::IfStatement { body, else_if, else_body, .. } => {
+                std::iter::once(body).chain(else_if.iter().map(|(_, b)| b)).chain([else_body])
+                    .find_map(|block| first_assignment(block, var))
+            }
+        };
+        if loc.is_some() {
+            return loc;
+        }
+    }
+
+    return None;
+}
+
+fn lint_block(block: &Block, warnings: &mut Vec<Warning>) {
+    for stmt in block.statements.iter() {
+        let loc = stmt.location();
+
+        match stmt {
+            Statement::AssignStatement { var, exp, .. } => {
+                if *exp == Expression::Var(var.clone()) {
+                    warnings.push(Warning{code: "L002", loc, message: format!("Variable {:?} is assigned to itself", var)});
+                }
+            },
+            Statement::OutputStatement { .. } | Statement::AssertStatement { .. } => (),
+            Statement::RepeatStatement { times, body, .. } => {
+                if let Some(count) = constant(times).filter(|c| *c < 0) {
+                    warnings.push(Warning{code: "L004", loc, message: format!("REPEAT count is always {}, so this always fails", count)});
+                }
+                lint_block(body, warnings);
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                let conditions = std::iter::once(condition).chain(else_if.iter().map(|(c, _)| c));
+
+                for (arm, condition) in conditions.clone().enumerate() {
+                    let name = if arm == 0 { "IF".to_string() } else { format!("ELSEIF {}", arm) };
+
+                    if let Some(value) = constant(condition) {
+                        let always = if value != 0 { "true" } else { "false" };
+                        warnings.push(Warning{code: "L003", loc, message: format!("{} condition is always {}", name, always)});
+                    }
+                    if conditions.clone().take(arm).any(|earlier| earlier == condition) {
+                        warnings.push(Warning{code: "L005", loc, message: format!("{} repeats an earlier condition, so can never run", name)});
+                    }
+                }
+
+                lint_block(body, warnings);
+                for (_, block) in else_if {
+                    lint_block(block, warnings);
+                }
+                lint_block(else_body, warnings);
+            }
+        }
+    }
+}
+
+/// Every warning for `source` that isn't turned off, in source order. When
+/// a literal is too big to parse only those are reported, as nothing else
+/// can be checked
+pub fn lint(source: &str, config: &Config) -> Result<Vec<Warning>, ParseError> {
+    return lint_parsed(source, parser::parse_source(source), config);
+}
+
+/// `lint` for source whose imports are relative to `dir`. Only warnings in
+/// `source` itself are given, imported files are linted on their own
+pub fn lint_in(source: &str, dir: &Path, config: &Config) -> Result<Vec<Warning>, ParseError> {
+    return lint_parsed(source, parser::Loader::new().load_source(source, dir), config);
+}
+
+fn lint_parsed(source: &str, parsed: Result<Program, ParseError>, config: &Config) -> Result<Vec<Warning>, ParseError> {
+    let mut warnings = literal_overflows(source);
+
+    match parsed {
+        Ok(program) => {
+            // Each test runs in its own memory, so is checked on its own
+            for block in std::iter::once(&program.program).chain(program.tests.iter().map(|t| &t.body)) {
+                for var in block.assigned_variables().difference(&block.read_variables()) {
+                    let loc = first_assignment(block, var).unwrap();
+                    warnings.push(Warning{code: "L001", loc, message: format!("Variable {:?} is assigned but never read", var)});
+                }
+                lint_block(block, &mut warnings);
+            }
+        },
+        Err(ParseError::IntegerParseError(_)) if !warnings.is_empty() => (),
+        Err(e) => return Err(e)
+    }
+
+    let (disables, bad_directives) = line_disables(source);
+    warnings.extend(bad_directives);
+
+    warnings.retain(|w| {
+        if w.loc.file != 0 {
+            return false;
+        }
+        let line_disabled = disables.get(w.loc.line as usize - 1).is_some_and(|d| d.contains(w.code));
+        !config.disabled.contains(w.code) && !line_disabled
+    });
+    warnings.sort_by_key(|w| w.loc);
+
+    return Ok(warnings);
+}
@@ -0,0 +1,96 @@
+use crate::*;
+
+fn warnings(source: &str) -> Vec<String> {
+    lint(source, &Config::default()).unwrap().iter().map(|w| w.to_string()).collect()
+}
+
+#[test]
+fn rules() {
+    let source = "x = 1;
+y = 2;
+y = y;
+IF (2 > 1) {
+    OUTPUT y;
+} ELSEIF (y < 3) {
+    OUTPUT 1;
+} ELSEIF (y < 3) {
+    OUTPUT 2;
+} ELSEIF (0) {
+}
+REPEAT (1 - 3) {
+    OUTPUT 3;
+}
+REPEAT (y) {
+    IF (y) {
+        unused = y;
+    }
+}";
+
+    assert_eq!(warnings(source), vec![
+        "1:1: warning[L001]: Variable \"x\" is assigned but never read",
+        "3:1: warning[L002]: Variable \"y\" is assigned to itself",
+        "4:1: warning[L003]: IF condition is always true",
+        "4:1: warning[L005]: ELSEIF 2 repeats an earlier condition, so can never run",
+        "4:1: warning[L003]: ELSEIF 3 condition is always false",
+        "12:1: warning[L004]: REPEAT count is always -2, so this always fails",
+        "17:9: warning[L001]: Variable \"unused\" is assigned but never read"
+    ]);
+
+    assert!(warnings(include_str!("../../../examples/fibbonachi.xa")).is_empty());
+    assert!(warnings(include_str!("../../../examples/moreops.xa")).is_empty());
+}
+
+#[test]
+fn literal_overflow() {
+    // The parser can't get past these, so they are all that is reported
+    assert_eq!(warnings("x = 2147483647;\ny = 2147483648 + 99999999999;\nz = z;"), vec![
+        "2:5: warning[L006]: 2147483648 does not fit in 32 bits, the largest literal is 2147483647",
+        "2:18: warning[L006]: 99999999999 does not fit in 32 bits, the largest literal is 2147483647"
+    ]);
+
+    // Any other parse error is an error
+    assert!(lint("x = ;", &Config::default()).is_err());
+}
+
+#[test]
+fn line_directives() {
+    let source = "x = 1; // lint: disable L001
+y = y; // lint: disable self-assignment, unused-variable
+// lint: disable-next-line all
+IF (1) {
+    OUTPUT 1; // lint: disable L003
+}
+IF (0) {
+    // lint: disable L003
+}
+z = 1; // lint: ignore L001
+w = 1; // lint: disable L999";
+
+    assert_eq!(warnings(source), vec![
+        "7:1: warning[L003]: IF condition is always false",
+        "10:1: warning[L001]: Variable \"z\" is assigned but never read",
+        "10:8: warning[L007]: expected disable or disable-next-line, found \"ignore\"",
+        "11:1: warning[L001]: Variable \"w\" is assigned but never read",
+        "11:8: warning[L007]: unknown lint rule \"L999\""
+    ]);
+}
+
+#[test]
+fn project_config() {
+    let config = Config::parse("# Too noisy for us\ndisable all\n\nenable L002 # but not this one\n").unwrap();
+    assert_eq!(config.disabled.len(), RULES.len() - 1);
+    assert!(!config.disabled.contains("L002"));
+    assert_eq!(lint("x = x;\nIF (1) {\n}", &config).unwrap().len(), 1);
+
+    assert_eq!(Config::parse("disable L001\nturn off L002"), Err(".xalint:2: expected disable or enable, found \"turn\"".to_string()));
+    assert_eq!(Config::parse("disable nonsense"), Err(".xalint:1: unknown lint rule \"nonsense\"".to_string()));
+
+    // The nearest config wins
+    let dir = std::env::temp_dir().join(format!("lint-{}", std::process::id()));
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join(CONFIG_FILE), "disable L001").unwrap();
+    assert_eq!(Config::find(&dir.join("sub").join("a.xa")).unwrap().disabled, BTreeSet::from(["L001"]));
+    fs::write(dir.join("sub").join(CONFIG_FILE), "disable L002").unwrap();
+    assert_eq!(Config::find(&dir.join("sub").join("a.xa")).unwrap().disabled, BTreeSet::from(["L002"]));
+    fs::remove_dir_all(&dir).unwrap();
+}
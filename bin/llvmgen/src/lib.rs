@@ -0,0 +1,428 @@
+//! Compiles a program to textual LLVM IR with a `main` function. Variables
+//! are SSA values, with phi nodes where IF arms meet and at the top of each
+//! REPEAT, so nothing goes through memory. Pointers are written as `ptr`,
+//! which LLVM 15 and later read by default and LLVM 14 reads with
+//! `-opaque-pointers`. Runtime errors print `line:column: message` on
+//! stderr and exit with status 1.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use language::*;
+
+const DECLARATIONS: &str = "\
+declare i32 @printf(ptr, ...)
+declare i64 @write(i32, ptr, i64)
+declare void @exit(i32) noreturn
+declare {i32, i1} @llvm.sadd.with.overflow.i32(i32, i32)
+declare {i32, i1} @llvm.ssub.with.overflow.i32(i32, i32)
+declare {i32, i1} @llvm.smul.with.overflow.i32(i32, i32)
+
+define internal void @xa_output(i32 %value) {
+entry:
+  %written = call i32 (ptr, ...) @printf(ptr @.format, i32 %value)
+  ret void
+}
+
+define internal void @xa_fail(ptr %message, i64 %length) noreturn {
+entry:
+  %written = call i64 @write(i32 2, ptr %message, i64 %length)
+  call void @exit(i32 1)
+  unreachable
+}
+";
+
+/// Whether a variable has been given a value at some point in the program
+#[derive(Debug, Clone, PartialEq)]
+enum Assigned {
+    Yes,
+    No,
+    /// Depends on the way taken to get here, the `i1` value says
+    Maybe(String)
+}
+
+impl Assigned {
+    fn as_i1(&self) -> String {
+        match self {
+            Assigned::Yes => "true".to_string(),
+            Assigned::No => "false".to_string(),
+            Assigned::Maybe(value) => value.clone()
+        }
+    }
+}
+
+/// The SSA value a variable has at some point in the program
+#[derive(Debug, Clone, PartialEq)]
+struct Var {
+    value: String,
+    assigned: Assigned
+}
+
+// Variables that are missing have never been assigned
+type Vars = BTreeMap<String, Var>;
+
+fn unassigned() -> Var {
+    Var{value: "0".to_string(), assigned: Assigned::No}
+}
+
+struct BasicBlock {
+    label: String,
+    phis: Vec<String>,
+    body: Vec<String>
+}
+
+struct Generator {
+    blocks: Vec<BasicBlock>,
+    /// Blocks in the order they were started, which is the order written
+    order: Vec<usize>,
+    current: usize,
+    names: usize,
+    vars: Vars,
+    messages: Vec<String>
+}
+
+impl Generator {
+    fn name(&mut self, hint: &str) -> String {
+        self.names += 1;
+        format!("%{}.{}", hint, self.names)
+    }
+
+    /// A new empty block, to be started later
+    fn block(&mut self, hint: &str) -> usize {
+        self.names += 1;
+        self.blocks.push(BasicBlock{label: format!("{}.{}", hint, self.names), phis: Vec::new(), body: Vec::new()});
+        self.blocks.len() - 1
+    }
+
+    fn start(&mut self, block: usize) {
+        self.order.push(block);
+        self.current = block;
+    }
+
+    fn label(&self, block: usize) -> String {
+        format!("%{}", self.blocks[block].label)
+    }
+
+    fn emit(&mut self, line: String) {
+        self.blocks[self.current].body.push(line);
+    }
+
+    /// Stops with `message` if the `i1` value `cond` is true
+    fn fail_if(&mut self, cond: &str, message: &str, loc: Location) {
+        let text = format!("{}: {}\n", loc, message);
+        let index = match self.messages.iter().position(|m| *m == text) {
+            Some(i) => i,
+            None => {
+                self.messages.push(text.clone());
+                self.messages.len() - 1
+            }
+        };
+
+        let (error, ok) = (self.block("error"), self.block("ok"));
+        self.emit(format!("br i1 {}, label {}, label {}", cond, self.label(error), self.label(ok)));
+
+        self.start(error);
+        self.emit(format!("call void @xa_fail(ptr @.message.{}, i64 {})", index, text.len()));
+        self.emit("unreachable".to_string());
+
+        self.start(ok);
+    }
+
+    /// Works out `exp` and returns the `i32` value it comes to
+    fn exp(&mut self, exp: &Expression, loc: Location) -> String {
+        match exp {
+            Expression::Val(v) => return v.to_string(),
+            Expression::Var(var) => {
+                let state = self.vars.get(var).cloned().unwrap_or_else(unassigned);
+                let message = format!("Variable \"{}\" has not been assigned", var);
+
+                match &state.assigned {
+                    Assigned::Yes => (),
+                    Assigned::No => self.fail_if("true", &message, loc),
+                    Assigned::Maybe(flag) => {
+                        let missing = self.name("missing");
+                        self.emit(format!("{} = xor i1 {}, true", missing, flag));
+                        self.fail_if(&missing, &message, loc);
+                    }
+                }
+
+                return state.value;
+            },
+            Expression::BinOp(op, lhs, rhs) => {
+                let lhs = self.exp(lhs, loc);
+                let rhs = self.exp(rhs, loc);
+                return self.bin_op(*op, &lhs, &rhs, loc);
+            }
+        }
+    }
+
+    fn bin_op(&mut self, op: Op, lhs: &str, rhs: &str, loc: Location) -> String {
+        let result = self.name("t");
+
+        match op {
+            Op::Add | Op::Sub | Op::Multiply => {
+                let (intrinsic, message) = match op {
+                    Op::Add => ("sadd", "addition overflowed"),
+                    Op::Sub => ("ssub", "subtraction overflowed"),
+                    _ => ("smul", "multiplication overflowed")
+                };
+                let pair = self.name("pair");
+                let overflow = self.name("overflow");
+                self.emit(format!("{} = call {{i32, i1}} @llvm.{}.with.overflow.i32(i32 {}, i32 {})", pair, intrinsic, lhs, rhs));
+                self.emit(format!("{} = extractvalue {{i32, i1}} {}, 1", overflow, pair));
+                self.fail_if(&overflow, message, loc);
+                self.emit(format!("{} = extractvalue {{i32, i1}} {}, 0", result, pair));
+            },
+            Op::Divide | Op::Remainder => {
+                let (instr, zero, overflow) = match op {
+                    Op::Divide => ("sdiv", "division by zero", "division overflowed"),
+                    _ => ("srem", "remainder by zero", "remainder overflowed")
+                };
+                let is_zero = self.name("zero");
+                self.emit(format!("{} = icmp eq i32 {}, 0", is_zero, rhs));
+                self.fail_if(&is_zero, zero, loc);
+
+                let (is_min, is_minus_one, overflows) = (self.name("min"), self.name("minusone"), self.name("overflow"));
+                self.emit(format!("{} = icmp eq i32 {}, -2147483648", is_min, lhs));
+                self.emit(format!("{} = icmp eq i32 {}, -1", is_minus_one, rhs));
+                self.emit(format!("{} = and i1 {}, {}", overflows, is_min, is_minus_one));
+                self.fail_if(&overflows, overflow, loc);
+
+                self.emit(format!("{} = {} i32 {}, {}", result, instr, lhs, rhs));
+            },
+            Op::And | Op::Or => {
+                let (l, r, both) = (self.name("l"), self.name("r"), self.name("b"));
+                self.emit(format!("{} = icmp ne i32 {}, 0", l, lhs));
+                self.emit(format!("{} = icmp ne i32 {}, 0", r, rhs));
+                self.emit(format!("{} = {} i1 {}, {}", both, if op == Op::And { "and" } else { "or" }, l, r));
+                self.emit(format!("{} = zext i1 {} to i32", result, both));
+            },
+            _ => {
+                let cond = match op {
+                    Op::Equal => "eq",
+                    Op::NotEqual => "ne",
+                    Op::LessThan => "slt",
+                    Op::LessThanOrEqual => "sle",
+                    Op::GreaterThan => "sgt",
+                    _ => "sge"
+                };
+                let flag = self.name("c");
+                self.emit(format!("{} = icmp {} i32 {}, {}", flag, cond, lhs, rhs));
+                self.emit(format!("{} = zext i1 {} to i32", result, flag));
+            }
+        }
+
+        return result;
+    }
+
+    /// Where the ways into the current block meet, each with the label it
+    /// comes from and the variables it brings. Adds the phi nodes needed
+    fn merge(&mut self, incoming: &[(String, Vars)]) -> Vars {
+        let names: BTreeSet<&String> = incoming.iter().flat_map(|(_, vars)| vars.keys()).collect();
+
+        let mut merged = Vars::new();
+        for name in names {
+            let states: Vec<(&String, Var)> = incoming.iter()
+                .map(|(label, vars)| (label, vars.get(name).cloned().unwrap_or_else(unassigned)))
+                .collect();
+
+            let value = match states.iter().all(|(_, s)| s.value == states[0].1.value) {
+                true => states[0].1.value.clone(),
+                false => {
+                    let phi = self.name(name);
+                    let arms: Vec<String> = states.iter().map(|(l, s)| format!("[{}, {}]", s.value, l)).collect();
+                    self.blocks[self.current].phis.push(format!("{} = phi i32 {}", phi, arms.join(", ")));
+                    phi
+                }
+            };
+
+            let assigned = match states.iter().all(|(_, s)| s.assigned == states[0].1.assigned) {
+                true => states[0].1.assigned.clone(),
+                false => {
+                    let phi = self.name(&format!("{}.assigned", name));
+                    let arms: Vec<String> = states.iter().map(|(l, s)| format!("[{}, {}]", s.assigned.as_i1(), l)).collect();
+                    self.blocks[self.current].phis.push(format!("{} = phi i1 {}", phi, arms.join(", ")));
+                    Assigned::Maybe(phi)
+                }
+            };
+
+            if assigned != Assigned::No {
+                merged.insert(name.clone(), Var{value, assigned});
+            }
+        }
+
+        return merged;
+    }
+
+    fn statements(&mut self, block: &Block) {
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        let loc = stmt.location();
+
+        match stmt {
+            Statement::AssignStatement { var, exp, .. } => {
+                let value = self.exp(exp, loc);
+                self.vars.insert(var.clone(), Var{value, assigned: Assigned::Yes});
+            },
+            Statement::OutputStatement { to_output, .. } => {
+                let value = self.exp(to_output, loc);
+                self.emit(format!("call void @xa_output(i32 {})", value));
+            },
+            Statement::AssertStatement { exp, expected, .. } => {
+                let value = self.exp(exp, loc);
+                let failed = self.name("failed");
+                match expected {
+                    Some(expected) => {
+                        let expected = self.exp(expected, loc);
+                        self.emit(format!("{} = icmp ne i32 {}, {}", failed, value, expected));
+                    },
+                    None => self.emit(format!("{} = icmp eq i32 {}, 0", failed, value))
+                }
+                self.fail_if(&failed, "assertion failed", loc);
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                let end = self.block("endif");
+                let mut incoming = Vec::new();
+
+                let mut arms = vec![(condition, body)];
+                arms.extend(else_if.iter().map(|(exp, block)| (exp, block)));
+
+                for (exp, block) in arms {
+                    let value = self.exp(exp, loc);
+                    let cond = self.name("cond");
+                    let (then, next) = (self.block("then"), self.block("else"));
+                    self.emit(format!("{} = icmp ne i32 {}, 0", cond, value));
+                    self.emit(format!("br i1 {}, label {}, label {}", cond, self.label(then), self.label(next)));
+
+                    let before = self.vars.clone();
+                    self.start(then);
+                    self.statements(block);
+                    self.emit(format!("br label {}", self.label(end)));
+                    incoming.push((self.label(self.current), std::mem::replace(&mut self.vars, before)));
+
+                    self.start(next);
+                }
+
+                self.statements(else_body);
+                self.emit(format!("br label {}", self.label(end)));
+                incoming.push((self.label(self.current), self.vars.clone()));
+
+                self.start(end);
+                self.vars = self.merge(&incoming);
+            },
+            Statement::RepeatStatement { times, body, .. } => {
+                let times = self.exp(times, loc);
+                let negative = self.name("negative");
+                self.emit(format!("{} = icmp slt i32 {}, 0", negative, times));
+                self.fail_if(&negative, "REPEAT count is negative", loc);
+
+                let (header, loop_body, done) = (self.block("repeat"), self.block("body"), self.block("done"));
+                let before_label = self.label(self.current);
+                let before = self.vars.clone();
+                self.emit(format!("br label {}", self.label(header)));
+
+                // Everything the body assigns gets a phi node at the top of
+                // the loop, filled in once the body has been generated
+                self.start(header);
+                let count = self.name("count");
+                let changed = body.assigned_variables();
+                for var in changed.iter() {
+                    let value = self.name(var);
+                    let assigned = match before.get(var).map(|v| &v.assigned) {
+                        Some(Assigned::Yes) => Assigned::Yes,
+                        _ => Assigned::Maybe(self.name(&format!("{}.assigned", var)))
+                    };
+                    self.vars.insert(var.clone(), Var{value, assigned});
+                }
+                let at_header = self.vars.clone();
+
+                let finished = self.name("finished");
+                self.emit(format!("{} = icmp eq i32 {}, 0", finished, count));
+                self.emit(format!("br i1 {}, label {}, label {}", finished, self.label(done), self.label(loop_body)));
+
+                self.start(loop_body);
+                let next = self.name("next");
+                self.emit(format!("{} = sub i32 {}, 1", next, count));
+                self.statements(body);
+                self.emit(format!("br label {}", self.label(header)));
+                let latch_label = self.label(self.current);
+
+                let mut phis = vec![format!("{} = phi i32 [{}, {}], [{}, {}]", count, times, before_label, next, latch_label)];
+                for var in changed.iter() {
+                    let entry = before.get(var).cloned().unwrap_or_else(unassigned);
+                    let latch = self.vars.get(var).cloned().unwrap_or_else(unassigned);
+                    let phi = &at_header[var];
+
+                    phis.push(format!("{} = phi i32 [{}, {}], [{}, {}]", phi.value, entry.value, before_label, latch.value, latch_label));
+                    if let Assigned::Maybe(flag) = &phi.assigned {
+                        phis.push(format!(
+                            "{} = phi i1 [{}, {}], [{}, {}]",
+                            flag, entry.assigned.as_i1(), before_label, latch.assigned.as_i1(), latch_label
+                        ));
+                    }
+                }
+                self.blocks[header].phis = phis;
+
+                self.vars = at_header;
+                self.start(done);
+            }
+        }
+    }
+}
+
+/// Escapes `text` for an LLVM `c"..."` string
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(result, "\\{:02X}", byte).unwrap(),
+            0x20..=0x7e => result.push(byte as char),
+            _ => write!(result, "\\{:02X}", byte).unwrap()
+        }
+    }
+    return result;
+}
+
+/// The LLVM IR module for `program`
+pub fn generate(program: &Program) -> String {
+    let mut gen = Generator{
+        blocks: vec![BasicBlock{label: "entry".to_string(), phis: Vec::new(), body: Vec::new()}],
+        order: vec![0],
+        current: 0,
+        names: 0,
+        vars: Vars::new(),
+        messages: Vec::new()
+    };
+    gen.statements(&program.program);
+    gen.emit("ret i32 0".to_string());
+
+    let mut result = String::from("; Generated from an .xa program\n\n");
+    writeln!(result, "@.format = private unnamed_addr constant [4 x i8] c\"%d\\0A\\00\"").unwrap();
+    for (i, message) in gen.messages.iter().enumerate() {
+        writeln!(result, "@.message.{} = private unnamed_addr constant [{} x i8] c\"{}\"", i, message.len(), escape(message)).unwrap();
+    }
+
+    writeln!(result, "\n{}", DECLARATIONS).unwrap();
+
+    writeln!(result, "define i32 @main() {{").unwrap();
+    for (i, index) in gen.order.iter().enumerate() {
+        let block = &gen.blocks[*index];
+        if i > 0 {
+            result.push('\n');
+        }
+        writeln!(result, "{}:", block.label).unwrap();
+        for line in block.phis.iter().chain(block.body.iter()) {
+            writeln!(result, "  {}", line).unwrap();
+        }
+    }
+    writeln!(result, "}}").unwrap();
+
+    return result;
+}
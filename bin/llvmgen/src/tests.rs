@@ -0,0 +1,166 @@
+use crate::*;
+
+use std::collections::HashSet;
+use std::fs;
+use std::process::Command;
+
+const CONTROL_FLOW: &str = "n = 3;
+REPEAT (n) {
+    n = n + 1;
+    IF (n == 4) {
+        OUTPUT 40;
+    } ELSEIF (n == 5) {
+        late = n;
+        OUTPUT 50;
+    } ELSE {
+        OUTPUT 0;
+    }
+    REPEAT (0) {
+        OUTPUT 1;
+    }
+}
+OUTPUT n;
+OUTPUT 7 - 9 * 2 >= 0 - 11 || 0;
+OUTPUT late;";
+
+/// Blocks of `main` by label, each a list of instructions
+fn blocks(ir: &str) -> Vec<(String, Vec<String>)> {
+    let main = ir.split("define i32 @main() {\n").nth(1).unwrap();
+    let mut blocks: Vec<(String, Vec<String>)> = Vec::new();
+
+    for line in main.lines().take_while(|l| *l != "}") {
+        if line.is_empty() {
+            continue;
+        }
+        match line.strip_prefix("  ") {
+            Some(instr) => blocks.last_mut().unwrap().1.push(instr.to_string()),
+            None => blocks.push((line.trim_end_matches(':').to_string(), Vec::new()))
+        }
+    }
+
+    blocks
+}
+
+/// Checks the things LLVM would reject: values defined twice, blocks that
+/// don't end in a terminator, phi nodes after other instructions and jumps
+/// to blocks that don't exist
+fn check_ssa(ir: &str) {
+    let blocks = blocks(ir);
+    let labels: HashSet<&str> = blocks.iter().map(|(l, _)| l.as_str()).collect();
+    let mut defined = HashSet::new();
+
+    for (label, instrs) in blocks.iter() {
+        let last = instrs.last().unwrap_or_else(|| panic!("{} is empty", label));
+        assert!(last.starts_with("br ") || last.starts_with("ret ") || last == "unreachable", "{} ends with {}", label, last);
+
+        let mut past_phis = false;
+        for instr in instrs.iter() {
+            if let Some((name, _)) = instr.split_once(" = ") {
+                assert!(defined.insert(name.to_string()), "{} is defined twice", name);
+            }
+
+            let is_phi = instr.contains(" = phi ");
+            assert!(!(is_phi && past_phis), "phi after other instructions in {}", label);
+            past_phis |= !is_phi;
+
+            for target in instr.split("label %").skip(1) {
+                let target = target.split([',', ' ']).next().unwrap();
+                assert!(labels.contains(target), "jump to missing block {}", target);
+            }
+        }
+    }
+}
+
+fn generate_source(source: &str) -> String {
+    generate(&parser::parse_source(source).unwrap())
+}
+
+#[test]
+fn straight_line_code_has_no_phis() {
+    let ir = generate_source(include_str!("../../../examples/moreops.xa"));
+    check_ssa(&ir);
+
+    assert!(!ir.contains(" phi "), "{}", ir);
+    assert!(!ir.contains("alloca"), "{}", ir);
+    // Variables are always assigned here so are never checked
+    assert!(!ir.contains("has not been assigned"), "{}", ir);
+}
+
+#[test]
+fn loops_get_phis() {
+    let ir = generate_source(include_str!("../../../examples/fibbonachi.xa"));
+    check_ssa(&ir);
+
+    let blocks = blocks(&ir);
+    let (_, header) = blocks.iter().find(|(l, _)| l.starts_with("repeat.")).unwrap();
+    let phis: Vec<&String> = header.iter().filter(|i| i.contains(" = phi i32 ")).collect();
+
+    // The counter and the three variables the body assigns
+    assert_eq!(phis.len(), 4, "{:?}", header);
+    assert!(phis.iter().any(|p| p.starts_with("%temp.")), "{:?}", header);
+    // temp starts out unassigned so whether it is has to be tracked too
+    assert!(header.iter().any(|i| i.starts_with("%temp.assigned.") && i.contains(" = phi i1 [false, ")), "{:?}", header);
+}
+
+#[test]
+fn if_arms_meet_in_phis() {
+    let ir = generate_source(CONTROL_FLOW);
+    check_ssa(&ir);
+
+    let blocks = blocks(&ir);
+    let (_, endif) = blocks.iter().find(|(l, _)| l.starts_with("endif.")).unwrap();
+
+    // late is only assigned in the ELSEIF arm, the others bring what it
+    // was at the top of the loop
+    let late = endif.iter().find(|i| i.starts_with("%late.assigned.")).unwrap();
+    assert!(late.contains(" = phi i1 [%late.assigned."), "{}", late);
+    assert!(late.contains(", [true, %then."), "{}", late);
+    assert!(endif.iter().any(|i| i.starts_with("%late.") && i.contains(" = phi i32 ")), "{:?}", endif);
+    assert!(ir.contains("c\"18:1: Variable \\22late\\22 has not been assigned\\0A\""), "{}", ir);
+}
+
+/// Runs the IR with `lli` when LLVM is installed, otherwise does nothing
+fn run_with_lli(name: &str, source: &str) -> Option<(Vec<i32>, String)> {
+    let version = Command::new("lli").arg("--version").output().ok()?;
+    let version = String::from_utf8_lossy(&version.stdout).to_string();
+    let major: u32 = version.split("version ").nth(1)?.split('.').next()?.parse().ok()?;
+
+    let file = std::env::temp_dir().join(format!("llvmgen-{}-{}.ll", std::process::id(), name));
+    fs::write(&file, generate_source(source)).unwrap();
+
+    let mut lli = Command::new("lli");
+    if major < 15 {
+        lli.arg("-opaque-pointers");
+    }
+    let run = lli.arg(&file).output().unwrap();
+    fs::remove_file(&file).unwrap();
+
+    let outputs = String::from_utf8(run.stdout).unwrap().lines().map(|l| l.parse().unwrap()).collect();
+    Some((outputs, String::from_utf8(run.stderr).unwrap()))
+}
+
+#[test]
+fn runs_with_llvm_when_installed() {
+    for (name, source) in [
+        ("fibbonachi", include_str!("../../../examples/fibbonachi.xa")),
+        ("moreops", include_str!("../../../examples/moreops.xa")),
+        ("control", CONTROL_FLOW)
+    ] {
+        let Some((outputs, errors)) = run_with_lli(name, source) else {
+            return;
+        };
+        let expected = interpreter::interpret(parser::parse_source(source).unwrap()).unwrap();
+        assert_eq!(outputs, expected, "{}", errors);
+    }
+
+    let (outputs, errors) = run_with_lli("negative", "OUTPUT 1;\nx = 0 - 2;\nREPEAT (x) {}").unwrap();
+    assert_eq!(outputs, vec![1]);
+    assert_eq!(errors, "3:1: REPEAT count is negative\n");
+
+    let (_, errors) = run_with_lli("unassigned", "IF (0) {\n    x = 1;\n}\nOUTPUT x;").unwrap();
+    assert_eq!(errors, "4:1: Variable \"x\" has not been assigned\n");
+
+    let (outputs, errors) = run_with_lli("assertion", "x = 2;\nASSERT (x > 1);\nASSERT_EQ (x * 2, 4);\nOUTPUT x;\nASSERT_EQ (x, 3);").unwrap();
+    assert_eq!(outputs, vec![2]);
+    assert_eq!(errors, "5:1: assertion failed\n");
+}
@@ -0,0 +1,888 @@
+//! A language server for `.xa` files, speaking JSON-RPC over stdin and
+//! stdout. Everything is worked out from the text the editor sends, so
+//! unsaved buffers are checked as they are typed.
+//!
+//! Lines and characters count from 0 here, as they do in the protocol,
+//! where `Location` counts from 1.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use analysis::{Interval, Intervals, Uninitialised};
+use language::*;
+use lexer::{Lexer, TOKEN_TYPE};
+
+/// A JSON value. Objects keep their fields in the order they were written
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>)
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// The field called `key`, null if there isn't one
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map_or(&NULL, |(_, value)| value),
+            _ => &NULL
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Json::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64 => Some(*n as u32),
+            _ => None
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser{chars: text.chars().collect(), pos: 0};
+        let value = parser.value()?;
+        parser.skip_space();
+
+        if parser.pos != parser.chars.len() {
+            return Err(format!("unexpected {:?} after the value", parser.chars[parser.pos]));
+        }
+        return Ok(value);
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize
+}
+
+impl JsonParser {
+    fn skip_space(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.chars.get(self.pos).copied().ok_or("unexpected end of JSON")?;
+        self.pos += 1;
+        return Ok(c);
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        for c in expected.chars() {
+            let found = self.next()?;
+            if found != c {
+                return Err(format!("expected {:?}, found {:?}", c, found));
+            }
+        }
+        return Ok(());
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_space();
+
+        match self.chars.get(self.pos).copied() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                self.skip_space();
+                if self.chars.get(self.pos) == Some(&']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_space();
+                    match self.next()? {
+                        ',' => (),
+                        ']' => return Ok(Json::Array(values)),
+                        c => return Err(format!("expected ',' or ']', found {:?}", c))
+                    }
+                }
+            },
+            Some('{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_space();
+                if self.chars.get(self.pos) == Some(&'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_space();
+                    let key = self.string()?;
+                    self.skip_space();
+                    self.expect(":")?;
+                    fields.push((key, self.value()?));
+                    self.skip_space();
+                    match self.next()? {
+                        ',' => (),
+                        '}' => return Ok(Json::Object(fields)),
+                        c => return Err(format!("expected ',' or '}}', found {:?}", c))
+                    }
+                }
+            },
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                text.parse().map(Json::Number).map_err(|_| format!("bad number {:?}", text))
+            },
+            Some(c) => Err(format!("unexpected {:?}", c)),
+            None => Err("unexpected end of JSON".to_string())
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).map(|_| self.next()).collect::<Result<_, _>>()?;
+        return u32::from_str_radix(&digits, 16).map_err(|_| format!("bad escape \\u{}", digits));
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut result = String::new();
+
+        loop {
+            match self.next()? {
+                '"' => return Ok(result),
+                '\\' => {
+                    let c = match self.next()? {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let mut code = self.hex()?;
+                            // Characters outside the BMP come as two halves
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect("\\u")?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (self.hex()? - 0xdc00);
+                            }
+                            char::from_u32(code).ok_or(format!("bad character {:x}", code))?
+                        },
+                        c => c
+                    };
+                    result.push(c);
+                },
+                c => result.push(c)
+            }
+        }
+    }
+}
+
+/// Reads the body of the next message, `None` once the input has ended
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message has no Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    return String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    return output.flush();
+}
+
+/// A token and the characters it covers on its line. `start` and `end`
+/// count bytes as the lexer does, `utf16` is the same span counted in UTF-16
+/// code units as the protocol does
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TOKEN_TYPE,
+    text: String,
+    line: u32,
+    start: u32,
+    end: u32,
+    utf16: (u32, u32)
+}
+
+/// How many UTF-16 code units the first `bytes` bytes of `line` are
+fn utf16(line: &str, bytes: usize) -> u32 {
+    return line.char_indices().take_while(|(i, _)| *i < bytes).map(|(_, c)| c.len_utf16() as u32).sum();
+}
+
+impl Token {
+    fn location(&self) -> Location {
+        Location::new(self.line + 1, self.start + 1)
+    }
+}
+
+/// Every token up to the end or the first one the lexer doesn't know
+fn tokens(source: &str) -> Vec<Token> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut lexer = Lexer::from_source(source.as_bytes());
+    let mut found: Vec<Token> = Vec::new();
+
+    loop {
+        let tok = lexer.next_token();
+        if matches!(tok.tok_type, TOKEN_TYPE::EOF_TOK | TOKEN_TYPE::INVALID) {
+            break;
+        }
+        let (line, start) = (tok.line as u32 - 1, tok.column as u32 - 1);
+        found.push(Token{kind: tok.tok_type, text: String::new(), line, start, end: start, utf16: (0, 0)});
+    }
+
+    // A token runs up to the next one on its line, less any space in
+    // between. The last one on a line stops before a comment, or at its
+    // closing quote if it is a string, which can have `//` in it. The lexer
+    // cuts long names short so they aren't used
+    for i in 0..found.len() {
+        let line = lines.get(found[i].line as usize).copied().unwrap_or("");
+        let start = found[i].start as usize;
+        let rest = line.get(start..).unwrap_or("");
+        let stop = match found.get(i + 1) {
+            Some(next) if next.line == found[i].line => next.start as usize,
+            _ if found[i].kind == TOKEN_TYPE::STR_LIT => start + rest.get(1..).and_then(|s| s.find('"')).map_or(rest.len(), |end| end + 2),
+            _ => start + rest.find("//").unwrap_or(rest.len())
+        };
+
+        let text = line.get(start..stop).unwrap_or("").trim_end().to_string();
+        found[i].end = found[i].start + text.len() as u32;
+        found[i].utf16 = (utf16(line, start), utf16(line, found[i].end as usize));
+        found[i].text = text;
+    }
+
+    // A name from an imported file, `lib.x`, is one variable
+    let mut merged: Vec<Token> = Vec::new();
+    for token in found {
+        let qualifies = merged.len() >= 2 && token.kind == TOKEN_TYPE::VAR && {
+            let (name, dot) = (&merged[merged.len() - 2], &merged[merged.len() - 1]);
+            name.kind == TOKEN_TYPE::VAR && dot.kind == TOKEN_TYPE::DOT && name.line == token.line && dot.line == token.line
+        };
+        match qualifies {
+            true => {
+                merged.pop();
+                let name = merged.last_mut().unwrap();
+                name.text = format!("{}.{}", name.text, token.text);
+                name.end = token.end;
+                name.utf16.1 = token.utf16.1;
+            },
+            false => merged.push(token)
+        }
+    }
+
+    return merged;
+}
+
+/// The token the cursor is in, or else just after
+fn token_at(tokens: &[Token], line: u32, character: u32) -> Option<usize> {
+    return tokens.iter().position(|t| t.line == line && t.utf16.0 <= character && character < t.utf16.1)
+        .or_else(|| tokens.iter().position(|t| t.line == line && t.utf16.1 == character));
+}
+
+/// Where a variable is first assigned
+fn definition<'a>(tokens: &'a [Token], var: &str) -> Option<&'a Token> {
+    return tokens.windows(2)
+        .find(|w| w[0].kind == TOKEN_TYPE::VAR && w[0].text == var && w[1].kind == TOKEN_TYPE::ASSIGN)
+        .map(|w| &w[0]);
+}
+
+fn range(line: u32, start: u32, end: u32) -> Json {
+    let position = |character: u32| Json::object([("line", line.into()), ("character", character.into())]);
+    return Json::object([("start", position(start)), ("end", position(end))]);
+}
+
+fn token_range(token: &Token) -> Json {
+    return range(token.line, token.utf16.0, token.utf16.1);
+}
+
+/// The token starting at `loc`, or a character if there isn't one
+fn location_range(source: &str, tokens: &[Token], loc: Location) -> Json {
+    let (line, start) = (loc.line.saturating_sub(1), loc.column.saturating_sub(1));
+    return match tokens.iter().find(|t| t.line == line && t.start == start) {
+        Some(token) => token_range(token),
+        None => {
+            let start = utf16(source.lines().nth(line as usize).unwrap_or(""), start as usize);
+            range(line, start, start + 1)
+        }
+    };
+}
+
+/// The message of something displayed as `line:column: message`
+fn message(displayed: String) -> String {
+    return match displayed.split_once(": ") {
+        Some((_, message)) => message.to_string(),
+        None => displayed
+    };
+}
+
+const ERROR: u32 = 1;
+const WARNING: u32 = 2;
+
+/// The file a `file://` URI names, unsaved buffers don't have one
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%').then(|| path.get(i + 1..i + 3)).flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    return String::from_utf8(decoded).ok().map(PathBuf::from);
+}
+
+/// Where the document at `uri` is saved, which is what it imports relative to
+fn directory(uri: &str) -> Option<PathBuf> {
+    return file_path(uri)?.parent().map(Path::to_path_buf);
+}
+
+/// Parses `source` as the document at `uri`, reading what it imports.
+/// Unsaved buffers can't import. The loader says which file errors are in
+fn parse(uri: &str, source: &str) -> (parser::Loader, Result<Program, parser::ParseError>) {
+    let mut loader = parser::Loader::new();
+    let parsed = match directory(uri) {
+        Some(dir) => loader.load_source(source, &dir),
+        None => parser::parse_source(source)
+    };
+    return (loader, parsed);
+}
+
+/// Parse errors, lint warnings and what the analyses find for `source`
+pub fn diagnostics(uri: &str, source: &str) -> Vec<Json> {
+    let tokens = tokens(source);
+    let mut found = Vec::new();
+    let mut diagnostic = |range: Json, severity: u32, code: Option<&str>, message: String| {
+        let mut fields = vec![
+            ("range".to_string(), range),
+            ("severity".to_string(), severity.into()),
+            ("source".to_string(), "xa".into()),
+            ("message".to_string(), message.into())
+        ];
+        if let Some(code) = code {
+            fields.push(("code".to_string(), code.into()));
+        }
+        found.push(Json::Object(fields));
+    };
+
+    let config = match file_path(uri).map(|path| lint::Config::find(&path)) {
+        Some(Err(e)) => {
+            diagnostic(range(0, 0, 0), ERROR, None, e);
+            lint::Config::default()
+        },
+        Some(Ok(config)) => config,
+        None => lint::Config::default()
+    };
+
+    let (loader, parsed) = parse(uri, source);
+    let linted = match directory(uri) {
+        Some(dir) => lint::lint_in(source, &dir, &config),
+        None => lint::lint(source, &config)
+    };
+
+    match linted {
+        Ok(warnings) => {
+            for warning in warnings {
+                diagnostic(location_range(source, &tokens, warning.loc), WARNING, Some(warning.code), warning.message);
+            }
+        },
+        // An error in an imported file is shown at the start, saying where
+        Err(e) if e.location().is_some_and(|loc| loc.file != 0) => diagnostic(range(0, 0, 0), ERROR, None, loader.describe(&e)),
+        Err(e) => diagnostic(location_range(source, &tokens, e.location().unwrap_or_default()), ERROR, None, message(e.to_string()))
+    }
+
+    // What is found in imported files is shown when they are open
+    if let Ok(program) = parsed {
+        for read in analysis::uninitialised_reads(&program).into_iter().filter(|r| r.loc.file == 0) {
+            let severity = if read.kind == Uninitialised::Definitely { ERROR } else { WARNING };
+            diagnostic(location_range(source, &tokens, read.loc), severity, None, message(read.to_string()));
+        }
+        for problem in analysis::intervals(&program).problems.into_iter().filter(|p| p.loc.file == 0) {
+            diagnostic(location_range(source, &tokens, problem.loc), WARNING, None, message(problem.to_string()));
+        }
+    }
+
+    let start = |d: &Json| {
+        let start = d.get("range").get("start");
+        (start.get("line").as_u32(), start.get("character").as_u32())
+    };
+    found.sort_by_key(start);
+    return found;
+}
+
+/// Every range `var` has anywhere in the program
+fn overall_range(found: &Intervals, var: &str) -> Option<Interval> {
+    return found.before.values().chain(found.after.iter())
+        .filter_map(|ranges| ranges.get(var))
+        .fold(None, |overall: Option<Interval>, interval| Some(overall.map_or(*interval, |o| o.join(interval))));
+}
+
+fn variable_hover(uri: &str, source: &str, tokens: &[Token], index: usize) -> String {
+    let token = &tokens[index];
+    let var = &token.text;
+    let Ok(program) = parse(uri, source).1 else {
+        return format!("variable {}", var);
+    };
+    let found = analysis::intervals(&program);
+    let loc = token.location();
+
+    let assigned = tokens.get(index + 1).is_some_and(|t| t.kind == TOKEN_TYPE::ASSIGN);
+    // The statement the token is in, when one starts earlier on its line
+    let statement = found.before.range(..=loc).next_back().filter(|(start, _)| start.line == loc.line);
+
+    let detail = match (assigned, statement) {
+        (true, _) => match found.assigned.get(&loc) {
+            Some(interval) => format!("assigned {} here", interval),
+            None => "never assigned here, as this is never reached or always fails".to_string()
+        },
+        (false, Some((_, ranges))) => match ranges.get(var) {
+            Some(interval) => format!("{} here", interval),
+            None => "not assigned yet here".to_string()
+        },
+        (false, None) => match overall_range(&found, var) {
+            Some(interval) => format!("{} across the program", interval),
+            None => "never assigned".to_string()
+        }
+    };
+
+    return format!("variable {}\n\n{}", var, detail);
+}
+
+pub fn hover(uri: &str, source: &str, line: u32, character: u32) -> Json {
+    let tokens = tokens(source);
+    let Some(index) = token_at(&tokens, line, character) else {
+        return Json::Null;
+    };
+    let token = &tokens[index];
+
+    let text = match token.kind {
+        TOKEN_TYPE::VAR => variable_hover(uri, source, &tokens, index),
+        TOKEN_TYPE::INT_LIT => format!("number {}", token.text),
+        TOKEN_TYPE::STR_LIT => format!("string {}", token.text),
+        TOKEN_TYPE::IF | TOKEN_TYPE::ELSEIF | TOKEN_TYPE::ELSE | TOKEN_TYPE::REPEAT | TOKEN_TYPE::OUTPUT |
+        TOKEN_TYPE::ASSERT | TOKEN_TYPE::ASSERT_EQ | TOKEN_TYPE::TEST | TOKEN_TYPE::IMPORT |
+        TOKEN_TYPE::EXPOSING => format!("keyword {}", token.text),
+        TOKEN_TYPE::LBRA | TOKEN_TYPE::RBRA | TOKEN_TYPE::LPAR | TOKEN_TYPE::RPAR | TOKEN_TYPE::SC |
+        TOKEN_TYPE::COMMA | TOKEN_TYPE::DOT => format!("punctuation {}", token.text),
+        _ => format!("operator {}", token.text)
+    };
+
+    let contents = Json::object([("kind", "plaintext".into()), ("value", text.into())]);
+    return Json::object([("contents", contents), ("range", token_range(token))]);
+}
+
+/// Every use of the variable at the cursor, including where it's first
+/// assigned if `declaration`
+pub fn references(uri: &str, source: &str, line: u32, character: u32, declaration: bool) -> Json {
+    let tokens = tokens(source);
+    let Some(token) = token_at(&tokens, line, character).map(|i| &tokens[i]).filter(|t| t.kind == TOKEN_TYPE::VAR) else {
+        return Json::Null;
+    };
+    let first = definition(&tokens, &token.text);
+
+    return Json::Array(tokens.iter()
+        .filter(|t| t.kind == TOKEN_TYPE::VAR && t.text == token.text)
+        .filter(|t| declaration || Some(*t) != first)
+        .map(|t| Json::object([("uri", uri.into()), ("range", token_range(t))]))
+        .collect());
+}
+
+/// Where the variable at the cursor is first assigned
+pub fn goto_definition(uri: &str, source: &str, line: u32, character: u32) -> Json {
+    let tokens = tokens(source);
+    let Some(token) = token_at(&tokens, line, character).map(|i| &tokens[i]).filter(|t| t.kind == TOKEN_TYPE::VAR) else {
+        return Json::Null;
+    };
+
+    return match definition(&tokens, &token.text) {
+        Some(first) => Json::object([("uri", uri.into()), ("range", token_range(first))]),
+        None => Json::Null
+    };
+}
+
+const SYMBOL_VARIABLE: u32 = 13;
+
+/// A symbol for each variable, where it is first assigned
+pub fn document_symbols(uri: &str, source: &str) -> Json {
+    let tokens = tokens(source);
+    let found = parse(uri, source).1.ok().map(|program| analysis::intervals(&program));
+    let mut symbols: BTreeMap<&str, Json> = BTreeMap::new();
+
+    for token in tokens.iter().filter(|t| t.kind == TOKEN_TYPE::VAR) {
+        if symbols.contains_key(token.text.as_str()) {
+            continue;
+        }
+
+        let at = token_range(definition(&tokens, &token.text).unwrap_or(token));
+        let detail = found.as_ref().and_then(|found| overall_range(found, &token.text))
+            .map_or(String::new(), |interval| interval.to_string());
+        symbols.insert(&token.text, Json::object([
+            ("name", token.text.as_str().into()),
+            ("detail", detail.into()),
+            ("kind", SYMBOL_VARIABLE.into()),
+            ("range", at.clone()),
+            ("selectionRange", at)
+        ]));
+    }
+
+    return Json::Array(symbols.into_values().collect());
+}
+
+/// `source` laid out the standard way: four spaces of indent for each
+/// block, lines carried on from the one before indented once more and one
+/// space between tokens, except around the `.` of `lib.x`. Line breaks and
+/// comments are kept. `None` if it doesn't parse
+pub fn format(source: &str) -> Option<String> {
+    parser::check_syntax(source).ok()?;
+
+    let tokens = tokens(source);
+    let mut tokens = tokens.iter().peekable();
+    let mut formatted = String::new();
+    let mut depth = 0;
+    // Whether the last code didn't finish a statement
+    let mut continues = false;
+
+    for (number, line) in source.lines().enumerate() {
+        let mut on_line = Vec::new();
+        while let Some(token) = tokens.next_if(|t| t.line as usize == number) {
+            on_line.push(token);
+        }
+
+        let mut code = String::new();
+        for (i, token) in on_line.iter().enumerate() {
+            let glued = i == 0 || matches!(token.kind, TOKEN_TYPE::SC | TOKEN_TYPE::RPAR | TOKEN_TYPE::COMMA | TOKEN_TYPE::DOT)
+                || matches!(on_line[i - 1].kind, TOKEN_TYPE::LPAR | TOKEN_TYPE::DOT);
+            if !glued {
+                code.push(' ');
+            }
+            code.push_str(&token.text);
+        }
+        // A `//` before the end of the code is inside a string
+        let code_end = on_line.last().map_or(0, |t| t.end as usize);
+        let comment = line.get(code_end..).and_then(|rest| rest.find("//").map(|start| rest[start..].trim_end()));
+
+        let closes = on_line.first().is_some_and(|t| t.kind == TOKEN_TYPE::RBRA);
+        let indent = depth - closes as usize + continues as usize;
+        for token in on_line.iter() {
+            match token.kind {
+                TOKEN_TYPE::LBRA => depth += 1,
+                TOKEN_TYPE::RBRA => depth -= 1,
+                _ => ()
+            }
+        }
+        if let Some(last) = on_line.last() {
+            continues = !matches!(last.kind, TOKEN_TYPE::SC | TOKEN_TYPE::LBRA | TOKEN_TYPE::RBRA);
+        }
+
+        let text = match comment {
+            Some(comment) if code.is_empty() => comment.to_string(),
+            Some(comment) => format!("{} {}", code, comment),
+            None => code
+        };
+        if !text.is_empty() {
+            formatted.push_str(&"    ".repeat(indent));
+            formatted.push_str(&text);
+        }
+        formatted.push('\n');
+    }
+
+    if !source.ends_with('\n') {
+        formatted.pop();
+    }
+    return Some(formatted);
+}
+
+/// Edits that replace the whole document with its formatted text
+fn formatting(source: &str) -> Json {
+    let Some(formatted) = format(source).filter(|f| f != source) else {
+        return Json::Array(Vec::new());
+    };
+
+    let last = source.split('\n').count() as u32 - 1;
+    let end = source.split('\n').next_back().unwrap().encode_utf16().count() as u32;
+    let whole = Json::object([
+        ("start", Json::object([("line", 0.into()), ("character", 0.into())])),
+        ("end", Json::object([("line", last.into()), ("character", end.into())]))
+    ]);
+    return Json::Array(vec![Json::object([("range", whole), ("newText", formatted.into())])]);
+}
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+fn error_response(id: Json, code: i32, message: String) -> Json {
+    let error = Json::object([("code", Json::Number(code as f64)), ("message", message.into())]);
+    return Json::object([("jsonrpc", "2.0".into()), ("id", id), ("error", error)]);
+}
+
+fn capabilities() -> Json {
+    let capabilities = Json::object([
+        // The whole text is sent on every change
+        ("textDocumentSync", 1.into()),
+        ("definitionProvider", true.into()),
+        ("referencesProvider", true.into()),
+        ("hoverProvider", true.into()),
+        ("documentSymbolProvider", true.into()),
+        ("documentFormattingProvider", true.into())
+    ]);
+    let info = Json::object([("name", "xa-lsp".into()), ("version", env!("CARGO_PKG_VERSION").into())]);
+    return Json::object([("capabilities", capabilities), ("serverInfo", info)]);
+}
+
+/// The open documents and where the session is up to
+#[derive(Default)]
+pub struct Server {
+    documents: BTreeMap<String, String>,
+    shut_down: bool,
+    exit_code: Option<i32>
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What to exit with, once the client has said to
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    fn publish(&self, uri: &str) -> Json {
+        let diagnostics = self.documents.get(uri).map_or(Vec::new(), |source| diagnostics(uri, source));
+        let params = Json::object([("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))]);
+        return Json::object([("jsonrpc", "2.0".into()), ("method", "textDocument/publishDiagnostics".into()), ("params", params)]);
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        if method == "exit" {
+            self.exit_code = Some(if self.shut_down { 0 } else { 1 });
+            return Vec::new();
+        }
+
+        let document = params.get("textDocument");
+        let Some(uri) = document.get("uri").as_str() else {
+            return Vec::new();
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = document.get("text").as_str().unwrap_or("");
+                self.documents.insert(uri.to_string(), text.to_string());
+            },
+            "textDocument/didChange" => {
+                let Json::Array(changes) = params.get("contentChanges") else {
+                    return Vec::new();
+                };
+                let Some(text) = changes.last().and_then(|change| change.get("text").as_str()) else {
+                    return Vec::new();
+                };
+                self.documents.insert(uri.to_string(), text.to_string());
+            },
+            // Closed documents have their diagnostics cleared
+            "textDocument/didClose" => { self.documents.remove(uri); },
+            _ => return Vec::new()
+        }
+
+        return vec![self.publish(uri)];
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i32, String)> {
+        match method {
+            "initialize" => return Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                return Ok(Json::Null);
+            },
+            "textDocument/definition" | "textDocument/references" | "textDocument/hover" |
+            "textDocument/documentSymbol" | "textDocument/formatting" => (),
+            _ => return Err((METHOD_NOT_FOUND, format!("unknown method {}", method)))
+        }
+
+        let uri = params.get("textDocument").get("uri").as_str().ok_or((INVALID_PARAMS, "no textDocument.uri".to_string()))?;
+        let position = params.get("position");
+        let (line, character) = (position.get("line").as_u32().unwrap_or(0), position.get("character").as_u32().unwrap_or(0));
+
+        let Some(source) = self.documents.get(uri) else {
+            return Err((INVALID_PARAMS, format!("{} isn't open", uri)));
+        };
+
+        return match method {
+            "textDocument/definition" => Ok(goto_definition(uri, source, line, character)),
+            "textDocument/references" => {
+                let declaration = params.get("context").get("includeDeclaration") == &Json::Bool(true);
+                Ok(references(uri, source, line, character, declaration))
+            },
+            "textDocument/hover" => Ok(hover(uri, source, line, character)),
+            "textDocument/documentSymbol" => Ok(document_symbols(uri, source)),
+            _ => Ok(formatting(source))
+        };
+    }
+
+    /// Handles one message from the client, giving back the messages to send
+    /// in reply
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        // Replies to anything the server asked are ignored
+        let Some(method) = message.get("method").as_str() else {
+            return Vec::new();
+        };
+        let params = message.get("params");
+        let id = message.get("id");
+
+        if *id == Json::Null {
+            return self.notification(method, params);
+        }
+        if self.shut_down {
+            return vec![error_response(id.clone(), INVALID_REQUEST, "the server has been shut down".to_string())];
+        }
+
+        return match self.request(method, params) {
+            Ok(result) => vec![Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)])],
+            Err((code, message)) => vec![error_response(id.clone(), code, message)]
+        };
+    }
+}
+
+/// Answers the client on `input` until it says to exit, giving back the
+/// code to exit with
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server::new();
+
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match Json::parse(&body) {
+            Ok(message) => server.handle(&message),
+            Err(e) => vec![error_response(Json::Null, PARSE_ERROR, e)]
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+
+        if let Some(code) = server.exit_code() {
+            return Ok(code);
+        }
+    }
+
+    // The client went away without saying to exit
+    return Ok(1);
+}
@@ -0,0 +1,202 @@
+use crate::*;
+
+use std::fs;
+
+const SOURCE: &str = "x = 1;
+REPEAT (5) {
+  x = x * 3; // triple
+}
+IF (x > 100) {
+OUTPUT 1000/x;
+}
+z=x;
+";
+
+fn request(server: &mut Server, id: u32, method: &str, params: Json) -> Json {
+    let mut replies = server.handle(&Json::object([("jsonrpc", "2.0".into()), ("id", id.into()), ("method", method.into()), ("params", params)]));
+    assert_eq!(replies.len(), 1);
+    return replies.remove(0);
+}
+
+fn at(line: u32, character: u32) -> Json {
+    Json::object([
+        ("textDocument", Json::object([("uri", "untitled:1".into())])),
+        ("position", Json::object([("line", line.into()), ("character", character.into())]))
+    ])
+}
+
+#[test]
+fn json_round_trips() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"quote \" slash \\ line \n tab \t é 😀","c":{}}"#;
+    let value = Json::parse(text).unwrap();
+
+    assert_eq!(value.get("a"), &Json::Array(vec![1.into(), Json::Number(-2.5), true.into(), Json::Null]));
+    assert_eq!(value.get("b").as_str(), Some("quote \" slash \\ line \n tab \t é 😀"));
+    assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+    assert_eq!(value.get("missing"), &Json::Null);
+
+    assert!(Json::parse("{\"a\" 1}").is_err());
+    assert!(Json::parse("[1, 2").is_err());
+    assert!(Json::parse("1 2").is_err());
+}
+
+#[test]
+fn diagnostics_come_from_every_check() {
+    let found = diagnostics("untitled:1", "a = 1;\nb = 2147483647;\nOUTPUT b + a;\nOUTPUT c;");
+    let messages: Vec<_> = found.iter().map(|d| (d.get("range").get("start").get("line").as_u32().unwrap(), d.get("message").as_str().unwrap())).collect();
+    assert_eq!(messages, vec![
+        (2, "addition of 2147483647 and 1 can overflow"),
+        (3, "Variable \"c\" is definitely uninitialised")
+    ]);
+
+    // A parse error is all there is to say
+    let found = diagnostics("untitled:1", "x = 1;\nOUTPUT x +;");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get("severity"), &Json::from(ERROR));
+    assert_eq!(found[0].get("range"), &range(1, 10, 11));
+
+    let found = diagnostics("untitled:1", "x = 1;\nx = x;");
+    assert_eq!(found[0].get("code").as_str(), Some("L002"));
+    assert_eq!(found[0].get("range"), &range(1, 0, 1));
+}
+
+#[test]
+fn definitions_and_references() {
+    let mut server = Server::new();
+    let open = Json::object([("textDocument", Json::object([("uri", "untitled:1".into()), ("text", SOURCE.into())]))]);
+    let published = server.handle(&Json::object([("jsonrpc", "2.0".into()), ("method", "textDocument/didOpen".into()), ("params", open)]));
+    assert_eq!(published[0].get("method").as_str(), Some("textDocument/publishDiagnostics"));
+
+    // From the x divided by to where x is first assigned
+    let reply = request(&mut server, 1, "textDocument/definition", at(5, 12));
+    assert_eq!(reply.get("result").get("range"), &range(0, 0, 1));
+
+    let Json::Object(mut params) = at(7, 2) else { unreachable!() };
+    params.push(("context".to_string(), Json::object([("includeDeclaration", false.into())])));
+    let reply = request(&mut server, 2, "textDocument/references", Json::Object(params));
+    let Json::Array(found) = reply.get("result") else { panic!("{}", reply) };
+    let lines: Vec<_> = found.iter().map(|r| r.get("range").get("start").get("line").as_u32().unwrap()).collect();
+    assert_eq!(lines, vec![2, 2, 4, 5, 7]);
+
+    let reply = request(&mut server, 3, "textDocument/definition", at(1, 2));
+    assert_eq!(reply.get("result"), &Json::Null);
+}
+
+/// Characters are counted in UTF-16 code units, the lexer counts bytes
+#[test]
+fn positions_after_wide_characters() {
+    let source = "TEST \"é😀\" { y = 1; OUTPUT y; }";
+    // `y` is at byte 16 but character 13
+    assert_eq!(hover("untitled:1", source, 0, 13).get("contents").get("value").as_str(), Some("variable y\n\nassigned 1 here"));
+    assert_eq!(hover("untitled:1", source, 0, 13).get("range"), &range(0, 13, 14));
+    let Json::Array(found) = references("untitled:1", source, 0, 27, true) else { panic!() };
+    assert_eq!(found.iter().map(|r| r.get("range").clone()).collect::<Vec<_>>(), vec![range(0, 13, 14), range(0, 27, 28)]);
+    assert_eq!(goto_definition("untitled:1", source, 0, 27).get("range"), &range(0, 13, 14));
+    // So are diagnostics, here on the OUTPUT reading z
+    assert_eq!(diagnostics("untitled:1", "TEST \"é\" { OUTPUT z; }")[0].get("range"), &range(0, 11, 17));
+}
+
+#[test]
+fn hover_shows_ranges() {
+    let hovered = |line, character| hover("untitled:1", SOURCE, line, character).get("contents").get("value").as_str().unwrap().to_string();
+
+    assert_eq!(hovered(2, 6), "variable x\n\n[1, 81] here");
+    assert_eq!(hovered(2, 2), "variable x\n\nassigned [3, 243] here");
+    // The IF narrows x in its arm
+    assert_eq!(hovered(5, 12), "variable x\n\n243 here");
+    assert_eq!(hovered(1, 8), "number 5");
+    assert_eq!(hovered(1, 0), "keyword REPEAT");
+    assert_eq!(hovered(2, 8), "operator *");
+    assert_eq!(hover("untitled:1", "TEST \"t\" {\n}", 0, 6).get("contents").get("value").as_str(), Some("string \"t\""));
+    assert_eq!(hover("untitled:1", SOURCE, 2, 14), Json::Null);
+
+    let symbols = document_symbols("untitled:1", SOURCE);
+    let Json::Array(symbols) = &symbols else { panic!("{}", symbols) };
+    assert_eq!(symbols.iter().map(|s| s.get("name").as_str().unwrap()).collect::<Vec<_>>(), vec!["x", "z"]);
+    assert_eq!(symbols[0].get("detail").as_str(), Some("[1, 243]"));
+}
+
+/// A saved document reads what it imports from next to it, and `lib.x` is
+/// one name
+#[test]
+fn imports_next_to_the_document() {
+    let dir = std::env::temp_dir().join(format!("lsp-imports-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lib.xa"), "x = 4;\n").unwrap();
+    let uri = format!("file://{}", dir.join("main.xa").display());
+    let source = "IMPORT \"lib.xa\";\ny = lib.x + 1;\nOUTPUT y;\n";
+
+    assert_eq!(diagnostics(&uri, source), Vec::new());
+    assert_eq!(hover(&uri, source, 1, 0).get("contents").get("value").as_str(), Some("variable y\n\nassigned 5 here"));
+    assert_eq!(hover(&uri, source, 1, 7).get("contents").get("value").as_str(), Some("variable lib.x\n\n4 here"));
+    let Json::Array(symbols) = document_symbols(&uri, source) else { panic!() };
+    assert_eq!(symbols.iter().map(|s| s.get("name").as_str().unwrap()).collect::<Vec<_>>(), vec!["lib.x", "y"]);
+
+    // An unsaved buffer has nowhere to import from
+    let found = diagnostics("untitled:1", source);
+    assert_eq!(found[0].get("message").as_str(), Some("IMPORT needs the program to be in a file"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn formats_keeping_comments_and_lines() {
+    assert_eq!(format(SOURCE).unwrap(), "x = 1;
+REPEAT (5) {
+    x = x * 3; // triple
+}
+IF (x > 100) {
+    OUTPUT 1000 / x;
+}
+z = x;
+");
+
+    let source = "IF(a){\n// note\nb=1;\n}ELSEIF(a<2){\nOUTPUT a\n+1;\n} ELSE {\n}";
+    assert_eq!(format(source).unwrap(), "IF (a) {\n    // note\n    b = 1;\n} ELSEIF (a < 2) {\n    OUTPUT a\n        + 1;\n} ELSE {\n}");
+    assert_eq!(format("OUTPUT ;"), None);
+    assert_eq!(format("TEST \"a b\"{\nASSERT_EQ(a,1) ;\n}").unwrap(), "TEST \"a b\" {\n    ASSERT_EQ (a, 1);\n}");
+    // `//` in a string isn't a comment
+    let formatted = format("TEST \"a // b\"{ // c\n}").unwrap();
+    assert_eq!(formatted, "TEST \"a // b\" { // c\n}");
+    assert_eq!(format(&formatted).unwrap(), formatted);
+    // Imports aren't read to format
+    assert_eq!(format("IMPORT \"missing.xa\" EXPOSING(a,b);\nlib . x=a;").unwrap(), "IMPORT \"missing.xa\" EXPOSING (a, b);\nlib.x = a;");
+    for example in [include_str!("../../../examples/fibbonachi.xa"), include_str!("../../../examples/moreops.xa")] {
+        assert_eq!(format(example).unwrap(), example);
+    }
+}
+
+/// A whole session over the wire, on a buffer that is never saved
+#[test]
+fn serves_a_session() {
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"untitled:2","text":"OUTPUT 1;"}}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"untitled:2"},"contentChanges":[{"text":"OUTPUT x;"}]}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/formatting","params":{"textDocument":{"uri":"untitled:2"}}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"unknown"}"#,
+        "not json",
+        r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#
+    ];
+    let input: String = messages.iter().map(|m| format!("Content-Length: {}\r\n\r\n{}", m.len(), m)).collect();
+
+    let mut output = Vec::new();
+    assert_eq!(serve(input.as_bytes(), &mut output).unwrap(), 0);
+
+    let mut output = output.as_slice();
+    let mut replies = Vec::new();
+    while let Some(body) = read_message(&mut output).unwrap() {
+        replies.push(Json::parse(&body).unwrap());
+    }
+
+    assert_eq!(replies.len(), 7);
+    assert_eq!(replies[0].get("result").get("capabilities").get("hoverProvider"), &Json::Bool(true));
+    assert_eq!(replies[1].get("params").get("diagnostics"), &Json::Array(Vec::new()));
+    let Json::Array(diagnostics) = replies[2].get("params").get("diagnostics") else { panic!("{}", replies[2]) };
+    assert_eq!(diagnostics[0].get("message").as_str(), Some("Variable \"x\" is definitely uninitialised"));
+    assert_eq!(replies[3].get("result"), &Json::Array(Vec::new()));
+    assert_eq!(replies[4].get("error").get("code"), &Json::Number(METHOD_NOT_FOUND as f64));
+    assert_eq!(replies[5].get("error").get("code"), &Json::Number(PARSE_ERROR as f64));
+    assert_eq!(replies[6].get("result"), &Json::Null);
+}
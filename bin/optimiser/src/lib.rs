@@ -0,0 +1,683 @@
+//! Passes that rewrite a program into one that outputs the same values, and
+//! fails with the same error at the same statement, doing less work.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use language::*;
+
+/// What is known about a variable at some point in the program. Variables
+/// that are missing have definitely not been assigned
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Fact {
+    Const(i32),
+    Assigned,
+    /// Assigned on some ways of getting here but not others
+    Maybe
+}
+
+type Facts = BTreeMap<String, Fact>;
+
+/// What is known where two ways through the program meet
+fn merge(a: &Facts, b: &Facts) -> Facts {
+    let mut merged = Facts::new();
+
+    for var in a.keys().chain(b.keys()) {
+        let fact = match (a.get(var), b.get(var)) {
+            (Some(Fact::Const(x)), Some(Fact::Const(y))) if x == y => Fact::Const(*x),
+            (Some(Fact::Const(_) | Fact::Assigned), Some(Fact::Const(_) | Fact::Assigned)) => Fact::Assigned,
+            _ => Fact::Maybe
+        };
+        merged.insert(var.clone(), fact);
+    }
+
+    return merged;
+}
+
+fn definitely_assigned(facts: &Facts) -> BTreeSet<String> {
+    facts.iter()
+        .filter(|(_, fact)| **fact != Fact::Maybe)
+        .map(|(var, _)| var.clone())
+        .collect()
+}
+
+/// Whether working out `exp` could fail when the variables in `assigned`
+/// are known to have values. Any arithmetic is taken as able to fail
+fn can_fail(exp: &Expression, assigned: &BTreeSet<String>) -> bool {
+    match exp {
+        Expression::Val(_) => false,
+        Expression::Var(var) => !assigned.contains(var),
+        Expression::BinOp(op, lhs, rhs) => match op {
+            Op::Add | Op::Sub | Op::Multiply | Op::Divide | Op::Remainder => true,
+            _ => can_fail(lhs, assigned) || can_fail(rhs, assigned)
+        }
+    }
+}
+
+/// Walks a program forwards keeping `Facts`, folding as it goes and, if
+/// `propagate` is set, replacing variables known to hold a constant with it
+struct Folder {
+    propagate: bool
+}
+
+impl Folder {
+    /// Folds `exp` from the bottom up. Operations on constants that would
+    /// fail are left for the run to fail on
+    fn exp(&self, exp: Expression, facts: &Facts) -> Expression {
+        let (op, lhs, rhs) = match exp {
+            Expression::Var(var) => return match facts.get(&var) {
+                Some(Fact::Const(value)) if self.propagate => Expression::Val(*value),
+                _ => Expression::Var(var)
+            },
+            Expression::BinOp(op, lhs, rhs) => (op, self.exp(*lhs, facts), self.exp(*rhs, facts)),
+            _ => return exp
+        };
+
+        match (op, &lhs, &rhs) {
+            (_, Expression::Val(a), Expression::Val(b)) => {
+                if let Ok(value) = op.apply(*a, *b) {
+                    return Expression::Val(value);
+                }
+            },
+            (Op::Add, _, Expression::Val(0)) |
+            (Op::Sub, _, Expression::Val(0)) |
+            (Op::Multiply, _, Expression::Val(1)) |
+            (Op::Divide, _, Expression::Val(1)) => return lhs,
+            (Op::Add, Expression::Val(0), _) |
+            (Op::Multiply, Expression::Val(1), _) => return rhs,
+            // Only when nothing is lost by not working the sides out
+            (Op::Sub, _, _) if lhs == rhs && !can_fail(&lhs, &definitely_assigned(facts)) => return Expression::Val(0),
+            _ => ()
+        }
+
+        return Expression::BinOp(op, Box::new(lhs), Box::new(rhs));
+    }
+
+    /// Folds every statement of `block` into `out`, leaving `facts` as they
+    /// are once the block has run
+    fn block(&self, block: Block, facts: &mut Facts, out: &mut Vec<Statement>) {
+        for stmt in block.statements {
+            match stmt {
+                Statement::AssignStatement { var, exp, loc } => {
+                    let exp = self.exp(exp, facts);
+                    let fact = match exp {
+                        Expression::Val(value) => Fact::Const(value),
+                        _ => Fact::Assigned
+                    };
+                    facts.insert(var.clone(), fact);
+                    out.push(Statement::AssignStatement{var, exp, loc});
+                },
+                Statement::OutputStatement { to_output, loc } => {
+                    out.push(Statement::OutputStatement{to_output: self.exp(to_output, facts), loc});
+                },
+                Statement::AssertStatement { exp, expected, loc } => {
+                    let exp = self.exp(exp, facts);
+                    let expected = expected.map(|expected| self.exp(expected, facts));
+                    // One that is known to hold has nothing left to check
+                    let holds = match (&exp, &expected) {
+                        (Expression::Val(value), None) => *value != 0,
+                        (Expression::Val(value), Some(Expression::Val(expected))) => value == expected,
+                        _ => false
+                    };
+                    if !holds {
+                        out.push(Statement::AssertStatement{exp, expected, loc});
+                    }
+                },
+                Statement::RepeatStatement { times, body, loc } => self.repeat(times, body, loc, facts, out),
+                stmt @ Statement::IfStatement { .. } => self.if_chain(stmt, facts, out)
+            }
+        }
+    }
+
+    fn repeat(&self, times: Expression, body: Block, loc: Location, facts: &mut Facts, out: &mut Vec<Statement>) {
+        let times = self.exp(times, facts);
+        if times == Expression::Val(0) {
+            return;
+        }
+
+        // What holds at the top of the body has to hold however many times
+        // round it has been, so keep merging in what the body leaves until
+        // nothing changes
+        let mut top = facts.clone();
+        loop {
+            let mut after = top.clone();
+            self.block(body.clone(), &mut after, &mut Vec::new());
+            let merged = merge(&top, &after);
+            if merged == top {
+                break;
+            }
+            top = merged;
+        }
+
+        let mut after = top.clone();
+        let mut statements = Vec::new();
+        self.block(body, &mut after, &mut statements);
+
+        // A count that is known not to be negative can't fail, so a loop
+        // that does nothing can go
+        if let (Expression::Val(count), true) = (&times, statements.is_empty()) {
+            if *count >= 0 {
+                return;
+            }
+        }
+
+        *facts = match times {
+            Expression::Val(count) if count > 0 => after,
+            _ => top
+        };
+        out.push(Statement::RepeatStatement{times, body: Block{statements}, loc});
+    }
+
+    /// Arms whose condition is a constant are taken out or, if always taken,
+    /// become the ELSE. An IF that is left with only an ELSE is replaced by
+    /// its statements, and one that can't fail and does nothing goes
+    fn if_chain(&self, stmt: Statement, facts: &mut Facts, out: &mut Vec<Statement>) {
+        let Statement::IfStatement { condition, body, else_if, mut else_body, loc } = stmt else {
+            unreachable!("only called with IF statements");
+        };
+
+        // Conditions are all worked out with what is known before the IF
+        let before = facts.clone();
+        let mut arms = Vec::new();
+
+        for (condition, block) in std::iter::once((condition, body)).chain(else_if) {
+            match self.exp(condition, &before) {
+                Expression::Val(0) => (),
+                Expression::Val(_) => {
+                    else_body = block;
+                    break;
+                },
+                condition => arms.push((condition, block))
+            }
+        }
+
+        let mut after: Option<Facts> = None;
+        let mut fold_arm = |block: Block| {
+            let mut arm_facts = before.clone();
+            let mut statements = Vec::new();
+            self.block(block, &mut arm_facts, &mut statements);
+            after = Some(match after.take() {
+                Some(a) => merge(&a, &arm_facts),
+                None => arm_facts
+            });
+            Block{statements}
+        };
+
+        if arms.is_empty() {
+            let else_body = fold_arm(else_body);
+            out.extend(else_body.statements);
+        } else {
+            let mut arms: Vec<(Expression, Block)> = arms.into_iter().map(|(c, b)| (c, fold_arm(b))).collect();
+            let else_body = fold_arm(else_body);
+
+            let assigned = definitely_assigned(&before);
+            let does_nothing = else_body.statements.is_empty()
+                && arms.iter().all(|(c, b)| b.statements.is_empty() && !can_fail(c, &assigned));
+            if !does_nothing {
+                let (condition, body) = arms.remove(0);
+                out.push(Statement::IfStatement{condition, body, else_if: arms, else_body, loc});
+            }
+        }
+
+        *facts = after.unwrap();
+    }
+
+    fn program(&self, program: Program) -> Program {
+        let mut statements = Vec::new();
+        self.block(program.program, &mut Facts::new(), &mut statements);
+
+        return Program{program: Block{statements}, tests: program.tests};
+    }
+}
+
+/// Folds constant operations, applies `x + 0`, `x * 1`, `x / 1` and `x - x`,
+/// removes IF arms whose condition is a constant and REPEATs that run 0
+/// times. Anything that could fail at runtime is kept so it still does
+pub fn fold(program: Program) -> Program {
+    return Folder{propagate: false}.program(program);
+}
+
+/// Does what `fold` does, also replacing each variable that is known to
+/// hold the same constant whichever way the program got there
+pub fn propagate(program: Program) -> Program {
+    return Folder{propagate: true}.program(program);
+}
+
+/// Works out what `stmt` definitely assigns, on top of `assigned`
+fn assign_after(stmt: &Statement, assigned: &mut BTreeSet<String>) {
+    match stmt {
+        Statement::AssignStatement { var, .. } => { assigned.insert(var.clone()); },
+        Statement::OutputStatement { .. } | Statement::AssertStatement { .. } | Statement::RepeatStatement { .. } => (),
+        Statement::IfStatement { body, else_if, else_body, .. } => {
+            let arm = |block: &Block| {
+                let mut arm_assigned = assigned.clone();
+                for stmt in block.statements.iter() {
+                    assign_after(stmt, &mut arm_assigned);
+                }
+                arm_assigned
+            };
+
+            *assigned = std::iter::once(body).chain(else_if.iter().map(|(_, b)| b)).chain([else_body])
+                .map(arm)
+                .reduce(|a, b| a.intersection(&b).cloned().collect())
+                .unwrap();
+        }
+    }
+}
+
+fn read_by(exp: &Expression, live: &mut BTreeSet<String>) {
+    match exp {
+        Expression::Val(_) => (),
+        Expression::Var(var) => { live.insert(var.clone()); },
+        Expression::BinOp(_, lhs, rhs) => {
+            read_by(lhs, live);
+            read_by(rhs, live);
+        }
+    }
+}
+
+/// Removes the assignments in `block` whose value is never read, given
+/// `assigned` before it and what is `live` after it. Returns what is left
+/// and what is live before it
+fn dead_stores(block: Block, assigned: &BTreeSet<String>, live: &BTreeSet<String>) -> (Block, BTreeSet<String>) {
+    let mut before = Vec::new();
+    let mut assigned = assigned.clone();
+    for stmt in block.statements.iter() {
+        before.push(assigned.clone());
+        assign_after(stmt, &mut assigned);
+    }
+
+    let mut live = live.clone();
+    let mut statements = Vec::new();
+
+    for (stmt, assigned) in block.statements.into_iter().zip(before).rev() {
+        match stmt {
+            Statement::AssignStatement { var, exp, loc } => {
+                // An assignment that could fail has to stay to fail
+                if !live.contains(&var) && !can_fail(&exp, &assigned) {
+                    continue;
+                }
+                live.remove(&var);
+                read_by(&exp, &mut live);
+                statements.push(Statement::AssignStatement{var, exp, loc});
+            },
+            Statement::OutputStatement { to_output, loc } => {
+                read_by(&to_output, &mut live);
+                statements.push(Statement::OutputStatement{to_output, loc});
+            },
+            Statement::AssertStatement { exp, expected, loc } => {
+                read_by(&exp, &mut live);
+                if let Some(expected) = &expected {
+                    read_by(expected, &mut live);
+                }
+                statements.push(Statement::AssertStatement{exp, expected, loc});
+            },
+            Statement::RepeatStatement { times, body, loc } => {
+                // Live at the top of the body is what is live after the loop
+                // or at the top of the body the next time round
+                let mut top = live.clone();
+                loop {
+                    let (_, body_live) = dead_stores(body.clone(), &assigned, &top);
+                    let next: BTreeSet<String> = top.union(&body_live).cloned().collect();
+                    if next == top {
+                        break;
+                    }
+                    top = next;
+                }
+
+                let (body, _) = dead_stores(body, &assigned, &top);
+                live = top;
+                read_by(&times, &mut live);
+                statements.push(Statement::RepeatStatement{times, body, loc});
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, loc } => {
+                let mut live_before = BTreeSet::new();
+                let mut arm = |block: Block| {
+                    let (block, arm_live) = dead_stores(block, &assigned, &live);
+                    live_before.extend(arm_live);
+                    block
+                };
+
+                let body = arm(body);
+                let else_if: Vec<(Expression, Block)> = else_if.into_iter().map(|(c, b)| (c, arm(b))).collect();
+                let else_body = arm(else_body);
+
+                read_by(&condition, &mut live_before);
+                for (c, _) in else_if.iter() {
+                    read_by(c, &mut live_before);
+                }
+                live = live_before;
+                statements.push(Statement::IfStatement{condition, body, else_if, else_body, loc});
+            }
+        }
+    }
+
+    statements.reverse();
+    return (Block{statements}, live);
+}
+
+/// Removes assignments whose value is never read before being assigned
+/// again or the program ending, unless working out the value could fail
+pub fn remove_dead_stores(program: Program) -> Program {
+    let (block, _) = dead_stores(program.program, &BTreeSet::new(), &BTreeSet::new());
+
+    return Program{program: block, tests: program.tests};
+}
+
+/// REPEATs with a constant count up to this are unrolled
+const UNROLL_MAX_COUNT: i32 = 4;
+/// As long as the copies of the body come to no more nodes than this
+const UNROLL_MAX_NODES: usize = 32;
+
+/// Names for the variables passes add, made of letters like a program's own
+/// names and different from all of them
+struct Names {
+    used: BTreeSet<String>
+}
+
+impl Names {
+    fn new(program: &Program) -> Self {
+        let mut used = program.program.read_variables();
+        used.extend(program.program.assigned_variables());
+        Self{used}
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        let mut n = 0;
+        loop {
+            let mut suffix = String::new();
+            let mut k = n;
+            loop {
+                suffix.insert(0, (b'a' + (k % 26) as u8) as char);
+                k /= 26;
+                if k == 0 {
+                    break;
+                }
+            }
+
+            let name = format!("{}{}", prefix, suffix);
+            if self.used.insert(name.clone()) {
+                return name;
+            }
+            n += 1;
+        }
+    }
+}
+
+/// `x = x + c` or `x = x - c` run `count` times as one assignment. `x` only
+/// moves one way, so it overflows part way through exactly when the total
+/// does, and if `x` isn't assigned both fail on the first go at the same
+/// statement
+fn closed_form(body: &Block, count: i32) -> Option<Statement> {
+    let [Statement::AssignStatement { var, exp, loc }] = body.statements.as_slice() else {
+        return None;
+    };
+
+    let (op, step) = match exp {
+        Expression::BinOp(Op::Add, lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+            (Expression::Var(v), Expression::Val(c)) | (Expression::Val(c), Expression::Var(v)) if v == var => (Op::Add, *c),
+            _ => return None
+        },
+        Expression::BinOp(Op::Sub, lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+            (Expression::Var(v), Expression::Val(c)) if v == var => (Op::Sub, *c),
+            _ => return None
+        },
+        _ => return None
+    };
+
+    let total = i32::try_from(i64::from(step) * i64::from(count)).ok()?;
+    let exp = Expression::BinOp(op, Box::new(Expression::Var(var.clone())), Box::new(Expression::Val(total)));
+    return Some(Statement::AssignStatement{var: var.clone(), exp, loc: *loc});
+}
+
+/// An expression moved out of a loop into its own variable
+struct Hoisted {
+    var: String,
+    exp: Expression,
+    /// The statement it came from, so a failure is reported in the same place
+    loc: Location,
+    can_fail: bool
+}
+
+/// Moves what doesn't change out of one loop body. An expression that could
+/// fail is only moved while nothing before it in the first time round could
+/// fail or output, so it fails at the same point with the same message
+struct Hoister<'a> {
+    names: &'a mut Names,
+    /// Assigned somewhere in the body
+    changing: BTreeSet<String>,
+    /// Definitely assigned before the loop
+    assigned: &'a BTreeSet<String>,
+    hoisted: Vec<Hoisted>,
+    /// Something that could fail or output has been left in the loop
+    blocked: bool
+}
+
+impl Hoister<'_> {
+    fn exp(&mut self, exp: Expression, loc: Location) -> Expression {
+        let mut vars = BTreeSet::new();
+        read_by(&exp, &mut vars);
+
+        match exp {
+            Expression::Val(_) => exp,
+            Expression::Var(ref var) => {
+                self.blocked |= !self.assigned.contains(var);
+                exp
+            },
+            Expression::BinOp(op, lhs, rhs) => {
+                let exp = Expression::BinOp(op, lhs, rhs);
+                let can_fail = can_fail(&exp, self.assigned);
+                if vars.is_disjoint(&self.changing) && !(can_fail && self.blocked) {
+                    let var = self.names.fresh("hoisted");
+                    self.hoisted.push(Hoisted{var: var.clone(), exp, loc, can_fail});
+                    return Expression::Var(var);
+                }
+
+                let Expression::BinOp(op, lhs, rhs) = exp else { unreachable!() };
+                let lhs = self.exp(*lhs, loc);
+                let rhs = self.exp(*rhs, loc);
+                self.blocked |= matches!(op, Op::Add | Op::Sub | Op::Multiply | Op::Divide | Op::Remainder);
+                Expression::BinOp(op, Box::new(lhs), Box::new(rhs))
+            }
+        }
+    }
+
+    /// Only the statements of the body itself are looked at, as what is
+    /// nested in them might not run
+    fn body(&mut self, body: Block) -> Block {
+        let mut statements = Vec::new();
+
+        for stmt in body.statements {
+            statements.push(match stmt {
+                Statement::AssignStatement { var, exp, loc } => {
+                    let exp = self.exp(exp, loc);
+                    Statement::AssignStatement{var, exp, loc}
+                },
+                Statement::OutputStatement { to_output, loc } => {
+                    let to_output = self.exp(to_output, loc);
+                    self.blocked = true;
+                    Statement::OutputStatement{to_output, loc}
+                },
+                Statement::AssertStatement { exp, expected, loc } => {
+                    let exp = self.exp(exp, loc);
+                    let expected = expected.map(|expected| self.exp(expected, loc));
+                    self.blocked = true;
+                    Statement::AssertStatement{exp, expected, loc}
+                },
+                Statement::IfStatement { condition, body, else_if, else_body, loc } => {
+                    let condition = self.exp(condition, loc);
+                    self.blocked = true;
+                    Statement::IfStatement{condition, body, else_if, else_body, loc}
+                },
+                Statement::RepeatStatement { times, body, loc } => {
+                    let times = self.exp(times, loc);
+                    self.blocked = true;
+                    Statement::RepeatStatement{times, body, loc}
+                }
+            });
+        }
+
+        return Block{statements};
+    }
+}
+
+struct Loops {
+    names: Names
+}
+
+impl Loops {
+    fn block(&mut self, block: Block, assigned: &BTreeSet<String>) -> Block {
+        let mut assigned = assigned.clone();
+        let mut statements = Vec::new();
+
+        for stmt in block.statements {
+            let before = assigned.clone();
+            assign_after(&stmt, &mut assigned);
+
+            match stmt {
+                Statement::RepeatStatement { times, body, loc } => self.repeat(times, body, loc, &before, &mut statements),
+                Statement::IfStatement { condition, body, else_if, else_body, loc } => {
+                    let body = self.block(body, &before);
+                    let else_if = else_if.into_iter().map(|(c, b)| (c, self.block(b, &before))).collect();
+                    let else_body = self.block(else_body, &before);
+                    statements.push(Statement::IfStatement{condition, body, else_if, else_body, loc});
+                },
+                stmt => statements.push(stmt)
+            }
+        }
+
+        return Block{statements};
+    }
+
+    fn repeat(&mut self, times: Expression, body: Block, loc: Location, assigned: &BTreeSet<String>, out: &mut Vec<Statement>) {
+        let body = self.block(body, assigned);
+
+        if let Expression::Val(count) = times {
+            // A loop that never runs, or fails, is left as it is
+            if count <= 0 {
+                out.push(Statement::RepeatStatement{times, body, loc});
+                return;
+            }
+
+            if let Some(stmt) = closed_form(&body, count) {
+                out.push(stmt);
+                return;
+            }
+
+            if count <= UNROLL_MAX_COUNT && block_nodes(&body) * count as usize <= UNROLL_MAX_NODES {
+                for _ in 0..count {
+                    out.extend(body.statements.iter().cloned());
+                }
+                return;
+            }
+        }
+
+        let mut hoister = Hoister{
+            names: &mut self.names,
+            changing: body.assigned_variables(),
+            assigned,
+            hoisted: Vec::new(),
+            blocked: false
+        };
+        let body = hoister.body(body);
+        let hoisted = hoister.hoisted;
+
+        let hoist = |h: Hoisted| Statement::AssignStatement{var: h.var, exp: h.exp, loc: h.loc};
+        let known_to_run = matches!(times, Expression::Val(_));
+
+        if known_to_run || hoisted.iter().all(|h| !h.can_fail) {
+            out.extend(hoisted.into_iter().map(hoist));
+            out.push(Statement::RepeatStatement{times, body, loc});
+            return;
+        }
+
+        // Work out the count first, as the loop would, and only work out
+        // what could fail if the body is going to run
+        let counter = self.names.fresh("times");
+        out.push(Statement::AssignStatement{var: counter.clone(), exp: times, loc});
+        out.push(Statement::IfStatement{
+            condition: Expression::BinOp(Op::GreaterThan, Box::new(Expression::Var(counter.clone())), Box::new(Expression::Val(0))),
+            body: Block{statements: hoisted.into_iter().map(hoist).collect()},
+            else_if: Vec::new(),
+            else_body: Block{statements: Vec::new()},
+            loc
+        });
+        out.push(Statement::RepeatStatement{times: Expression::Var(counter), body, loc});
+    }
+}
+
+/// Replaces loops that add or take away a constant with one assignment,
+/// unrolls short loops with a constant count and moves expressions that
+/// don't change out of the rest
+pub fn optimise_loops(program: Program) -> Program {
+    let mut loops = Loops{names: Names::new(&program)};
+
+    return Program{program: loops.block(program.program, &BTreeSet::new()), tests: program.tests};
+}
+
+fn exp_nodes(exp: &Expression) -> usize {
+    match exp {
+        Expression::Val(_) | Expression::Var(_) => 1,
+        Expression::BinOp(_, lhs, rhs) => 1 + exp_nodes(lhs) + exp_nodes(rhs)
+    }
+}
+
+fn block_nodes(block: &Block) -> usize {
+    block.statements.iter().map(|stmt| 1 + match stmt {
+        Statement::AssignStatement { exp, .. } => exp_nodes(exp),
+        Statement::OutputStatement { to_output, .. } => exp_nodes(to_output),
+        Statement::AssertStatement { exp, expected, .. } => exp_nodes(exp) + expected.as_ref().map_or(0, exp_nodes),
+        Statement::RepeatStatement { times, body, .. } => exp_nodes(times) + block_nodes(body),
+        Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+            exp_nodes(condition) + block_nodes(body) + block_nodes(else_body)
+                + else_if.iter().map(|(c, b)| exp_nodes(c) + block_nodes(b)).sum::<usize>()
+        }
+    }).sum()
+}
+
+/// How many statements and expressions make up `program`
+pub fn count_nodes(program: &Program) -> usize {
+    return block_nodes(&program.program);
+}
+
+/// What `optimise` did to a program
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Report {
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    /// Times round all the passes
+    pub passes: usize
+}
+
+impl Report {
+    /// Unrolling can make a program bigger, which counts as none
+    pub fn eliminated(&self) -> usize {
+        self.nodes_before.saturating_sub(self.nodes_after)
+    }
+}
+
+/// Stops `optimise` going round forever should the passes keep undoing each
+/// other
+const MAX_PASSES: usize = 16;
+
+/// Propagates constants, removes dead stores and optimises loops until
+/// none of them finds anything more to do
+pub fn optimise(program: Program) -> (Program, Report) {
+    let nodes_before = count_nodes(&program);
+    let mut program = program;
+    let mut passes = 0;
+
+    loop {
+        passes += 1;
+        let next = optimise_loops(remove_dead_stores(propagate(program.clone())));
+        if next == program || passes == MAX_PASSES {
+            break;
+        }
+        program = next;
+    }
+
+    let report = Report{nodes_before, nodes_after: count_nodes(&program), passes};
+    return (program, report);
+}
@@ -0,0 +1,231 @@
+use crate::*;
+
+fn fold_source(source: &str) -> Program {
+    fold(parser::parse_source(source).unwrap())
+}
+
+/// Optimising has to keep what the program outputs and how it fails, which
+/// the IR interpreter reports without panicking on arithmetic
+fn same_run(source: &str) {
+    let program = parser::parse_source(source).unwrap();
+    for optimised in [fold(program.clone()), optimise_loops(program.clone()), optimise(program.clone()).0] {
+        assert_eq!(ir::run(&ir::lower(&optimised)), ir::run(&ir::lower(&program)), "{}", source);
+    }
+}
+
+#[test]
+fn folds_constants_and_identities() {
+    // The expected programs are laid out so locations line up
+    assert_eq!(fold_source("x = 2;\nOUTPUT 3 * 4 + x * 1;"), parser::parse_source("x = 2;\nOUTPUT 12 + x;").unwrap());
+    assert_eq!(
+        fold_source("x = 2;\nOUTPUT 0 + x - 0 + x / 1;\nOUTPUT x - x;"),
+        parser::parse_source("x = 2;\nOUTPUT x + x;\nOUTPUT 0;").unwrap()
+    );
+    assert_eq!(fold_source("OUTPUT 7 - 9 * 2 >= 0 - 11 || 0;"), parser::parse_source("OUTPUT 1;").unwrap());
+}
+
+#[test]
+fn removes_dead_branches_and_loops() {
+    let folded = fold_source("x = 1;
+IF (x) {
+    OUTPUT 1;
+} ELSEIF (2 - 2) {
+    OUTPUT 2;
+} ELSEIF (3 > 2) {
+    OUTPUT 3;
+} ELSE {
+    OUTPUT 4;
+}
+IF (0) {
+    OUTPUT 5;
+} ELSE {
+    OUTPUT 6;
+}
+REPEAT (2 * 0) {
+    OUTPUT 7;
+}
+IF (1 == 2) {
+    OUTPUT 8;
+}");
+
+    let output = |value, line| Statement::OutputStatement{to_output: Expression::Val(value), loc: Location::new(line, 5)};
+    let statements = folded.program.statements;
+    assert_eq!(statements.len(), 3, "{:?}", statements);
+
+    // The ELSEIF that is always taken becomes the ELSE
+    match &statements[1] {
+        Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+            assert_eq!(*condition, Expression::Var("x".to_string()));
+            assert_eq!(body.statements, vec![output(1, 3)]);
+            assert!(else_if.is_empty());
+            assert_eq!(else_body.statements, vec![output(3, 7)]);
+        },
+        stmt => panic!("{:?}", stmt)
+    }
+
+    // An IF that always takes its ELSE is replaced by it
+    assert_eq!(statements[2], output(6, 14));
+}
+
+#[test]
+fn runtime_errors_are_kept() {
+    assert_eq!(fold_source("OUTPUT 1 / 0;"), parser::parse_source("OUTPUT 1 / 0;").unwrap());
+
+    same_run("OUTPUT 1;\nOUTPUT 1 / 0 * 0;");
+    same_run("OUTPUT 2147483647 + 1 - 1;");
+    same_run("OUTPUT x - x;");
+    same_run("IF (0) {\n    x = 1;\n}\nOUTPUT x - x + 0;");
+    same_run("x = 2147483647;\nOUTPUT x * x - x * x;");
+    same_run("REPEAT (0 - 1) {\n    OUTPUT 1;\n}");
+    same_run("REPEAT (0) {\n    x = 1;\n}\nIF (1) {\n    OUTPUT x;\n}");
+    same_run("IF (y) {\n    OUTPUT 1;\n} ELSEIF (0) {\n    OUTPUT 2;\n}");
+
+    // Assertions known to hold go, the rest stay to fail
+    assert_eq!(
+        propagate(parser::parse_source("x = 2;\nASSERT (x > 1);\nASSERT_EQ (x, 2);\nASSERT_EQ (x, 3);\nASSERT (y);").unwrap()),
+        parser::parse_source("x = 2;\n\n\nASSERT_EQ (2, 3);\nASSERT (y);").unwrap()
+    );
+    same_run("x = 1;\nREPEAT (3) {\n    ASSERT (x < 3);\n    x = x + 1;\n}");
+}
+
+#[test]
+fn propagates_constants_through_control_flow() {
+    let (program, _) = optimise(parser::parse_source("a = 2;
+b = 5;
+n = 40;
+IF (n > 3) {
+    c = a * 10;
+} ELSE {
+    c = 20;
+}
+REPEAT (n) {
+    a = a + 1;
+    d = b;
+    OUTPUT c + b;
+}
+OUTPUT a;
+OUTPUT d;").unwrap());
+
+    // c is 20 whichever arm runs, b is never changed and a is by the loop.
+    // The loop runs at least once so d is 5 after it, and isn't needed in it
+    assert_eq!(program, parser::parse_source("a = 2;
+
+
+
+
+
+
+
+REPEAT (40) {
+    a = a + 1;
+
+    OUTPUT 25;
+}
+OUTPUT a;
+OUTPUT 5;").unwrap());
+}
+
+#[test]
+fn removes_dead_stores() {
+    let (program, report) = optimise(parser::parse_source("x = 1;
+y = 2;
+x = y;
+z = q;
+w = 1 / 0;
+x = 4;
+OUTPUT x;").unwrap());
+
+    // Only x is read, but reading q could fail as could working out w
+    assert_eq!(program, parser::parse_source("\n\n\nz = q;\nw = 1 / 0;\n\nOUTPUT 4;").unwrap());
+    assert_eq!(report.nodes_before - report.nodes_after, report.eliminated());
+}
+
+#[test]
+fn unrolls_and_closes_loops() {
+    let (program, _) = optimise(parser::parse_source("x = 0;\nREPEAT (1000) {\n    x = x + 3;\n}\nOUTPUT x;").unwrap());
+    assert_eq!(program, parser::parse_source("\n\n\n\nOUTPUT 3000;").unwrap());
+
+    let program = optimise_loops(parser::parse_source("REPEAT (3) {\n    OUTPUT i;\n    i = i + 1;\n}").unwrap());
+    assert_eq!(program.program.statements.len(), 6);
+
+    // Overflowing part way through fails where the loop would have
+    same_run("x = 2147483000;\nREPEAT (1000) {\n    x = 1 + x;\n}\nOUTPUT x;");
+    same_run("x = 0 - 2147483000;\nREPEAT (648) {\n    x = x - 1;\n}\nOUTPUT x;");
+    same_run("x = 0 - 2147483000;\nREPEAT (649) {\n    x = x - 1;\n}\nOUTPUT x;");
+    same_run("REPEAT (5) {\n    x = x + 1;\n}");
+    same_run("REPEAT (2) {\n    OUTPUT 1;\n    OUTPUT 1 / 0;\n}");
+}
+
+#[test]
+fn hoists_invariant_expressions() {
+    let program = optimise_loops(parser::parse_source("i = 0;
+y = 4;
+REPEAT (k) {
+    OUTPUT y * 2 + i;
+    i = i + 1;
+}").unwrap());
+
+    // y * 2 could overflow, so is only worked out if the loop runs. The new
+    // statements have the location of where they came from
+    let exp = |source: &str| match parser::parse_source(&format!("OUTPUT {};", source)).unwrap().program.statements.remove(0) {
+        Statement::OutputStatement { to_output, .. } => to_output,
+        _ => unreachable!()
+    };
+    let statements = program.program.statements;
+    assert_eq!(statements.len(), 5, "{:?}", statements);
+    assert_eq!(statements[2], Statement::AssignStatement{var: "timesa".to_string(), exp: exp("k"), loc: Location::new(3, 1)});
+
+    match &statements[3] {
+        Statement::IfStatement { condition, body, .. } => {
+            assert_eq!(*condition, exp("timesa > 0"));
+            assert_eq!(body.statements, vec![Statement::AssignStatement{var: "hoisteda".to_string(), exp: exp("y * 2"), loc: Location::new(4, 5)}]);
+        },
+        stmt => panic!("{:?}", stmt)
+    }
+    match &statements[4] {
+        Statement::RepeatStatement { times, body, .. } => {
+            assert_eq!(*times, exp("timesa"));
+            assert_eq!(body.statements[0], Statement::OutputStatement{to_output: exp("hoisteda + i"), loc: Location::new(4, 5)});
+        },
+        stmt => panic!("{:?}", stmt)
+    }
+
+    // The count is only unknown when loops aren't unrolled first
+    for source in [
+        "k = 0;\nz = 0;\nREPEAT (k) {\n    OUTPUT 1 / z;\n}\nOUTPUT 5;",
+        "k = 0 - 1;\nREPEAT (k) {\n    OUTPUT 1 / z;\n}",
+        "k = 2;\nz = 0;\nREPEAT (k) {\n    OUTPUT 7;\n    OUTPUT 1 / z;\n}",
+        "k = 3;\ny = 2147483647;\nREPEAT (k) {\n    x = y + 1;\n}",
+        "k = 3;\nREPEAT (k) {\n    i = a < 2;\n}",
+        "k = 3;\na = 1;\nb = 2;\nREPEAT (k) {\n    k = a < b;\n    OUTPUT k + a * b;\n}"
+    ] {
+        same_run(source);
+    }
+}
+
+/// Every example has to output the same after each pass, checked against
+/// both interpreters
+#[test]
+fn examples_output_the_same() {
+    let mut examples: Vec<_> = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/../../examples")).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "xa"))
+        .collect();
+    examples.sort();
+    assert!(!examples.is_empty());
+
+    for path in examples {
+        let source = std::fs::read_to_string(&path).unwrap();
+        let expected = interpreter::interpret(parser::parse_source(&source).unwrap()).unwrap();
+
+        for pass in [fold, propagate, remove_dead_stores, |p| optimise(p).0] {
+            assert_eq!(interpreter::interpret(pass(parser::parse_source(&source).unwrap())).unwrap(), expected, "{:?}", path);
+        }
+        same_run(&source);
+    }
+
+    // Everything in moreops is a constant, so only the outputs are left
+    let (program, report) = optimise(parser::parse_source(include_str!("../../../examples/moreops.xa")).unwrap());
+    assert!(program.program.statements.iter().all(|stmt| matches!(stmt, Statement::OutputStatement { to_output: Expression::Val(_), .. })));
+    assert_eq!(report, Report{nodes_before: 40, nodes_after: 18, passes: 2});
+}
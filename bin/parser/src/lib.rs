@@ -1,25 +1,385 @@
 use language::*;
 use lexer::*;
 
-use std::os::raw::c_void;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ParseError {
-    UnexpectedToken(TOKEN_TYPE),
-    UnknownOperator(TOKEN_TYPE),
+    UnexpectedToken(TOKEN_TYPE, Location),
+    UnknownOperator(TOKEN_TYPE, Location),
     FileNotFound,
-    VariableParseError,
-    IntegerParseError
+    VariableParseError(Location),
+    IntegerParseError(Location),
+    StringParseError(Location),
+    /// The file an `IMPORT` names can't be read
+    ImportNotFound(Location),
+    /// An `IMPORT` of a file that is still being read
+    ImportCycle(Location),
+    /// An imported file whose name isn't usable as a module name
+    BadModuleName(Location),
+    /// An imported file with the same name as another one
+    ModuleNameClash(Location),
+    /// A name qualified by a module this file doesn't import
+    UnknownModule(Location),
+    /// A name that the module it is taken from never assigns
+    UnknownImport(Location),
+    /// An `IMPORT` in source that wasn't read from a file or given a
+    /// directory, so has nothing to import relative to
+    ImportWithoutFile(Location)
 }
 
-pub fn gen_ast(context: *const c_void) -> Result<Program, ParseError> {
+impl ParseError {
+    /// Where in the source the error was found, if it came from the source
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            ParseError::UnexpectedToken(_, loc) |
+            ParseError::UnknownOperator(_, loc) |
+            ParseError::VariableParseError(loc) |
+            ParseError::IntegerParseError(loc) |
+            ParseError::StringParseError(loc) |
+            ParseError::ImportNotFound(loc) |
+            ParseError::ImportCycle(loc) |
+            ParseError::BadModuleName(loc) |
+            ParseError::ModuleNameClash(loc) |
+            ParseError::UnknownModule(loc) |
+            ParseError::UnknownImport(loc) |
+            ParseError::ImportWithoutFile(loc) => Some(*loc),
+            ParseError::FileNotFound => None
+        }
+    }
+
+    /// The same error, found in `file`
+    fn in_file(self, file: FileId) -> Self {
+        match self {
+            ParseError::UnexpectedToken(tok, loc) => ParseError::UnexpectedToken(tok, loc.in_file(file)),
+            ParseError::UnknownOperator(tok, loc) => ParseError::UnknownOperator(tok, loc.in_file(file)),
+            ParseError::VariableParseError(loc) => ParseError::VariableParseError(loc.in_file(file)),
+            ParseError::IntegerParseError(loc) => ParseError::IntegerParseError(loc.in_file(file)),
+            ParseError::StringParseError(loc) => ParseError::StringParseError(loc.in_file(file)),
+            ParseError::ImportNotFound(loc) => ParseError::ImportNotFound(loc.in_file(file)),
+            ParseError::ImportCycle(loc) => ParseError::ImportCycle(loc.in_file(file)),
+            ParseError::BadModuleName(loc) => ParseError::BadModuleName(loc.in_file(file)),
+            ParseError::ModuleNameClash(loc) => ParseError::ModuleNameClash(loc.in_file(file)),
+            ParseError::UnknownModule(loc) => ParseError::UnknownModule(loc.in_file(file)),
+            ParseError::UnknownImport(loc) => ParseError::UnknownImport(loc.in_file(file)),
+            ParseError::ImportWithoutFile(loc) => ParseError::ImportWithoutFile(loc.in_file(file)),
+            ParseError::FileNotFound => ParseError::FileNotFound
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken(tok, loc) => write!(f, "{}: unexpected token {:?}", loc, tok),
+            ParseError::UnknownOperator(tok, loc) => write!(f, "{}: unknown operator {:?}", loc, tok),
+            ParseError::FileNotFound => write!(f, "file not found"),
+            ParseError::VariableParseError(loc) => write!(f, "{}: could not read variable name", loc),
+            ParseError::IntegerParseError(loc) => write!(f, "{}: integer literal does not fit in 32 bits", loc),
+            ParseError::StringParseError(loc) => write!(f, "{}: could not read string", loc),
+            ParseError::ImportNotFound(loc) => write!(f, "{}: imported file not found", loc),
+            ParseError::ImportCycle(loc) => write!(f, "{}: import cycle, the file imports itself", loc),
+            ParseError::BadModuleName(loc) => write!(f, "{}: imported file name must be letters only", loc),
+            ParseError::ModuleNameClash(loc) => write!(f, "{}: another imported file has the same name", loc),
+            ParseError::UnknownModule(loc) => write!(f, "{}: no imported file has that name", loc),
+            ParseError::UnknownImport(loc) => write!(f, "{}: the imported file never assigns that variable", loc),
+            ParseError::ImportWithoutFile(loc) => write!(f, "{}: IMPORT needs the program to be in a file", loc)
+        }
+    }
+}
+
+/// An `IMPORT "path" EXPOSING (a, b);` as written
+#[derive(Debug, Clone, PartialEq)]
+struct Import {
+    path: String,
+    exposing: Vec<(String, Location)>,
+    /// How many of the file's statements come before it
+    position: usize,
+    loc: Location
+}
+
+/// A file as it was parsed, before its names are resolved
+#[derive(Debug)]
+struct Module {
+    /// What its variables are qualified with, the main file's aren't
+    namespace: Option<String>,
+    program: Program,
+    /// Each import with the file it read
+    imports: Vec<(Import, FileId)>
+}
+
+/// Reads a program and every file it imports, each only once.
+///
+/// An imported file's variables are its name then the variable, so `x` in
+/// `lib.xa` is `lib.x`, and its statements run where it is first imported.
+/// `EXPOSING` names can also be used unqualified by the importing file. The
+/// `TEST` blocks of imported files are left out
+#[derive(Debug, Default)]
+pub struct Loader {
+    /// Where each file was read from, the main file has none if it came
+    /// from memory
+    paths: Vec<Option<PathBuf>>,
+    /// The file read from each canonical path
+    ids: HashMap<PathBuf, FileId>,
+    /// Each file once it is read, a file still being read has none
+    modules: Vec<Option<Module>>,
+    /// The file each module name belongs to
+    namespaces: HashMap<String, FileId>
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the program at `path`, importing relative to where it is
+    pub fn load(&mut self, path: &Path) -> Result<Program, ParseError> {
+        *self = Self::new();
+        self.read_file(path, None)?;
+        return self.link();
+    }
+
+    /// Parses a program held in memory, importing relative to `dir`
+    pub fn load_source(&mut self, source: &str, dir: &Path) -> Result<Program, ParseError> {
+        return self.load_context(&mut Lexer::from_source(source.as_bytes()), Some(dir));
+    }
+
+    /// Parses the main file from `context`. Without a `dir` it can't import
+    fn load_context(&mut self, context: &mut Lexer, dir: Option<&Path>) -> Result<Program, ParseError> {
+        *self = Self::new();
+        self.paths.push(None);
+        self.modules.push(None);
+        self.read(0, context, dir)?;
+        return self.link();
+    }
+
+    /// Where `file` was read from
+    pub fn path(&self, file: FileId) -> Option<&Path> {
+        return self.paths.get(file as usize)?.as_deref();
+    }
+
+    /// `loc` with the file it is in, if that isn't the main file
+    pub fn locate(&self, loc: Location) -> String {
+        match self.path(loc.file).filter(|_| loc.file != 0) {
+            Some(path) => return format!("{}:{}", path.display(), loc),
+            None => return loc.to_string()
+        }
+    }
+
+    /// The error with the file it was found in, if that isn't the main file
+    pub fn describe(&self, error: &ParseError) -> String {
+        match error.location().and_then(|loc| self.path(loc.file).filter(|_| loc.file != 0)) {
+            Some(path) => return format!("{}:{}", path.display(), error),
+            None => return error.to_string()
+        }
+    }
+
+    /// Reads the file at `path` if it hasn't been already. `from` is where
+    /// it is imported, none for the main file
+    fn read_file(&mut self, path: &Path, from: Option<Location>) -> Result<FileId, ParseError> {
+        let not_found = from.map_or(ParseError::FileNotFound, ParseError::ImportNotFound);
+        let canonical = fs::canonicalize(path).map_err(|_| not_found)?;
+
+        if let Some(&file) = self.ids.get(&canonical) {
+            if self.modules[file as usize].is_none() {
+                return Err(ParseError::ImportCycle(from.unwrap_or_default()));
+            }
+            return Ok(file);
+        }
+
+        let source = fs::read_to_string(&canonical).map_err(|_| not_found)?;
+        let file = self.paths.len() as FileId;
+        self.paths.push(Some(path.to_path_buf()));
+        self.ids.insert(canonical, file);
+        self.modules.push(None);
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        self.read(file, &mut Lexer::from_source(source.as_bytes()), Some(dir))?;
+        return Ok(file);
+    }
+
+    /// Parses `file` and reads what it imports
+    fn read(&mut self, file: FileId, context: &mut Lexer, dir: Option<&Path>) -> Result<(), ParseError> {
+        let (program, imports) = parse_module(context).map_err(|e| e.in_file(file))?;
+
+        let mut read = Vec::new();
+        for mut import in imports {
+            import.loc = import.loc.in_file(file);
+            let Some(dir) = dir else {
+                return Err(ParseError::ImportWithoutFile(import.loc));
+            };
+            let path = dir.join(&import.path);
+
+            let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphabetic()) {
+                return Err(ParseError::BadModuleName(import.loc));
+            }
+            let name = name.to_string();
+
+            let target = self.read_file(&path, Some(import.loc))?;
+            if *self.namespaces.entry(name.clone()).or_insert(target) != target {
+                return Err(ParseError::ModuleNameClash(import.loc));
+            }
+            self.modules[target as usize].as_mut().unwrap().namespace = Some(name);
+
+            let assigned = self.modules[target as usize].as_ref().unwrap().program.program.assigned_variables();
+            for (exposed, loc) in import.exposing.iter_mut() {
+                *loc = loc.in_file(file);
+                if !assigned.contains(exposed) {
+                    return Err(ParseError::UnknownImport(*loc));
+                }
+            }
+            read.push((import, target));
+        }
+
+        self.modules[file as usize] = Some(Module{namespace: None, program, imports: read});
+        return Ok(());
+    }
+
+    /// The program run from the main file, with each module's statements
+    /// where it is first imported. Tests run in memory of their own, so each
+    /// starts by running the modules the main file imports
+    fn link(&self) -> Result<Program, ParseError> {
+        let mut program = Program::new();
+        self.link_module(0, &mut HashSet::from([0]), &mut program.program.statements)?;
+
+        let main = self.modules[0].as_ref().unwrap();
+        let mut imported = Vec::new();
+        let mut included = HashSet::from([0]);
+        for (_, target) in main.imports.iter() {
+            if included.insert(*target) {
+                self.link_module(*target, &mut included, &mut imported)?;
+            }
+        }
+
+        for test in main.program.tests.iter() {
+            let mut body = Block{statements: imported.clone()};
+            body.statements.extend(self.resolve_block(0, &test.body)?.statements);
+            program.tests.push(Test{name: test.name.clone(), body, loc: test.loc});
+        }
+
+        return Ok(program);
+    }
+
+    fn link_module(&self, file: FileId, included: &mut HashSet<FileId>, statements: &mut Vec<Statement>) -> Result<(), ParseError> {
+        let module = self.modules[file as usize].as_ref().unwrap();
+        let mut imports = module.imports.iter().peekable();
+
+        for position in 0..=module.program.program.statements.len() {
+            while let Some((_, target)) = imports.next_if(|(import, _)| import.position == position) {
+                if included.insert(*target) {
+                    self.link_module(*target, included, statements)?;
+                }
+            }
+            if let Some(statement) = module.program.program.statements.get(position) {
+                statements.push(self.resolve_statement(file, statement)?);
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// What the variable `name` used at `loc` in `file` is called once
+    /// files are put together
+    fn resolve_name(&self, file: FileId, name: &str, loc: Location) -> Result<String, ParseError> {
+        let module = self.modules[file as usize].as_ref().unwrap();
+        let namespace = |target: &FileId| self.modules[*target as usize].as_ref().unwrap().namespace.as_deref().unwrap();
+
+        if let Some((qualifier, var)) = name.split_once('.') {
+            let Some((_, target)) = module.imports.iter().find(|(_, target)| namespace(target) == qualifier) else {
+                return Err(ParseError::UnknownModule(loc));
+            };
+            if !self.modules[*target as usize].as_ref().unwrap().program.program.assigned_variables().contains(var) {
+                return Err(ParseError::UnknownImport(loc));
+            }
+            return Ok(name.to_string());
+        }
+
+        // The last import exposing it wins, as it would if assigned
+        let exposed = module.imports.iter().rev().find(|(import, _)| import.exposing.iter().any(|(n, _)| n == name));
+        match (exposed, &module.namespace) {
+            (Some((_, target)), _) => return Ok(format!("{}.{}", namespace(target), name)),
+            (None, Some(own)) => return Ok(format!("{}.{}", own, name)),
+            (None, None) => return Ok(name.to_string())
+        }
+    }
+
+    fn resolve_block(&self, file: FileId, block: &Block) -> Result<Block, ParseError> {
+        let statements = block.statements.iter().map(|s| self.resolve_statement(file, s)).collect::<Result<_, _>>()?;
+        return Ok(Block{statements});
+    }
+
+    fn resolve_expression(&self, file: FileId, exp: &Expression, loc: Location) -> Result<Expression, ParseError> {
+        match exp {
+            Expression::Val(i) => return Ok(Expression::Val(*i)),
+            Expression::Var(name) => return Ok(Expression::Var(self.resolve_name(file, name, loc)?)),
+            Expression::BinOp(op, lhs, rhs) => {
+                let lhs = self.resolve_expression(file, lhs, loc)?;
+                let rhs = self.resolve_expression(file, rhs, loc)?;
+                return Ok(Expression::BinOp(*op, Box::new(lhs), Box::new(rhs)));
+            }
+        }
+    }
+
+    /// `statement` with its locations in `file` and its variables resolved
+    fn resolve_statement(&self, file: FileId, statement: &Statement) -> Result<Statement, ParseError> {
+        let loc = statement.location().in_file(file);
+        match statement {
+            Statement::AssignStatement { var, exp, .. } => return Ok(Statement::AssignStatement {
+                var: self.resolve_name(file, var, loc)?,
+                exp: self.resolve_expression(file, exp, loc)?,
+                loc
+            }),
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                let mut resolved_else_if = Vec::new();
+                for (cond, block) in else_if {
+                    resolved_else_if.push((self.resolve_expression(file, cond, loc)?, self.resolve_block(file, block)?));
+                }
+                return Ok(Statement::IfStatement {
+                    condition: self.resolve_expression(file, condition, loc)?,
+                    body: self.resolve_block(file, body)?,
+                    else_if: resolved_else_if,
+                    else_body: self.resolve_block(file, else_body)?,
+                    loc
+                });
+            },
+            Statement::RepeatStatement { times, body, .. } => return Ok(Statement::RepeatStatement {
+                times: self.resolve_expression(file, times, loc)?,
+                body: self.resolve_block(file, body)?,
+                loc
+            }),
+            Statement::OutputStatement { to_output, .. } => return Ok(Statement::OutputStatement {
+                to_output: self.resolve_expression(file, to_output, loc)?,
+                loc
+            }),
+            Statement::AssertStatement { exp, expected, .. } => return Ok(Statement::AssertStatement {
+                exp: self.resolve_expression(file, exp, loc)?,
+                expected: expected.as_ref().map(|e| self.resolve_expression(file, e, loc)).transpose()?,
+                loc
+            })
+        }
+    }
+}
+
+/// Parses a program that can't import anything, as it isn't known where it
+/// is. `Loader` reads programs that import
+pub fn gen_ast(context: &mut Lexer) -> Result<Program, ParseError> {
+    return Loader::new().load_context(context, None);
+}
+
+/// Parses one file, leaving its imports to be read
+fn parse_module(context: &mut Lexer) -> Result<(Program, Vec<Import>), ParseError> {
     let mut my_program = Program::new();
+    let mut imports = Vec::new();
 
     // Get the first token
-    let mut cur_tok = get_token_safe(context);
+    let mut cur_tok = context.next_token();
 
     while cur_tok.tok_type != TOKEN_TYPE::EOF_TOK {
 
@@ -40,25 +400,50 @@ pub fn gen_ast(context: *const c_void) -> Result<Program, ParseError> {
                 let result = parse_output(&mut cur_tok, context)?;
                 my_program.program.statements.push(result);
             },
+            TOKEN_TYPE::ASSERT |
+            TOKEN_TYPE::ASSERT_EQ => {
+                let result = parse_assert(&mut cur_tok, context)?;
+                my_program.program.statements.push(result);
+            },
+            // Tests and imports can only be at the top, not inside other blocks
+            TOKEN_TYPE::TEST => {
+                let result = parse_test(&mut cur_tok, context)?;
+                my_program.tests.push(result);
+            },
+            TOKEN_TYPE::IMPORT => {
+                let result = parse_import(&mut cur_tok, context, my_program.program.statements.len())?;
+                imports.push(result);
+            },
             _ => {
-                lexer::close_file_safe(context);
-                return Err(ParseError::UnexpectedToken(cur_tok.tok_type));
+                return Err(unexpected(&cur_tok));
             }
         }
     }
 
-    return Ok(my_program);
+    return Ok((my_program, imports));
+}
+
+pub fn get_file_context(filename: &str) -> Option<Lexer> {
+    Lexer::open(filename)
 }
 
-pub fn get_file_context(filename: &str) -> Option<*const c_void> {
-    open_file_safe(filename)
+/// Closes the file, the context can not be used after this
+pub fn close_file_from_context(context: Lexer) {
+    drop(context);
 }
 
-pub fn close_file_from_context(context: *const c_void) {
-    close_file_safe(context);
+/// Parses a whole program held in memory, which can't import anything
+pub fn parse_source(source: &str) -> Result<Program, ParseError> {
+    let mut context = Lexer::from_source(source.as_bytes());
+    gen_ast(&mut context)
 }
 
-fn parse_block(cur_tok: &mut token, context: *const c_void) -> Result<language::Block, ParseError> {
+/// Whether `source` parses, without reading the files it imports
+pub fn check_syntax(source: &str) -> Result<(), ParseError> {
+    return parse_module(&mut Lexer::from_source(source.as_bytes())).map(|_| ());
+}
+
+fn parse_block(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Block, ParseError> {
     let mut my_block = language::Block{statements: Vec::new()};
 
     while cur_tok.tok_type != TOKEN_TYPE::RBRA {
@@ -80,9 +465,13 @@ fn parse_block(cur_tok: &mut token, context: *const c_void) -> Result<language::
                 let result = parse_output(cur_tok, context)?;
                 my_block.statements.push(result);
             },
+            TOKEN_TYPE::ASSERT |
+            TOKEN_TYPE::ASSERT_EQ => {
+                let result = parse_assert(cur_tok, context)?;
+                my_block.statements.push(result);
+            },
             _ => {
-                lexer::close_file_safe(context);
-                return Err(ParseError::UnexpectedToken(cur_tok.tok_type));
+                return Err(unexpected(cur_tok));
             }
         }
     }
@@ -90,17 +479,100 @@ fn parse_block(cur_tok: &mut token, context: *const c_void) -> Result<language::
     return Ok(my_block);
 }
 
-fn parse_output(cur_tok: &mut token, context: *const c_void) -> Result<language::Statement, ParseError> {
+fn parse_output(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Statement, ParseError> {
+    let loc = token_location(cur_tok);
     consume_token(cur_tok, TOKEN_TYPE::OUTPUT, context)?;
 
     let to_output = parse_expression(cur_tok, context)?;
 
     consume_token(cur_tok, TOKEN_TYPE::SC, context)?;
 
-    return Ok(language::Statement::OutputStatement { to_output: to_output })
+    return Ok(language::Statement::OutputStatement { to_output, loc })
+}
+
+fn parse_assert(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Statement, ParseError> {
+    let loc = token_location(cur_tok);
+    let equal = cur_tok.tok_type == TOKEN_TYPE::ASSERT_EQ;
+    consume_token(cur_tok, cur_tok.tok_type, context)?;
+    consume_token(cur_tok, TOKEN_TYPE::LPAR, context)?;
+
+    let exp = parse_expression(cur_tok, context)?;
+
+    let mut expected = None;
+    if equal {
+        consume_token(cur_tok, TOKEN_TYPE::COMMA, context)?;
+        expected = Some(parse_expression(cur_tok, context)?);
+    }
+
+    consume_token(cur_tok, TOKEN_TYPE::RPAR, context)?;
+    consume_token(cur_tok, TOKEN_TYPE::SC, context)?;
+
+    return Ok(language::Statement::AssertStatement { exp, expected, loc })
+}
+
+fn parse_test(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Test, ParseError> {
+    let loc = token_location(cur_tok);
+    consume_token(cur_tok, TOKEN_TYPE::TEST, context)?;
+
+    if cur_tok.tok_type != TOKEN_TYPE::STR_LIT {
+        return Err(unexpected(cur_tok));
+    }
+    let name = match lexer::val_to_str(&cur_tok.val) {
+        None => return Err(ParseError::StringParseError(token_location(cur_tok))),
+        Some(n) => n
+    };
+    consume_token(cur_tok, TOKEN_TYPE::STR_LIT, context)?;
+
+    consume_token(cur_tok, TOKEN_TYPE::LBRA, context)?;
+    let body = parse_block(cur_tok, context)?;
+    consume_token(cur_tok, TOKEN_TYPE::RBRA, context)?;
+
+    return Ok(language::Test { name, body, loc })
+}
+
+fn parse_import(cur_tok: &mut token, context: &mut Lexer, position: usize) -> Result<Import, ParseError> {
+    let loc = token_location(cur_tok);
+    consume_token(cur_tok, TOKEN_TYPE::IMPORT, context)?;
+
+    if cur_tok.tok_type != TOKEN_TYPE::STR_LIT {
+        return Err(unexpected(cur_tok));
+    }
+    let path = match lexer::val_to_str(&cur_tok.val) {
+        None => return Err(ParseError::StringParseError(token_location(cur_tok))),
+        Some(p) => p
+    };
+    consume_token(cur_tok, TOKEN_TYPE::STR_LIT, context)?;
+
+    let mut exposing = Vec::new();
+    if cur_tok.tok_type == TOKEN_TYPE::EXPOSING {
+        consume_token(cur_tok, TOKEN_TYPE::EXPOSING, context)?;
+        consume_token(cur_tok, TOKEN_TYPE::LPAR, context)?;
+        loop {
+            let name_loc = token_location(cur_tok);
+            if cur_tok.tok_type != TOKEN_TYPE::VAR {
+                return Err(unexpected(cur_tok));
+            }
+            match lexer::val_to_str(&cur_tok.val) {
+                None => return Err(ParseError::VariableParseError(name_loc)),
+                Some(n) => exposing.push((n, name_loc))
+            }
+            consume_token(cur_tok, TOKEN_TYPE::VAR, context)?;
+
+            if cur_tok.tok_type != TOKEN_TYPE::COMMA {
+                break;
+            }
+            consume_token(cur_tok, TOKEN_TYPE::COMMA, context)?;
+        }
+        consume_token(cur_tok, TOKEN_TYPE::RPAR, context)?;
+    }
+
+    consume_token(cur_tok, TOKEN_TYPE::SC, context)?;
+
+    return Ok(Import { path, exposing, position, loc })
 }
 
-fn parse_if(cur_tok: &mut token, context: *const c_void) -> Result<language::Statement, ParseError> {
+fn parse_if(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Statement, ParseError> {
+    let loc = token_location(cur_tok);
     consume_token(cur_tok, TOKEN_TYPE::IF, context)?;
     consume_token(cur_tok, TOKEN_TYPE::LPAR, context)?;
     
@@ -147,12 +619,14 @@ fn parse_if(cur_tok: &mut token, context: *const c_void) -> Result<language::Sta
         condition: if_cond, 
         body: if_body, 
         else_if: else_if_vec, 
-        else_body: else_body
+        else_body,
+        loc
     });
 
 }
 
-fn parse_repeat(cur_tok: &mut token, context: *const c_void) -> Result<language::Statement, ParseError> {
+fn parse_repeat(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Statement, ParseError> {
+    let loc = token_location(cur_tok);
     // This check should be unnessesary but whatever
     consume_token(cur_tok, TOKEN_TYPE::REPEAT, context)?; // REPEAT
     consume_token(cur_tok, TOKEN_TYPE::LPAR, context)?; // (
@@ -166,133 +640,129 @@ fn parse_repeat(cur_tok: &mut token, context: *const c_void) -> Result<language:
 
     consume_token(cur_tok, TOKEN_TYPE::RBRA, context)?; // }
 
-    return Ok(language::Statement::RepeatStatement { times: times, body: block })
+    return Ok(language::Statement::RepeatStatement { times, body: block, loc })
 }
 
-fn parse_assign(cur_tok: &mut token, context: *const c_void) -> Result<language::Statement, ParseError> {
+fn parse_assign(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Statement, ParseError> {
     // This check should be unnessesary but whatever
-    if !(cur_tok.tok_type == TOKEN_TYPE::VAR) {
-        return Err(ParseError::UnexpectedToken(cur_tok.tok_type));
-    }
-
-    let name_result = lexer::val_to_str(&cur_tok.val);
-    let name;
-
-    match name_result {
-        None => return Err(ParseError::VariableParseError),
-        Some(n) => name = n
+    if cur_tok.tok_type != TOKEN_TYPE::VAR {
+        return Err(unexpected(cur_tok));
     }
 
-    *cur_tok = get_token_safe(context); // Consume the variable
+    let loc = token_location(cur_tok);
+    let name = parse_name(cur_tok, context)?;
 
     consume_token(cur_tok, TOKEN_TYPE::ASSIGN, context)?;
 
     // Follow set of expression
-    if !(cur_tok.tok_type == TOKEN_TYPE::VAR ||
-         cur_tok.tok_type == TOKEN_TYPE::INT_LIT) {
-            return Err(ParseError::UnexpectedToken(cur_tok.tok_type));
+    if cur_tok.tok_type != TOKEN_TYPE::VAR &&
+       cur_tok.tok_type != TOKEN_TYPE::INT_LIT {
+            return Err(unexpected(cur_tok));
     }
 
     let result = parse_expression(cur_tok, context)?;
 
     consume_token(cur_tok, TOKEN_TYPE::SC, context)?;
 
-    return Ok(language::Statement::AssignStatement { var: name, exp: result });
+    return Ok(language::Statement::AssignStatement { var: name, exp: result, loc });
 }
 
-fn rvalor(cur_tok: &mut token, context: *const c_void, lhs: language::Expression) -> Result<language::Expression, ParseError> {
+fn rvalor(cur_tok: &mut token, context: &mut Lexer, lhs: language::Expression) -> Result<language::Expression, ParseError> {
     
     match cur_tok.tok_type {
         TOKEN_TYPE::OR => {
-            let this_token = cur_tok.clone();
+            let this_token = *cur_tok;
             consume_token(cur_tok, TOKEN_TYPE::OR, context)?;
 
             let rhs = rvaland(cur_tok, context)?;
-            let result = language::Expression::BinOp(token_to_op(this_token.tok_type)?, Box::new(lhs), Box::new(rhs));
+            let result = language::Expression::BinOp(token_to_op(&this_token)?, Box::new(lhs), Box::new(rhs));
             rvalor(cur_tok, context, result)
         },
         TOKEN_TYPE::SC |
-        TOKEN_TYPE::RPAR => {
+        TOKEN_TYPE::RPAR |
+        TOKEN_TYPE::COMMA => {
             Ok(lhs)
         },
         _ => {
-            Err(ParseError::UnexpectedToken(cur_tok.tok_type))
+            Err(unexpected(cur_tok))
         }
     }
 }
 
-fn rvaland(cur_tok: &mut token, context: *const c_void) -> Result<language::Expression, ParseError> {
+fn rvaland(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Expression, ParseError> {
     let lhs = rvaleq(cur_tok, context)?;
     rvaland_p(cur_tok, context, lhs)
 }
 
-fn rvaland_p(cur_tok: &mut token, context: *const c_void, lhs: language::Expression) -> Result<language::Expression, ParseError> {
+fn rvaland_p(cur_tok: &mut token, context: &mut Lexer, lhs: language::Expression) -> Result<language::Expression, ParseError> {
     
     match cur_tok.tok_type {
         TOKEN_TYPE::AND => {
-            let this_token = cur_tok.clone();
+            let this_token = *cur_tok;
             consume_token(cur_tok, TOKEN_TYPE::AND, context)?;
 
             let rhs = rvaleq(cur_tok, context)?;
-            let result = language::Expression::BinOp(token_to_op(this_token.tok_type)?, Box::new(lhs), Box::new(rhs));
+            let result = language::Expression::BinOp(token_to_op(&this_token)?, Box::new(lhs), Box::new(rhs));
             rvaland_p(cur_tok, context, result)
         },
         TOKEN_TYPE::OR |
         TOKEN_TYPE::SC |
-        TOKEN_TYPE::RPAR => {
+        TOKEN_TYPE::RPAR |
+        TOKEN_TYPE::COMMA => {
             Ok(lhs)
         },
         _ => {
-            Err(ParseError::UnexpectedToken(cur_tok.tok_type))
+            Err(unexpected(cur_tok))
         }
     }
 }
 
-fn rvaleq(cur_tok: &mut token, context: *const c_void) -> Result<language::Expression, ParseError> {
+fn rvaleq(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Expression, ParseError> {
     let lhs = rvalcomp(cur_tok, context)?;
     rvaleq_p(cur_tok, context, lhs)
 }
 
-fn rvaleq_p(cur_tok: &mut token, context: *const c_void, lhs: language::Expression) -> Result<language::Expression, ParseError> {
+fn rvaleq_p(cur_tok: &mut token, context: &mut Lexer, lhs: language::Expression) -> Result<language::Expression, ParseError> {
     
     match cur_tok.tok_type {
         TOKEN_TYPE::EQ |
         TOKEN_TYPE::NEQ => {
-            let this_token = cur_tok.clone();
+            let this_token = *cur_tok;
             consume_token(cur_tok, cur_tok.tok_type, context)?;
 
             let rhs = rvalcomp(cur_tok, context)?;
-            let result = language::Expression::BinOp(token_to_op(this_token.tok_type)?, Box::new(lhs), Box::new(rhs));
+            let result = language::Expression::BinOp(token_to_op(&this_token)?, Box::new(lhs), Box::new(rhs));
             rvaleq_p(cur_tok, context, result)
         },
         TOKEN_TYPE::AND |
         TOKEN_TYPE::OR |
         TOKEN_TYPE::SC |
-        TOKEN_TYPE::RPAR => {
+        TOKEN_TYPE::RPAR |
+        TOKEN_TYPE::COMMA => {
             Ok(lhs)
         },
         _ => {
-            Err(ParseError::UnexpectedToken(cur_tok.tok_type))
+            Err(unexpected(cur_tok))
         }
     }
 }
 
-fn rvalcomp(cur_tok: &mut token, context: *const c_void) -> Result<language::Expression, ParseError> {
+fn rvalcomp(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Expression, ParseError> {
     let lhs = rvaladd(cur_tok, context)?;
     rvalcomp_p(cur_tok, context, lhs)
 }
 
-fn rvalcomp_p(cur_tok: &mut token, context: *const c_void, lhs: language::Expression) -> Result<language::Expression, ParseError> {
+fn rvalcomp_p(cur_tok: &mut token, context: &mut Lexer, lhs: language::Expression) -> Result<language::Expression, ParseError> {
     match cur_tok.tok_type {
         TOKEN_TYPE::LE | 
         TOKEN_TYPE::LT | 
         TOKEN_TYPE::GE | 
         TOKEN_TYPE::GT => {
-            let this_token = cur_tok.clone();
+            let this_token = *cur_tok;
             consume_token(cur_tok, cur_tok.tok_type, context)?;
 
             let rhs = rvaladd(cur_tok, context)?;
-            let result = language::Expression::BinOp(token_to_op(this_token.tok_type)?, Box::new(lhs), Box::new(rhs));
+            let result = language::Expression::BinOp(token_to_op(&this_token)?, Box::new(lhs), Box::new(rhs));
             rvalcomp_p(cur_tok, context, result)
         },
         TOKEN_TYPE::EQ |
@@ -300,29 +770,30 @@ fn rvalcomp_p(cur_tok: &mut token, context: *const c_void, lhs: language::Expres
         TOKEN_TYPE::AND |
         TOKEN_TYPE::OR |
         TOKEN_TYPE::SC |
-        TOKEN_TYPE::RPAR => {
+        TOKEN_TYPE::RPAR |
+        TOKEN_TYPE::COMMA => {
             Ok(lhs)
         }
         _ => {
-            Err(ParseError::UnexpectedToken(cur_tok.tok_type))
+            Err(unexpected(cur_tok))
         }
     }
 }
 
-fn rvaladd(cur_tok: &mut token, context: *const c_void) -> Result<language::Expression, ParseError> {
+fn rvaladd(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Expression, ParseError> {
     let lhs = rvalmult(cur_tok, context)?;
     rvaladd_p(cur_tok, context, lhs)
 }
 
-fn rvaladd_p(cur_tok: &mut token, context: *const c_void, lhs: language::Expression) -> Result<language::Expression, ParseError> {
+fn rvaladd_p(cur_tok: &mut token, context: &mut Lexer, lhs: language::Expression) -> Result<language::Expression, ParseError> {
     match cur_tok.tok_type {
         TOKEN_TYPE::PLUS | 
         TOKEN_TYPE::MINUS => {
-            let this_token = cur_tok.clone();
+            let this_token = *cur_tok;
             consume_token(cur_tok, cur_tok.tok_type, context)?;
 
             let rhs = rvalmult(cur_tok, context)?;
-            let result = language::Expression::BinOp(token_to_op(this_token.tok_type)?, Box::new(lhs), Box::new(rhs));
+            let result = language::Expression::BinOp(token_to_op(&this_token)?, Box::new(lhs), Box::new(rhs));
             rvaladd_p(cur_tok, context, result)
         },
         TOKEN_TYPE::LE | 
@@ -334,30 +805,31 @@ fn rvaladd_p(cur_tok: &mut token, context: *const c_void, lhs: language::Express
         TOKEN_TYPE::AND |
         TOKEN_TYPE::OR |
         TOKEN_TYPE::SC |
-        TOKEN_TYPE::RPAR => {
+        TOKEN_TYPE::RPAR |
+        TOKEN_TYPE::COMMA => {
             Ok(lhs)
         }
         _ => {
-            Err(ParseError::UnexpectedToken(cur_tok.tok_type))
+            Err(unexpected(cur_tok))
         }
     }
 }
 
-fn rvalmult(cur_tok: &mut token, context: *const c_void) -> Result<language::Expression, ParseError> {
+fn rvalmult(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Expression, ParseError> {
     let lhs = rvallit(cur_tok, context)?;
     rvalmult_p(cur_tok, context, lhs)
 }
 
-fn rvalmult_p(cur_tok: &mut token, context: *const c_void, lhs: language::Expression) -> Result<language::Expression, ParseError> {
+fn rvalmult_p(cur_tok: &mut token, context: &mut Lexer, lhs: language::Expression) -> Result<language::Expression, ParseError> {
     match cur_tok.tok_type {
         TOKEN_TYPE::ASTERIX | 
         TOKEN_TYPE::DIV |
         TOKEN_TYPE::MOD => {
-            let this_token = cur_tok.clone();
+            let this_token = *cur_tok;
             consume_token(cur_tok, cur_tok.tok_type, context)?;
 
             let rhs = rvallit(cur_tok, context)?;
-            let result = language::Expression::BinOp(token_to_op(this_token.tok_type)?, Box::new(lhs), Box::new(rhs));
+            let result = language::Expression::BinOp(token_to_op(&this_token)?, Box::new(lhs), Box::new(rhs));
             rvalmult_p(cur_tok, context, result)
         },
         TOKEN_TYPE::PLUS | 
@@ -371,25 +843,20 @@ fn rvalmult_p(cur_tok: &mut token, context: *const c_void, lhs: language::Expres
         TOKEN_TYPE::AND |
         TOKEN_TYPE::OR |
         TOKEN_TYPE::SC |
-        TOKEN_TYPE::RPAR => {
+        TOKEN_TYPE::RPAR |
+        TOKEN_TYPE::COMMA => {
             Ok(lhs)
         }
         _ => {
-            Err(ParseError::UnexpectedToken(cur_tok.tok_type))
+            Err(unexpected(cur_tok))
         }
     }
 }
 
-fn rvallit(cur_tok: &mut token, context: *const c_void) -> Result<language::Expression, ParseError> {
+fn rvallit(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Expression, ParseError> {
     match cur_tok.tok_type {
         TOKEN_TYPE::VAR => {
-            match lexer::val_to_str(&cur_tok.val) {
-                Some(name) => {
-                    consume_token(cur_tok, TOKEN_TYPE::VAR, context)?;
-                    Ok(language::Expression::Var(name))
-                },
-                None => Err(ParseError::VariableParseError)
-            }
+            Ok(language::Expression::Var(parse_name(cur_tok, context)?))
         },
         TOKEN_TYPE::INT_LIT => {
             match lexer::val_to_str(&cur_tok.val).map(|s| s.parse::<i32>()) {
@@ -397,34 +864,59 @@ fn rvallit(cur_tok: &mut token, context: *const c_void) -> Result<language::Expr
                     consume_token(cur_tok, TOKEN_TYPE::INT_LIT, context)?;
                     Ok(language::Expression::Val(i))
                 },
-                _ => Err(ParseError::IntegerParseError)
+                _ => Err(ParseError::IntegerParseError(token_location(cur_tok)))
             }
         }
-        _ => Err(ParseError::UnexpectedToken(cur_tok.tok_type))
+        _ => Err(unexpected(cur_tok))
     }
 }
 
-fn parse_expression(cur_tok: &mut token, context: *const c_void) -> Result<language::Expression, ParseError> {
+/// A variable, `lib.x` if it is qualified with the module it is from
+fn parse_name(cur_tok: &mut token, context: &mut Lexer) -> Result<String, ParseError> {
+    let mut name = match lexer::val_to_str(&cur_tok.val) {
+        None => return Err(ParseError::VariableParseError(token_location(cur_tok))),
+        Some(n) => n
+    };
+    consume_token(cur_tok, TOKEN_TYPE::VAR, context)?;
+
+    if cur_tok.tok_type == TOKEN_TYPE::DOT {
+        consume_token(cur_tok, TOKEN_TYPE::DOT, context)?;
+        if cur_tok.tok_type != TOKEN_TYPE::VAR {
+            return Err(unexpected(cur_tok));
+        }
+        match lexer::val_to_str(&cur_tok.val) {
+            None => return Err(ParseError::VariableParseError(token_location(cur_tok))),
+            Some(var) => name = format!("{}.{}", name, var)
+        }
+        consume_token(cur_tok, TOKEN_TYPE::VAR, context)?;
+    }
+
+    return Ok(name);
+}
+
+fn parse_expression(cur_tok: &mut token, context: &mut Lexer) -> Result<language::Expression, ParseError> {
     let lhs = rvaland(cur_tok, context)?;
     return rvalor(cur_tok, context, lhs);
 }
 
-fn consume_token(cur_tok: &mut token, expected: TOKEN_TYPE, context: *const c_void) -> Result<(), ParseError> {
-    if !(cur_tok.tok_type == expected) {
-        return Err(ParseError::UnexpectedToken(cur_tok.tok_type));
+fn consume_token(cur_tok: &mut token, expected: TOKEN_TYPE, context: &mut Lexer) -> Result<(), ParseError> {
+    if cur_tok.tok_type != expected {
+        return Err(unexpected(cur_tok));
     }
-    *cur_tok = get_token_safe(context);
+    *cur_tok = context.next_token();
     return Ok(());
 }
 
-/// Consumes the token without checking what it is for a parse error
-/// Only to be used if the token could be on of a set of options
-fn consume_token_unchecked(cur_tok: &mut token, context: *const c_void) {
-    *cur_tok = get_token_safe(context);
+fn token_location(tok: &token) -> Location {
+    Location::new(tok.line as u32, tok.column as u32)
+}
+
+fn unexpected(tok: &token) -> ParseError {
+    ParseError::UnexpectedToken(tok.tok_type, token_location(tok))
 }
 
-fn token_to_op(tok: TOKEN_TYPE) -> Result<Op, ParseError> {
-    match tok {
+fn token_to_op(tok: &token) -> Result<Op, ParseError> {
+    match tok.tok_type {
         TOKEN_TYPE::PLUS => Ok(Op::Add),
         TOKEN_TYPE::MINUS => Ok(Op::Sub),
         TOKEN_TYPE::ASTERIX => Ok(Op::Multiply),
@@ -438,6 +930,6 @@ fn token_to_op(tok: TOKEN_TYPE) -> Result<Op, ParseError> {
         TOKEN_TYPE::LT => Ok(Op::LessThan),
         TOKEN_TYPE::GE => Ok(Op::GreaterThanOrEqual),
         TOKEN_TYPE::GT => Ok(Op::GreaterThan),
-        _ => Err(ParseError::UnknownOperator(tok))
+        _ => Err(ParseError::UnknownOperator(tok.tok_type, token_location(tok)))
     }
-}
\ No newline at end of file
+}
@@ -1,21 +1,22 @@
 use crate::*;
+use std::path::Path;
 
 #[test]
 fn just_assign() {
-    let context = get_file_context("test/justassign.xa").expect("Could not open file");
+    let mut context = get_file_context("test/justassign.xa").expect("Could not open file");
 
-    let parse_result = gen_ast(context);
-    let ast;
-    match parse_result {
+    let parse_result = gen_ast(&mut context);
+    let ast = match parse_result {
         Err(e) => panic!("AST generation returned Err: {:?}", e),
-        Ok(a) => ast = a
-    }
+        Ok(a) => a
+    };
 
     let predicted = language::Program{
         program: Block { statements: vec![
             language::Statement::AssignStatement { 
                 var: String::from("variable"), 
-                exp: language::Expression::Val(4) 
+                exp: language::Expression::Val(4),
+                loc: Location::new(1, 1) 
             },
 
             language::Statement::AssignStatement { 
@@ -24,9 +25,11 @@ fn just_assign() {
                     language::Op::Add,
                     Box::new(Expression::Val(3)),
                     Box::new(Expression::Val(9))
-                ) 
+                ),
+                loc: Location::new(2, 1) 
             }
-        ] }
+        ] },
+        tests: Vec::new()
     };
 
     assert_eq!(ast, predicted);
@@ -34,14 +37,13 @@ fn just_assign() {
 
 #[test]
 fn just_repeat() {
-    let context = get_file_context("test/justrepeat.xa").expect("Could not open file");
+    let mut context = get_file_context("test/justrepeat.xa").expect("Could not open file");
 
-    let parse_result = gen_ast(context);
-    let ast;
-    match parse_result {
+    let parse_result = gen_ast(&mut context);
+    let ast = match parse_result {
         Err(e) => panic!("AST generation returned Err: {:?}", e),
-        Ok(a) => ast = a
-    }
+        Ok(a) => a
+    };
 
     let predicted = language::Program{
         program: Block { statements: vec![
@@ -50,9 +52,11 @@ fn just_repeat() {
                 body: Block { statements: vec![
                     language::Statement::AssignStatement { 
                         var: String::from("x"), 
-                        exp: language::Expression::Val(5) 
+                        exp: language::Expression::Val(5),
+                        loc: Location::new(2, 5) 
                     }
-                ] }
+                ] },
+                loc: Location::new(1, 1)
             },
 
             language::Statement::RepeatStatement { 
@@ -64,11 +68,14 @@ fn just_repeat() {
                 body: Block { statements: vec![
                     language::Statement::AssignStatement { 
                         var: String::from("y"), 
-                        exp: language::Expression::Val(2) 
+                        exp: language::Expression::Val(2),
+                        loc: Location::new(6, 5) 
                     }
-                ] }   
+                ] },
+                loc: Location::new(5, 1)
             }
-        ] }
+        ] },
+        tests: Vec::new()
     };
 
     assert_eq!(ast, predicted);
@@ -76,14 +83,13 @@ fn just_repeat() {
 
 #[test]
 fn just_if() {
-    let context = get_file_context("test/justif.xa").expect("Could not open file");
+    let mut context = get_file_context("test/justif.xa").expect("Could not open file");
 
-    let parse_result = gen_ast(context);
-    let ast;
-    match parse_result {
+    let parse_result = gen_ast(&mut context);
+    let ast = match parse_result {
         Err(e) => panic!("AST generation returned Err: {:?}", e),
-        Ok(a) => ast = a
-    }
+        Ok(a) => a
+    };
 
     let predicted = language::Program{
         program: Block { statements: vec![
@@ -93,7 +99,8 @@ fn just_if() {
                 body: Block { statements: vec![
                     language::Statement::AssignStatement { 
                         var: String::from("x"), 
-                        exp: language::Expression::Val(5) 
+                        exp: language::Expression::Val(5),
+                        loc: Location::new(2, 5) 
                     }
                 ] },
 
@@ -108,7 +115,8 @@ fn just_if() {
                         Block { statements: vec![
                             language::Statement::AssignStatement { 
                                 var: String::from("y"), 
-                                exp: language::Expression::Val(2) 
+                                exp: language::Expression::Val(2),
+                                loc: Location::new(4, 5) 
                             }
                         ] },
                     ) , (
@@ -121,7 +129,8 @@ fn just_if() {
                         Block { statements: vec![
                             language::Statement::AssignStatement { 
                                 var: String::from("z"), 
-                                exp: language::Expression::Val(5) 
+                                exp: language::Expression::Val(5),
+                                loc: Location::new(6, 5) 
                             }
                         ] },
                     )
@@ -130,9 +139,11 @@ fn just_if() {
                 else_body: Block { statements: vec![
                             language::Statement::AssignStatement { 
                                 var: String::from("abc"), 
-                                exp: language::Expression::Val(0) 
+                                exp: language::Expression::Val(0),
+                                loc: Location::new(8, 5) 
                             }
-                        ] }
+                        ] },
+                loc: Location::new(1, 1)
             },
 
             language::Statement::IfStatement { 
@@ -141,16 +152,137 @@ fn just_if() {
                 body: Block { statements: vec![
                     language::Statement::AssignStatement { 
                         var: String::from("abcd"), 
-                        exp: language::Expression::Val(1) 
+                        exp: language::Expression::Val(1),
+                        loc: Location::new(12, 5) 
                     }
                 ] },
 
                 else_if: vec![],
 
-                else_body: Block { statements: vec![] }
+                else_body: Block { statements: vec![] },
+                loc: Location::new(11, 1)
             },
-        ] }
+        ] },
+        tests: Vec::new()
     };
 
     assert_eq!(ast, predicted);
-}
\ No newline at end of file
+}
+
+#[test]
+fn error_locations() {
+    assert_eq!(
+        parse_source("x = 1;\nOUTPUT x +;"),
+        Err(ParseError::UnexpectedToken(TOKEN_TYPE::SC, Location::new(2, 11)))
+    );
+
+    assert_eq!(
+        parse_source("x = 99999999999;"),
+        Err(ParseError::IntegerParseError(Location::new(1, 5)))
+    );
+
+    assert_eq!(
+        parse_source("REPEAT (1) {\n    x = 1;\n"),
+        Err(ParseError::UnexpectedToken(TOKEN_TYPE::EOF_TOK, Location::new(3, 1)))
+    );
+}
+
+#[test]
+fn assertions_and_tests() {
+    let program = parse_source("ASSERT (x > 1);\nTEST \"adds\" {\n    ASSERT_EQ (1 + 1, 2);\n}").unwrap();
+
+    assert_eq!(program.program.statements, vec![Statement::AssertStatement {
+        exp: Expression::BinOp(Op::GreaterThan, Box::new(Expression::Var(String::from("x"))), Box::new(Expression::Val(1))),
+        expected: None,
+        loc: Location::new(1, 1)
+    }]);
+    assert_eq!(program.tests, vec![Test {
+        name: String::from("adds"),
+        body: Block { statements: vec![Statement::AssertStatement {
+            exp: Expression::BinOp(Op::Add, Box::new(Expression::Val(1)), Box::new(Expression::Val(1))),
+            expected: Some(Expression::Val(2)),
+            loc: Location::new(3, 5)
+        }] },
+        loc: Location::new(2, 1)
+    }]);
+
+    // ASSERT_EQ needs both, and tests can't be nested
+    assert_eq!(
+        parse_source("ASSERT_EQ (1);"),
+        Err(ParseError::UnexpectedToken(TOKEN_TYPE::RPAR, Location::new(1, 13)))
+    );
+    assert_eq!(
+        parse_source("ASSERT (1, 2);"),
+        Err(ParseError::UnexpectedToken(TOKEN_TYPE::COMMA, Location::new(1, 10)))
+    );
+    assert_eq!(
+        parse_source("REPEAT (1) {\n    TEST \"inner\" {\n    }\n}"),
+        Err(ParseError::UnexpectedToken(TOKEN_TYPE::TEST, Location::new(2, 5)))
+    );
+    assert_eq!(
+        parse_source("TEST name {\n}"),
+        Err(ParseError::UnexpectedToken(TOKEN_TYPE::VAR, Location::new(1, 6)))
+    );
+}
+
+fn var(name: &str) -> Box<Expression> {
+    Box::new(Expression::Var(String::from(name)))
+}
+
+#[test]
+fn imports_are_namespaced_or_exposed() {
+    let mut loader = Loader::new();
+    let program = loader.load(Path::new("test/imports/main.xa")).unwrap();
+
+    assert_eq!(program.program.statements, vec![
+        Statement::AssignStatement { var: String::from("x"), exp: Expression::Val(1), loc: Location::new(1, 1) },
+        Statement::AssignStatement { var: String::from("shapes.side"), exp: Expression::Val(3), loc: Location::new(1, 1).in_file(1) },
+        Statement::AssignStatement {
+            var: String::from("shapes.area"),
+            exp: Expression::BinOp(Op::Multiply, var("shapes.side"), var("shapes.side")),
+            loc: Location::new(2, 1).in_file(1)
+        },
+        Statement::OutputStatement { to_output: Expression::BinOp(Op::Add, var("shapes.area"), var("x")), loc: Location::new(3, 1) },
+        Statement::AssignStatement { var: String::from("shapes.side"), exp: Expression::Val(5), loc: Location::new(4, 1) }
+    ]);
+    // Only the main file's tests are kept
+    assert_eq!(program.tests, Vec::new());
+    assert_eq!(loader.path(1), Some(Path::new("test/imports/lib/shapes.xa")));
+}
+
+#[test]
+fn each_file_is_read_and_run_once() {
+    let mut loader = Loader::new();
+    let program = loader.load(Path::new("test/imports/diamond.xa")).unwrap();
+
+    let assigned: Vec<_> = program.program.statements.iter().filter_map(|s| match s {
+        Statement::AssignStatement { var, loc, .. } => Some((var.as_str(), loc.file)),
+        _ => None
+    }).collect();
+    assert_eq!(assigned, vec![("base.count", 2), ("base.count", 1), ("base.count", 3)]);
+    assert_eq!(loader.path(4), None);
+}
+
+#[test]
+fn import_errors_point_into_the_right_file() {
+    let mut loader = Loader::new();
+
+    let error = loader.load(Path::new("test/imports/broken.xa")).unwrap_err();
+    assert_eq!(error, ParseError::UnexpectedToken(TOKEN_TYPE::SC, Location::new(2, 5).in_file(1)));
+    assert_eq!(loader.describe(&error), "test/imports/bad.xa:2:5: unexpected token SC");
+
+    let error = loader.load(Path::new("test/imports/cycle/a.xa")).unwrap_err();
+    assert_eq!(error, ParseError::ImportCycle(Location::new(1, 1).in_file(1)));
+    assert_eq!(loader.describe(&error), "test/imports/cycle/b.xa:1:1: import cycle, the file imports itself");
+
+    let dir = Path::new("test/imports");
+    assert_eq!(loader.load_source("IMPORT \"missing.xa\";", dir), Err(ParseError::ImportNotFound(Location::new(1, 1))));
+    assert_eq!(loader.load_source("IMPORT \"lib/shapes.xa\" EXPOSING (side, volume);", dir), Err(ParseError::UnknownImport(Location::new(1, 40))));
+    assert_eq!(loader.load_source("IMPORT \"base.xa\";\nOUTPUT base.total;", dir), Err(ParseError::UnknownImport(Location::new(2, 1))));
+    assert_eq!(loader.load_source("OUTPUT lib.x;", dir), Err(ParseError::UnknownModule(Location::new(1, 1))));
+    assert_eq!(loader.load_source("IF (1) {\n    IMPORT \"base.xa\";\n}", dir), Err(ParseError::UnexpectedToken(TOKEN_TYPE::IMPORT, Location::new(2, 5))));
+    assert_eq!(loader.describe(&ParseError::UnknownModule(Location::new(1, 1))), "1:1: no imported file has that name");
+
+    // Without a file there is nothing to import relative to
+    assert_eq!(parse_source("x = 1;\nIMPORT \"base.xa\";"), Err(ParseError::ImportWithoutFile(Location::new(2, 1))));
+}
@@ -0,0 +1,255 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use interpreter::{ErrorType, Hook, Memory};
+use language::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SiteKind {
+    Statement,
+    BinOp(Op)
+}
+
+/// A statement or binary operation in the program and what running it cost.
+/// `self_time` leaves out time spent in the sites nested inside it
+#[derive(Debug, Clone)]
+pub struct Site {
+    pub kind: SiteKind,
+    pub label: String,
+    /// For a binary operation this is where its statement starts
+    pub loc: Location,
+    pub count: u64,
+    pub total: Duration,
+    pub self_time: Duration,
+    // Statements: the folded stack down to this statement, and self time
+    // of the statement plus the binary operations directly inside it
+    stack: String,
+    own_time: Duration,
+    // Binary operations: the statement they belong to
+    statement: usize
+}
+
+struct Frame {
+    site: usize,
+    start: Instant,
+    children: Duration
+}
+
+/// Hook that times every statement and binary operation of one program.
+/// Sites are found by address so the profiler has to be used to run the
+/// same program it was made from
+pub struct Profiler<'p> {
+    sites: Vec<Site>,
+    statements: HashMap<*const Statement, usize>,
+    bin_ops: HashMap<*const Expression, usize>,
+    stack: Vec<Frame>,
+    _program: PhantomData<&'p Program>
+}
+
+impl<'p> Profiler<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        let mut profiler = Self{
+            sites: Vec::new(),
+            statements: HashMap::new(),
+            bin_ops: HashMap::new(),
+            stack: Vec::new(),
+            _program: PhantomData
+        };
+
+        profiler.add_block(&program.program, "program");
+        profiler
+    }
+
+    fn add_site(&mut self, kind: SiteKind, label: String, loc: Location, stack: String, statement: usize) -> usize {
+        self.sites.push(Site{
+            kind,
+            label,
+            loc,
+            count: 0,
+            total: Duration::ZERO,
+            self_time: Duration::ZERO,
+            stack,
+            own_time: Duration::ZERO,
+            statement
+        });
+
+        self.sites.len() - 1
+    }
+
+    fn add_block(&mut self, block: &Block, stack: &str) {
+        for stmt in block.statements.iter() {
+            let loc = stmt.location();
+            let name = match stmt {
+                Statement::AssignStatement { .. } => "ASSIGN",
+                Statement::IfStatement { .. } => "IF",
+                Statement::RepeatStatement { .. } => "REPEAT",
+                Statement::OutputStatement { .. } => "OUTPUT",
+                Statement::AssertStatement { .. } => "ASSERT"
+            };
+            let label = format!("{}@{}", name, loc);
+            let stmt_stack = format!("{};{}", stack, label);
+
+            let id = self.sites.len();
+            self.add_site(SiteKind::Statement, label, loc, stmt_stack.clone(), id);
+            self.statements.insert(stmt as *const Statement, id);
+
+            let mut ordinal = 0;
+            match stmt {
+                Statement::AssignStatement { exp, .. } => self.add_exp(exp, id, &mut ordinal),
+                Statement::OutputStatement { to_output, .. } => self.add_exp(to_output, id, &mut ordinal),
+                Statement::AssertStatement { exp, expected, .. } => {
+                    self.add_exp(exp, id, &mut ordinal);
+                    if let Some(expected) = expected {
+                        self.add_exp(expected, id, &mut ordinal);
+                    }
+                },
+                Statement::RepeatStatement { times, body, .. } => {
+                    self.add_exp(times, id, &mut ordinal);
+                    self.add_block(body, &stmt_stack);
+                },
+                Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                    self.add_exp(condition, id, &mut ordinal);
+                    self.add_block(body, &stmt_stack);
+
+                    for (exp, block) in else_if {
+                        self.add_exp(exp, id, &mut ordinal);
+                        self.add_block(block, &stmt_stack);
+                    }
+
+                    self.add_block(else_body, &stmt_stack);
+                }
+            }
+        }
+    }
+
+    fn add_exp(&mut self, exp: &Expression, statement: usize, ordinal: &mut usize) {
+        if let Expression::BinOp(op, lhs, rhs) = exp {
+            let loc = self.sites[statement].loc;
+            let label = format!("{:?}@{}#{}", op, loc, ordinal);
+            *ordinal += 1;
+
+            let id = self.add_site(SiteKind::BinOp(*op), label, loc, String::new(), statement);
+            self.bin_ops.insert(exp as *const Expression, id);
+
+            self.add_exp(lhs, statement, ordinal);
+            self.add_exp(rhs, statement, ordinal);
+        }
+    }
+
+    fn enter(&mut self, site: usize) {
+        self.sites[site].count += 1;
+        self.stack.push(Frame{site, start: Instant::now(), children: Duration::ZERO});
+    }
+
+    fn leave(&mut self) {
+        let frame = self.stack.pop().expect("Left a site that was never entered");
+        let total = frame.start.elapsed();
+        let self_time = total.saturating_sub(frame.children);
+
+        let site = &mut self.sites[frame.site];
+        site.total += total;
+        site.self_time += self_time;
+
+        let statement = site.statement;
+        self.sites[statement].own_time += self_time;
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += total;
+        }
+    }
+
+    pub fn finish(self) -> Profile {
+        Profile{sites: self.sites}
+    }
+}
+
+impl<'p> Hook for Profiler<'p> {
+    fn before_statement(&mut self, stmt: &Statement, _depth: usize, _memory: &mut Memory) -> Result<(), ErrorType> {
+        let site = *self.statements.get(&(stmt as *const Statement))
+                                   .expect("Statement is not from the program given to Profiler::new");
+        self.enter(site);
+        Ok(())
+    }
+
+    fn after_statement(&mut self, _stmt: &Statement) {
+        self.leave();
+    }
+
+    fn before_bin_op(&mut self, exp: &Expression) {
+        let site = *self.bin_ops.get(&(exp as *const Expression))
+                                .expect("Expression is not from the program given to Profiler::new");
+        self.enter(site);
+    }
+
+    fn after_bin_op(&mut self, _exp: &Expression, _val: i32) {
+        self.leave();
+    }
+}
+
+fn micros(d: Duration) -> f64 {
+    d.as_secs_f64() * 1_000_000.0
+}
+
+/// What a profiled run cost, site by site
+pub struct Profile {
+    pub sites: Vec<Site>
+}
+
+impl Profile {
+    /// The source of `file` with how often each line's statements ran and
+    /// the time spent in them, not counting statements nested inside them
+    pub fn annotate(&self, file: FileId, source: &str) -> String {
+        let mut lines: HashMap<(FileId, u32), (u64, Duration)> = HashMap::new();
+        for site in self.sites.iter().filter(|s| s.kind == SiteKind::Statement) {
+            let line = lines.entry((site.loc.file, site.loc.line)).or_insert((0, Duration::ZERO));
+            line.0 += site.count;
+            line.1 += site.own_time;
+        }
+
+        let mut result = format!("{:>10} {:>12} | source\n", "count", "self us");
+        for (i, text) in source.lines().enumerate() {
+            match lines.get(&(file, i as u32 + 1)) {
+                Some((count, time)) => writeln!(result, "{:>10} {:>12.3} | {}", count, micros(*time), text),
+                None => writeln!(result, "{:>10} {:>12} | {}", "", "", text)
+            }.unwrap();
+        }
+
+        return result;
+    }
+
+    /// The `n` sites with the most self time
+    pub fn top(&self, n: usize) -> String {
+        let mut sites: Vec<&Site> = self.sites.iter().filter(|s| s.count > 0).collect();
+        sites.sort_by(|a, b| b.self_time.cmp(&a.self_time).then_with(|| a.label.cmp(&b.label)));
+
+        let mut result = format!("{:>12} {:>12} {:>10}  site\n", "self us", "total us", "count");
+        for site in sites.iter().take(n) {
+            writeln!(result, "{:>12.3} {:>12.3} {:>10}  {}", micros(site.self_time), micros(site.total), site.count, site.label).unwrap();
+        }
+
+        return result;
+    }
+
+    /// Folded stacks for flamegraph tools, one line per statement that ran.
+    /// Frames are the IF and REPEAT statements it is nested in and the
+    /// value is nanoseconds spent in the statement itself
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&str, u128)> = self.sites.iter()
+            .filter(|s| s.kind == SiteKind::Statement && s.count > 0)
+            .map(|s| (s.stack.as_str(), s.own_time.as_nanos()))
+            .collect();
+        stacks.sort();
+
+        let mut result = String::new();
+        for (stack, nanos) in stacks {
+            writeln!(result, "{} {}", stack, nanos).unwrap();
+        }
+
+        return result;
+    }
+}
@@ -0,0 +1,115 @@
+use crate::*;
+
+use interpreter::Interpreter;
+
+const SOURCE: &str = "x = 0;
+REPEAT (3) {
+    x = x + 1 * 2;
+    IF (x > 4) {
+        OUTPUT x;
+    }
+}";
+
+fn profile(source: &str) -> Profile {
+    let program = parser::parse_source(source).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Profiler::new(&program));
+    interpreter.run(&program).unwrap();
+
+    interpreter.hook.finish()
+}
+
+fn site<'a>(profile: &'a Profile, label: &str) -> &'a Site {
+    profile.sites.iter().find(|s| s.label == label).unwrap()
+}
+
+#[test]
+fn counts_sites() {
+    let profile = profile(SOURCE);
+
+    let counts: Vec<(&str, u64)> = profile.sites.iter().map(|s| (s.label.as_str(), s.count)).collect();
+    assert_eq!(counts, vec![
+        ("ASSIGN@1:1", 1),
+        ("REPEAT@2:1", 1),
+        ("ASSIGN@3:5", 3),
+        ("Add@3:5#0", 3),
+        ("Multiply@3:5#1", 3),
+        ("IF@4:5", 3),
+        ("GreaterThan@4:5#0", 3),
+        ("OUTPUT@5:9", 1)
+    ]);
+
+    assert_eq!(site(&profile, "Add@3:5#0").kind, SiteKind::BinOp(Op::Add));
+}
+
+#[test]
+fn times_nest() {
+    let profile = profile(SOURCE);
+
+    for site in profile.sites.iter() {
+        assert!(site.self_time <= site.total, "{}", site.label);
+    }
+
+    let repeat = site(&profile, "REPEAT@2:1");
+    let assign = site(&profile, "ASSIGN@3:5");
+    let add = site(&profile, "Add@3:5#0");
+    let multiply = site(&profile, "Multiply@3:5#1");
+
+    assert!(repeat.total >= assign.total + site(&profile, "IF@4:5").total);
+    assert!(assign.total >= add.total);
+    assert!(add.total >= multiply.total);
+    assert!(add.self_time <= add.total - multiply.total);
+}
+
+#[test]
+fn reports() {
+    let profile = profile(SOURCE);
+
+    let folded = profile.folded();
+    let stacks: Vec<&str> = folded.lines().map(|l| l.rsplit_once(' ').unwrap().0).collect();
+    assert_eq!(stacks, vec![
+        "program;ASSIGN@1:1",
+        "program;REPEAT@2:1",
+        "program;REPEAT@2:1;ASSIGN@3:5",
+        "program;REPEAT@2:1;IF@4:5",
+        "program;REPEAT@2:1;IF@4:5;OUTPUT@5:9"
+    ]);
+
+    let annotated = profile.annotate(0, SOURCE);
+    let counts: Vec<&str> = annotated.lines().map(|l| l.split_whitespace().next().unwrap_or("")).collect();
+    assert_eq!(counts, vec!["count", "1", "1", "3", "3", "1", "|", "|"]);
+    assert!(annotated.lines().nth(3).unwrap().ends_with("|     x = x + 1 * 2;"));
+
+    let top = profile.top(3);
+    assert_eq!(top.lines().count(), 4);
+    assert!(top.starts_with("     self us     total us      count  site\n"));
+}
+
+#[test]
+fn sites_that_never_ran() {
+    let profile = profile("IF (0) {\n    OUTPUT 1 + 1;\n}");
+
+    assert_eq!(site(&profile, "OUTPUT@2:5").count, 0);
+    assert_eq!(site(&profile, "Add@2:5#0").count, 0);
+    assert!(!profile.top(10).contains("OUTPUT@2:5"));
+    assert_eq!(profile.folded().lines().count(), 1);
+}
+
+#[test]
+fn imported_files_have_their_own_lines() {
+    let dir = std::env::temp_dir().join(format!("profiler-imports-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lib = "x = 4;\n";
+    std::fs::write(dir.join("lib.xa"), lib).unwrap();
+    let source = "IMPORT \"lib.xa\";\nOUTPUT lib.x;";
+    let program = parser::Loader::new().load_source(source, &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Profiler::new(&program));
+    interpreter.run(&program).unwrap();
+    let profile = interpreter.hook.finish();
+
+    let counts = |annotated: String| annotated.lines().map(|l| l.split_whitespace().next().unwrap_or("").to_string()).collect::<Vec<_>>();
+    assert_eq!(counts(profile.annotate(0, source)), vec!["count", "|", "1"]);
+    assert_eq!(counts(profile.annotate(1, lib)), vec!["count", "1"]);
+}
@@ -0,0 +1,248 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use interpreter::{Branch, ErrorType, Hook, Memory};
+use language::*;
+
+/// A variable being given a value. `old` is what it held before, so the
+/// write can be undone when going backwards
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Store {
+    pub var: u32,
+    pub old: Option<i32>,
+    pub new: i32
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Decision {
+    Branch(Branch),
+    Repeat(i32)
+}
+
+/// One statement being run
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Step {
+    pub loc: Location,
+    pub store: Option<Store>,
+    pub decision: Option<Decision>
+}
+
+/// Everything a run did. Steps count statements run from 1, and the state
+/// "at" a step is the state once that statement has run, so step 0 is the
+/// state before the program started
+#[derive(Debug, Default)]
+pub struct Recording {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    steps: Vec<Step>,
+    // Steps writing to each variable, in order
+    writes: Vec<Vec<usize>>
+}
+
+impl Recording {
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn step(&self, step: usize) -> Option<&Step> {
+        if step == 0 {
+            return None;
+        }
+
+        self.steps.get(step - 1)
+    }
+
+    pub fn var_name(&self, var: u32) -> &str {
+        &self.names[var as usize]
+    }
+
+    fn var_id(&self, var: &str) -> Option<usize> {
+        self.ids.get(var).map(|id| *id as usize)
+    }
+
+    /// Names of every variable written during the run
+    pub fn vars(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Value of `var` once `step` has run
+    pub fn value_at(&self, var: &str, step: usize) -> Option<i32> {
+        let id = self.var_id(var)?;
+        let writes = &self.writes[id];
+
+        // Number of writes up to and including this step
+        let count = writes.partition_point(|&s| s <= step);
+
+        if count == 0 {
+            // Whatever it held before the first write
+            return self.steps[writes[0] - 1].store.and_then(|s| s.old);
+        }
+
+        return self.steps[writes[count - 1] - 1].store.map(|s| s.new);
+    }
+
+    /// The last step before `step` that wrote to `var`
+    pub fn last_write_before(&self, var: &str, step: usize) -> Option<usize> {
+        let writes = &self.writes[self.var_id(var)?];
+        let count = writes.partition_point(|&s| s < step);
+
+        if count == 0 {
+            return None;
+        }
+
+        return Some(writes[count - 1]);
+    }
+}
+
+/// Hook that builds a `Recording` of the run
+#[derive(Default)]
+pub struct Recorder {
+    recording: Recording,
+    // Value of the variable being assigned before the assignment
+    old: Option<i32>
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+
+    fn current(&mut self) -> &mut Step {
+        self.recording.steps.last_mut().expect("Event before any statement ran")
+    }
+}
+
+impl Hook for Recorder {
+    fn before_statement(&mut self, stmt: &Statement, _depth: usize, memory: &mut Memory) -> Result<(), ErrorType> {
+        if let Statement::AssignStatement { var, .. } = stmt {
+            self.old = memory.mem.get(var).copied();
+        }
+
+        self.recording.steps.push(Step{loc: stmt.location(), store: None, decision: None});
+        Ok(())
+    }
+
+    fn after_assign(&mut self, _stmt: &Statement, var: &str, val: i32) {
+        let recording = &mut self.recording;
+        let id = match recording.ids.get(var) {
+            Some(id) => *id,
+            None => {
+                let id = recording.names.len() as u32;
+                recording.names.push(String::from(var));
+                recording.writes.push(Vec::new());
+                recording.ids.insert(String::from(var), id);
+                id
+            }
+        };
+
+        let step = self.recording.steps.len();
+        self.recording.writes[id as usize].push(step);
+
+        let old = self.old;
+        self.current().store = Some(Store{var: id, old, new: val});
+    }
+
+    fn after_branch(&mut self, _stmt: &Statement, taken: Branch) {
+        self.current().decision = Some(Decision::Branch(taken));
+    }
+
+    fn before_repeat(&mut self, _stmt: &Statement, times: i32) {
+        self.current().decision = Some(Decision::Repeat(times));
+    }
+}
+
+const HELP: &str = "\
+goto, g STEP      move to STEP, 0 is before the program started
+back, b [N]       move back N steps, 1 by default
+forward, f [N]    move forward N steps, 1 by default
+print, p VAR      show the value of VAR at this step
+last, l VAR       show when VAR was last written before this step
+vars              show every variable at this step
+where, w          show the current step
+quit, q           stop";
+
+/// Lets the user move around a recording, reading commands from `input`
+/// until it runs out or they quit. `sources` are used to show lines
+pub fn explore<R: BufRead, W: Write>(recording: &Recording, sources: &Sources, mut input: R, mut output: W) -> io::Result<()> {
+    let mut step = recording.len();
+
+    let show = |output: &mut W, step: usize| -> io::Result<()> {
+        match recording.step(step) {
+            Some(s) => writeln!(output, "step {} of {} at {}: {}", step, recording.len(), sources.locate(s.loc), sources.line(s.loc)),
+            None => writeln!(output, "step 0 of {}, before the program started", recording.len())
+        }
+    };
+
+    show(&mut output, step)?;
+
+    loop {
+        write!(output, "(replay) ")?;
+        output.flush()?;
+
+        let mut command = String::new();
+        if input.read_line(&mut command)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let count = |n: Option<&&str>| n.map_or(Some(1), |n| n.parse::<usize>().ok());
+
+        match words.as_slice() {
+            [] => (),
+            ["goto" | "g", n] => match n.parse::<usize>() {
+                Ok(n) => {
+                    step = n.min(recording.len());
+                    show(&mut output, step)?;
+                },
+                Err(_) => writeln!(output, "{:?} is not a step", n)?
+            },
+            ["back" | "b", rest @ ..] if rest.len() <= 1 => match count(rest.first()) {
+                Some(n) => {
+                    step = step.saturating_sub(n);
+                    show(&mut output, step)?;
+                },
+                None => writeln!(output, "{:?} is not a number of steps", rest[0])?
+            },
+            ["forward" | "f", rest @ ..] if rest.len() <= 1 => match count(rest.first()) {
+                Some(n) => {
+                    step = (step + n).min(recording.len());
+                    show(&mut output, step)?;
+                },
+                None => writeln!(output, "{:?} is not a number of steps", rest[0])?
+            },
+            ["print" | "p", var] => match recording.value_at(var, step) {
+                Some(v) => writeln!(output, "{} = {}", var, v)?,
+                None => writeln!(output, "{} has not been assigned", var)?
+            },
+            ["last" | "l", var] => match recording.last_write_before(var, step) {
+                Some(s) => writeln!(output, "{} was last written at step {} ({})", var, s, sources.locate(recording.steps[s - 1].loc))?,
+                None => writeln!(output, "{} was not written before step {}", var, step)?
+            },
+            ["vars"] => {
+                let mut vars: Vec<&str> = recording.vars().collect();
+                vars.sort();
+                for var in vars {
+                    if let Some(v) = recording.value_at(var, step) {
+                        writeln!(output, "{} = {}", var, v)?;
+                    }
+                }
+            },
+            ["where" | "w"] => show(&mut output, step)?,
+            ["quit" | "q"] => return Ok(()),
+            ["help" | "h"] => writeln!(output, "{}", HELP)?,
+            _ => writeln!(output, "unknown command {:?}, try help", command.trim())?
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::*;
+
+use interpreter::Interpreter;
+
+const SOURCE: &str = "a = 1;
+b = 0;
+REPEAT (3) {
+    b = b + a;
+    IF (b == 2) {
+        a = 5;
+    }
+}
+OUTPUT b;";
+
+fn record(source: &str, memory: Memory) -> Recording {
+    let program = parser::parse_source(source).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Recorder::new());
+    interpreter.memory = memory;
+    interpreter.run(&program).unwrap();
+
+    interpreter.hook.finish()
+}
+
+#[test]
+fn records_steps() {
+    let recording = record(SOURCE, Memory::new());
+
+    // a=1, b=0, REPEAT, b=1, IF, b=2, IF, a=5, b=7, IF, OUTPUT
+    assert_eq!(recording.len(), 11);
+
+    assert_eq!(recording.step(1), Some(&Step{
+        loc: Location::new(1, 1),
+        store: Some(Store{var: 0, old: None, new: 1}),
+        decision: None
+    }));
+    assert_eq!(recording.step(3).unwrap().decision, Some(Decision::Repeat(3)));
+    assert_eq!(recording.step(5).unwrap().decision, Some(Decision::Branch(Branch::Else)));
+    assert_eq!(recording.step(7).unwrap().decision, Some(Decision::Branch(Branch::If)));
+    assert_eq!(recording.step(8).unwrap().store, Some(Store{var: 0, old: Some(1), new: 5}));
+    assert_eq!(recording.step(0), None);
+    assert_eq!(recording.step(12), None);
+}
+
+#[test]
+fn values_at_steps() {
+    let recording = record(SOURCE, Memory::new());
+
+    assert_eq!(recording.value_at("a", 0), None);
+    assert_eq!(recording.value_at("a", 1), Some(1));
+    assert_eq!(recording.value_at("b", 1), None);
+    assert_eq!(recording.value_at("b", 4), Some(1));
+    assert_eq!(recording.value_at("a", 7), Some(1));
+    assert_eq!(recording.value_at("a", 8), Some(5));
+    assert_eq!(recording.value_at("b", 11), Some(7));
+    assert_eq!(recording.value_at("c", 11), None);
+
+    // Values from before the run are found from the first write
+    let mut memory = Memory::new();
+    memory.mem.insert(String::from("a"), 40);
+    let recording = record(SOURCE, memory);
+    assert_eq!(recording.value_at("a", 0), Some(40));
+}
+
+#[test]
+fn last_writes() {
+    let recording = record(SOURCE, Memory::new());
+
+    assert_eq!(recording.last_write_before("b", 11), Some(9));
+    assert_eq!(recording.last_write_before("b", 9), Some(6));
+    assert_eq!(recording.last_write_before("a", 8), Some(1));
+    assert_eq!(recording.last_write_before("a", 1), None);
+    assert_eq!(recording.last_write_before("c", 11), None);
+}
+
+#[test]
+fn explore_session() {
+    let recording = record(SOURCE, Memory::new());
+    let mut said = Vec::new();
+
+    explore(&recording, &Sources::new(SOURCE), "p a\ng 7\np a\nlast b\nb 2\nw\nf 100\nvars\nq\n".as_bytes(), &mut said).unwrap();
+
+    assert_eq!(String::from_utf8(said).unwrap(), "\
+step 11 of 11 at 9:1: OUTPUT b;
+(replay) a = 5
+(replay) step 7 of 11 at 5:5: IF (b == 2) {
+(replay) a = 1
+(replay) b was last written at step 6 (4:5)
+(replay) step 5 of 11 at 5:5: IF (b == 2) {
+(replay) step 5 of 11 at 5:5: IF (b == 2) {
+(replay) step 11 of 11 at 9:1: OUTPUT b;
+(replay) a = 5
+b = 7
+(replay) ");
+}
+
+#[test]
+fn steps_in_imported_files() {
+    let dir = std::env::temp_dir().join(format!("replay-imports-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lib = "x = 4;\n";
+    std::fs::write(dir.join("lib.xa"), lib).unwrap();
+    let source = "IMPORT \"lib.xa\";\ny = lib.x + 1;";
+    let program = parser::Loader::new().load_source(source, &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Recorder::new());
+    interpreter.run(&program).unwrap();
+    let recording = interpreter.hook.finish();
+
+    let mut sources = Sources::new(source);
+    sources.add(1, "lib.xa", lib);
+    let mut said = Vec::new();
+    explore(&recording, &sources, "g 1\nf\nlast lib.x\n".as_bytes(), &mut said).unwrap();
+
+    assert_eq!(String::from_utf8(said).unwrap(), "\
+step 2 of 2 at 2:1: y = lib.x + 1;
+(replay) step 1 of 2 at lib.xa:1:1: x = 4;
+(replay) step 2 of 2 at 2:1: y = lib.x + 1;
+(replay) lib.x was last written at step 1 (lib.xa:1:1)
+(replay) \n");
+}
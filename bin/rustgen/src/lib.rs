@@ -0,0 +1,348 @@
+//! Translates a program to a Rust function `pub fn run() -> Vec<i32>`
+//! returning what the program outputs. Runtime errors become panics whose
+//! message starts with the `line:column` of the statement, as the
+//! interpreter's errors do.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use language::*;
+
+/// Name of the vector the outputs are collected in
+const OUTPUT: &str = "output";
+
+/// What the translation needs to know about each variable
+#[derive(Debug, Default)]
+struct VarInfo {
+    /// Read somewhere it might not have been assigned yet, so it has to be
+    /// an `Option` that is checked
+    maybe_unassigned: bool,
+    read: bool,
+    /// Can be assigned more than once in a run, so needs `mut`
+    reassigned: bool
+}
+
+/// Works out `VarInfo` the way rustc does its own checks: a REPEAT body
+/// might not run and an IF only assigns what every arm assigns
+#[derive(Default)]
+struct Analysis {
+    vars: BTreeMap<String, VarInfo>
+}
+
+impl Analysis {
+    fn exp(&mut self, exp: &Expression, assigned: &BTreeSet<String>) {
+        match exp {
+            Expression::Val(_) => (),
+            Expression::Var(var) => {
+                let info = self.vars.entry(var.clone()).or_default();
+                info.read = true;
+                info.maybe_unassigned |= !assigned.contains(var);
+            },
+            Expression::BinOp(_, lhs, rhs) => {
+                self.exp(lhs, assigned);
+                self.exp(rhs, assigned);
+            }
+        }
+    }
+
+    /// Returns what is definitely assigned after `block`, and the most
+    /// times each variable can be assigned in one pass through it
+    fn block(&mut self, block: &Block, assigned: &BTreeSet<String>) -> (BTreeSet<String>, BTreeMap<String, u32>) {
+        let mut assigned = assigned.clone();
+        let mut counts = BTreeMap::new();
+
+        for stmt in block.statements.iter() {
+            match stmt {
+                Statement::AssignStatement { var, exp, .. } => {
+                    self.exp(exp, &assigned);
+                    self.vars.entry(var.clone()).or_default();
+                    assigned.insert(var.clone());
+                    *counts.entry(var.clone()).or_insert(0) += 1;
+                },
+                Statement::OutputStatement { to_output, .. } => self.exp(to_output, &assigned),
+                Statement::AssertStatement { exp, expected, .. } => {
+                    self.exp(exp, &assigned);
+                    if let Some(expected) = expected {
+                        self.exp(expected, &assigned);
+                    }
+                },
+                Statement::RepeatStatement { times, body, .. } => {
+                    self.exp(times, &assigned);
+                    // Whatever the body assigns it can assign again next time
+                    let (_, body_counts) = self.block(body, &assigned);
+                    for var in body_counts.keys() {
+                        counts.insert(var.clone(), 2);
+                    }
+                },
+                Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                    self.exp(condition, &assigned);
+                    let mut arms = vec![self.block(body, &assigned)];
+                    for (exp, block) in else_if {
+                        self.exp(exp, &assigned);
+                        arms.push(self.block(block, &assigned));
+                    }
+                    arms.push(self.block(else_body, &assigned));
+
+                    let mut arm_counts: BTreeMap<String, u32> = BTreeMap::new();
+                    for (_, c) in arms.iter() {
+                        for (var, n) in c {
+                            let most = arm_counts.entry(var.clone()).or_insert(0);
+                            *most = u32::max(*most, *n);
+                        }
+                    }
+                    for (var, n) in arm_counts {
+                        *counts.entry(var).or_insert(0) += n;
+                    }
+
+                    assigned = arms.iter()
+                        .map(|(a, _)| a.clone())
+                        .reduce(|a, b| a.intersection(&b).cloned().collect())
+                        .unwrap();
+                }
+            }
+        }
+
+        return (assigned, counts);
+    }
+}
+
+struct Generator {
+    out: String,
+    indent: usize,
+    vars: BTreeMap<String, VarInfo>,
+    /// Variables already declared with `let`
+    declared: BTreeSet<String>
+}
+
+impl Generator {
+    fn line(&mut self, text: &str) {
+        if !text.is_empty() {
+            for _ in 0..self.indent {
+                self.out.push_str("    ");
+            }
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Rust name for an xa variable, prefixed so it can't clash with a
+    /// keyword or a name from the prelude like `None`. An imported `lib.x`
+    /// becomes `v_lib_x`, plain names never have underscores
+    fn name(&self, var: &str) -> String {
+        let name = format!("v_{}", var.replace('.', "_"));
+        match self.vars[var].read {
+            true => return name,
+            false => return format!("_{}", name)
+        }
+    }
+
+    /// `exp` where it has to already be an `i32`, as the receiver of a method
+    fn typed(&self, exp: &Expression, loc: Location) -> String {
+        match exp {
+            // Without the brackets `-5_i32.checked_add(a)` is read as
+            // `-(5_i32.checked_add(a))`
+            Expression::Val(v) if *v < 0 => return format!("({}_i32)", v),
+            Expression::Val(v) => return format!("{}_i32", v),
+            _ => return self.exp(exp, loc)
+        }
+    }
+
+    fn exp(&self, exp: &Expression, loc: Location) -> String {
+        match exp {
+            Expression::Val(v) => v.to_string(),
+            Expression::Var(var) => match self.vars[var].maybe_unassigned {
+                true => format!("{}.expect(\"{}: Variable \\\"{}\\\" has not been assigned\")", self.name(var), loc, var),
+                false => self.name(var)
+            },
+            Expression::BinOp(op, lhs, rhs) => {
+                let method = match op {
+                    Op::Add => ("checked_add", "addition overflowed"),
+                    Op::Sub => ("checked_sub", "subtraction overflowed"),
+                    Op::Multiply => ("checked_mul", "multiplication overflowed"),
+                    // Dividing by zero and overflowing stop with different
+                    // messages, so are told apart first
+                    Op::Divide | Op::Remainder => {
+                        let (method, zero, overflow) = match op {
+                            Op::Divide => ("checked_div", "division by zero", "division overflowed"),
+                            _ => ("checked_rem", "remainder by zero", "remainder overflowed")
+                        };
+                        return format!(
+                            "match ({}, {}) {{ (_, 0) => panic!(\"{}: {}\"), (lhs, rhs) => lhs.{}(rhs).expect(\"{}: {}\") }}",
+                            self.typed(lhs, loc), self.exp(rhs, loc), loc, zero, method, loc, overflow
+                        );
+                    },
+                    _ => return format!("i32::from({})", self.condition(exp, loc))
+                };
+
+                format!("{}.{}({}).expect(\"{}: {}\")", self.typed(lhs, loc), method.0, self.exp(rhs, loc), loc, method.1)
+            }
+        }
+    }
+
+    /// `exp` as a `bool`, for IF conditions and comparisons
+    fn condition(&self, exp: &Expression, loc: Location) -> String {
+        let (op, lhs, rhs) = match exp {
+            Expression::BinOp(op, lhs, rhs) => (op, lhs, rhs),
+            _ => return format!("{} != 0", self.exp(exp, loc))
+        };
+
+        let symbol = match op {
+            Op::Equal => "==",
+            Op::NotEqual => "!=",
+            Op::LessThan => "<",
+            Op::LessThanOrEqual => "<=",
+            Op::GreaterThan => ">",
+            Op::GreaterThanOrEqual => ">=",
+            // & and | rather than && and || as the interpreter works out
+            // both sides, and the right one could panic
+            Op::And => return format!("({} != 0) & ({} != 0)", self.exp(lhs, loc), self.exp(rhs, loc)),
+            Op::Or => return format!("({} != 0) | ({} != 0)", self.exp(lhs, loc), self.exp(rhs, loc)),
+            _ => return format!("{} != 0", self.exp(exp, loc))
+        };
+
+        format!("{} {} {}", self.exp(lhs, loc), symbol, self.exp(rhs, loc))
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        let loc = stmt.location();
+
+        match stmt {
+            Statement::AssignStatement { var, exp, .. } => {
+                let info = &self.vars[var];
+                let value = match info.maybe_unassigned {
+                    true => format!("Some({})", self.exp(exp, loc)),
+                    false => self.exp(exp, loc)
+                };
+
+                if self.declared.insert(var.clone()) {
+                    // Only reached for a first assignment at the top level
+                    let keyword = if info.reassigned { "let mut" } else { "let" };
+                    self.line(&format!("{} {}: i32 = {};", keyword, self.name(var), value));
+                } else {
+                    self.line(&format!("{} = {};", self.name(var), value));
+                }
+            },
+            Statement::OutputStatement { to_output, .. } => {
+                let value = self.exp(to_output, loc);
+                self.line(&format!("{}.push({});", OUTPUT, value));
+            },
+            Statement::AssertStatement { exp, expected, .. } => {
+                let holds = match expected {
+                    Some(expected) => format!("{} == {}", self.exp(exp, loc), self.exp(expected, loc)),
+                    None => self.condition(exp, loc)
+                };
+                self.line(&format!("assert!({}, \"{}: assertion failed\");", holds, loc));
+            },
+            Statement::RepeatStatement { times, body, .. } => {
+                let times = match times {
+                    Expression::Val(v) if *v >= 0 => v.to_string(),
+                    _ => format!(
+                        "usize::try_from({}).unwrap_or_else(|_| panic!(\"{}: REPEAT count is negative\"))",
+                        self.exp(times, loc), loc
+                    )
+                };
+                self.line(&format!("for _ in 0..{} {{", times));
+                self.indent += 1;
+                self.block(body);
+                self.indent -= 1;
+                self.line("}");
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                self.line(&format!("if {} {{", self.condition(condition, loc)));
+                self.indent += 1;
+                self.block(body);
+                self.indent -= 1;
+
+                for (exp, block) in else_if {
+                    self.line(&format!("}} else if {} {{", self.condition(exp, loc)));
+                    self.indent += 1;
+                    self.block(block);
+                    self.indent -= 1;
+                }
+
+                if !else_body.statements.is_empty() {
+                    self.line("} else {");
+                    self.indent += 1;
+                    self.block(else_body);
+                    self.indent -= 1;
+                }
+                self.line("}");
+            }
+        }
+    }
+}
+
+/// Variables assigned inside the blocks of an IF or REPEAT
+fn nested_assigned(stmt: &Statement) -> BTreeSet<String> {
+    match stmt {
+        Statement::RepeatStatement { body, .. } => body.assigned_variables(),
+        Statement::IfStatement { body, else_if, else_body, .. } => {
+            let mut vars = body.assigned_variables();
+            for (_, block) in else_if {
+                vars.extend(block.assigned_variables());
+            }
+            vars.extend(else_body.assigned_variables());
+            vars
+        },
+        _ => BTreeSet::new()
+    }
+}
+
+/// The Rust source for `program`
+pub fn generate(program: &Program) -> String {
+    let mut analysis = Analysis::default();
+    let (_, counts) = analysis.block(&program.program, &BTreeSet::new());
+    for (var, info) in analysis.vars.iter_mut() {
+        info.reassigned = counts.get(var).copied().unwrap_or(0) > 1;
+    }
+
+    // Variables first assigned by a top level statement are declared there,
+    // the rest up front
+    let mut seen = BTreeSet::new();
+    let mut first_at_top = BTreeSet::new();
+    for stmt in program.program.statements.iter() {
+        match stmt {
+            Statement::AssignStatement { var, .. } => {
+                if seen.insert(var.clone()) {
+                    first_at_top.insert(var.clone());
+                }
+            },
+            _ => seen.extend(nested_assigned(stmt))
+        }
+    }
+
+    let mut gen = Generator{out: String::new(), indent: 1, vars: analysis.vars, declared: BTreeSet::new()};
+    gen.line(&format!("let mut {} = Vec::new();", OUTPUT));
+
+    let mut declarations = Vec::new();
+    for (var, info) in gen.vars.iter() {
+        let keyword = if info.reassigned { "let mut" } else { "let" };
+        if info.maybe_unassigned {
+            // Assigned at all means it goes from None to Some
+            let keyword = if counts.contains_key(var) { "let mut" } else { "let" };
+            declarations.push((var.clone(), format!("{} {}: Option<i32> = None;", keyword, gen.name(var))));
+        } else if !first_at_top.contains(var) {
+            declarations.push((var.clone(), format!("{} {}: i32;", keyword, gen.name(var))));
+        }
+    }
+    for (var, declaration) in declarations {
+        gen.line(&declaration);
+        gen.declared.insert(var);
+    }
+    gen.line("");
+
+    gen.block(&program.program);
+    gen.line("");
+    gen.line(OUTPUT);
+
+    // xa names keep their case
+    return format!("/// Generated from an .xa program\n#[allow(non_snake_case)]\npub fn run() -> Vec<i32> {{\n{}}}\n", gen.out);
+}
@@ -0,0 +1,135 @@
+use crate::*;
+
+use conformance::{matches_interpreter, Backend};
+
+/// Given a `main` that prints the outputs and built failing on any warning.
+/// The outputs are only returned at the end, so a panic loses them
+const RUST: Backend = Backend{
+    name: "rustgen",
+    file: "program.rs",
+    generate,
+    suffix: "\nfn main() {\n    for value in run() {\n        println!(\"{}\", value);\n    }\n}\n",
+    compiler: &["rustc", "--edition", "2021", "-D", "warnings"],
+    outputs_before_errors: false
+};
+
+#[test]
+fn examples() {
+    matches_interpreter(&RUST, "fibbonachi", include_str!("../../../examples/fibbonachi.xa"));
+    matches_interpreter(&RUST, "moreops", include_str!("../../../examples/moreops.xa"));
+}
+
+#[test]
+fn control_flow() {
+    matches_interpreter(&RUST, "control_flow", "n = 3;
+unused = 7;
+REPEAT (n) {
+    n = n + 1;
+    IF (n == 4) {
+        late = 1;
+        OUTPUT 40;
+    } ELSEIF (n == 5) {
+        OUTPUT late;
+    } ELSEIF (n == 6 && 1) {
+        OUTPUT 60;
+    } ELSE {
+        OUTPUT 0;
+    }
+    REPEAT (n - 4) {
+        OUTPUT 1;
+    }
+}
+IF (n > 2) {
+    both = 1;
+} ELSE {
+    both = 2;
+}
+fn = both * 10;
+OUTPUT fn + 1 / both;
+OUTPUT 7 - 9 * 2 >= 0 - 11 || 0;");
+}
+
+#[test]
+fn readable_output() {
+    let rust = generate(&parser::parse_source("a = 1;
+b = 2;
+REPEAT (a + b) {
+    a = a * 2;
+}
+IF (a < 8) {
+    OUTPUT a;
+} ELSE {
+    c = 3;
+}
+OUTPUT b;").unwrap());
+
+    assert_eq!(rust, "/// Generated from an .xa program
+#[allow(non_snake_case)]
+pub fn run() -> Vec<i32> {
+    let mut output = Vec::new();
+    let _v_c: i32;
+
+    let mut v_a: i32 = 1;
+    let v_b: i32 = 2;
+    for _ in 0..usize::try_from(v_a.checked_add(v_b).expect(\"3:1: addition overflowed\")).unwrap_or_else(|_| panic!(\"3:1: REPEAT count is negative\")) {
+        v_a = v_a.checked_mul(2).expect(\"4:5: multiplication overflowed\");
+    }
+    if v_a < 8 {
+        output.push(v_a);
+    } else {
+        _v_c = 3;
+    }
+    output.push(v_b);
+
+    output
+}
+");
+}
+
+#[test]
+fn runtime_errors() {
+    let run = matches_interpreter(&RUST, "negative", "OUTPUT 1;\nx = 0 - 2;\nREPEAT (x) {\n    OUTPUT 2;\n}");
+    assert_eq!(run.error.as_deref(), Some("3:1: REPEAT count is negative"));
+
+    let run = matches_interpreter(&RUST, "unassigned", "IF (0) {\n    x = 1;\n}\nOUTPUT x;");
+    assert_eq!(run.error.as_deref(), Some("4:1: Variable \"x\" has not been assigned"));
+
+    let run = matches_interpreter(&RUST, "overflow", "x = 2147483647;\nOUTPUT x + 1;");
+    assert_eq!(run.error.as_deref(), Some("2:1: addition overflowed"));
+
+    let run = matches_interpreter(&RUST, "assertion", "x = 2;\nASSERT (x > 1);\nASSERT_EQ (x * 2, 4);\nOUTPUT x;\nASSERT_EQ (x, 3);");
+    assert_eq!(run.error.as_deref(), Some("5:1: assertion failed"));
+
+    let run = matches_interpreter(&RUST, "divide", "x = 0;\nOUTPUT 5 / x;");
+    assert_eq!(run.error.as_deref(), Some("2:1: division by zero"));
+    let run = matches_interpreter(&RUST, "remainder", "x = 0 - 2147483647 - 1;\ny = 0 - 1;\nOUTPUT x % y;");
+    assert_eq!(run.error.as_deref(), Some("3:1: remainder overflowed"));
+}
+
+/// Optimising turns these into negative literals, which need care in Rust
+#[test]
+fn negative_constants() {
+    // The loop is left alone, so x is put in as -5 and a isn't known
+    matches_interpreter(&RUST, "negative_receiver", "a = 0;
+i = 0;
+REPEAT (100) {
+    i = i + 1;
+    IF (i % 7 == 0) {
+        a = a + i;
+    }
+}
+x = 0 - 5;
+b = x + a;
+OUTPUT b;
+OUTPUT x * a;
+OUTPUT x / a;");
+
+    let run = matches_interpreter(&RUST, "negative_count", "n = 0 - 2;\nREPEAT (n) {\n    OUTPUT 1;\n}");
+    assert_eq!(run.error.as_deref(), Some("2:1: REPEAT count is negative"));
+}
+
+/// Names from Rust's prelude or keywords are fine as xa variables
+#[test]
+fn rust_names() {
+    matches_interpreter(&RUST, "names", "None = 1;\nSome = 2;\nSelf = None + Some;\nfn = Self;\noutput = fn;\nOUTPUT output;");
+}
@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests;
+
+use std::io::{self, Write};
+
+use interpreter::{Branch, ErrorType, Hook, Memory};
+use language::*;
+
+/// Writes a JSON object per line for everything the program does. Every
+/// record has a `step` (counting statements run and times round a REPEAT from
+/// 1), the `file`, `line` and `column` of its statement, and a `kind` of
+/// assign, if, repeat, iteration, output or assert. Files added before the
+/// run come first, as records of kind file with the `file` id and its `path`
+pub struct Tracer<W: Write> {
+    out: W,
+    step: u64,
+    // The hook can't fail so the first write error is kept for `finish`
+    error: Option<io::Error>
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Self {
+        Self{out, step: 0, error: None}
+    }
+
+    /// Writes which `path` the id `file` stands for, so the trace can be read
+    /// without the program
+    pub fn add_file(&mut self, file: FileId, path: &str) {
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Err(e) = writeln!(self.out, "{{\"kind\":\"file\",\"file\":{},\"path\":{}}}", file, json_string(path)) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flushes the trace, giving back the writer or the first error hit
+    /// while writing
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        self.out.flush()?;
+        return Ok(self.out);
+    }
+
+    fn record(&mut self, stmt: &Statement, kind: &str, fields: &str) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let loc = stmt.location();
+        let result = writeln!(
+            self.out,
+            "{{\"step\":{},\"file\":{},\"line\":{},\"column\":{},\"kind\":\"{}\"{}}}",
+            self.step, loc.file, loc.line, loc.column, kind, fields
+        );
+
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn before_statement(&mut self, _stmt: &Statement, _depth: usize, _memory: &mut Memory) -> Result<(), ErrorType> {
+        self.step += 1;
+        Ok(())
+    }
+
+    fn after_assign(&mut self, stmt: &Statement, var: &str, val: i32) {
+        self.record(stmt, "assign", &format!(",\"var\":{},\"value\":{}", json_string(var), val));
+    }
+
+    fn after_branch(&mut self, stmt: &Statement, taken: Branch) {
+        let fields = match taken {
+            Branch::If => String::from(",\"branch\":\"if\""),
+            Branch::ElseIf(arm) => format!(",\"branch\":\"elseif\",\"arm\":{}", arm),
+            Branch::Else => String::from(",\"branch\":\"else\"")
+        };
+        self.record(stmt, "if", &fields);
+    }
+
+    fn before_repeat(&mut self, stmt: &Statement, times: i32) {
+        self.record(stmt, "repeat", &format!(",\"times\":{}", times));
+    }
+
+    fn before_iteration(&mut self, stmt: &Statement, iteration: i32) {
+        self.step += 1;
+        self.record(stmt, "iteration", &format!(",\"iteration\":{}", iteration));
+    }
+
+    fn after_output(&mut self, stmt: &Statement, val: i32) {
+        self.record(stmt, "output", &format!(",\"value\":{}", val));
+    }
+
+    fn after_assert(&mut self, stmt: &Statement, value: i32, expected: Option<i32>) {
+        let (fields, holds) = match expected {
+            Some(expected) => (format!(",\"value\":{},\"expected\":{}", value, expected), value == expected),
+            None => (format!(",\"value\":{}", value), value != 0)
+        };
+        self.record(stmt, "assert", &format!("{},\"holds\":{}", fields, holds));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::*;
+
+use interpreter::Interpreter;
+
+fn trace(source: &str) -> (Result<(), ErrorType>, String) {
+    let program = parser::parse_source(source).unwrap();
+
+    let mut interpreter = Interpreter::with_hook(Tracer::new(Vec::new()));
+    let result = interpreter.run(&program);
+    let out = interpreter.hook.finish().unwrap();
+
+    (result, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn records_every_statement() {
+    let (result, out) = trace("x = 1;
+REPEAT (2) {
+    IF (x == 2) {
+        OUTPUT x;
+    } ELSEIF (x == 1) {
+        x = x + 1;
+    }
+}");
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(out.lines().collect::<Vec<&str>>(), vec![
+        r#"{"step":1,"file":0,"line":1,"column":1,"kind":"assign","var":"x","value":1}"#,
+        r#"{"step":2,"file":0,"line":2,"column":1,"kind":"repeat","times":2}"#,
+        r#"{"step":3,"file":0,"line":2,"column":1,"kind":"iteration","iteration":1}"#,
+        r#"{"step":4,"file":0,"line":3,"column":5,"kind":"if","branch":"elseif","arm":0}"#,
+        r#"{"step":5,"file":0,"line":6,"column":9,"kind":"assign","var":"x","value":2}"#,
+        r#"{"step":6,"file":0,"line":2,"column":1,"kind":"iteration","iteration":2}"#,
+        r#"{"step":7,"file":0,"line":3,"column":5,"kind":"if","branch":"if"}"#,
+        r#"{"step":8,"file":0,"line":4,"column":9,"kind":"output","value":2}"#
+    ]);
+}
+
+#[test]
+fn trace_stops_at_error() {
+    // The count that made the REPEAT fail is the last thing recorded
+    let (result, out) = trace("IF (0) {\n} \nREPEAT (0 - 1) {\n}");
+
+    assert_eq!(result, Err(ErrorType::NegativeRepeateError));
+    assert_eq!(out.lines().collect::<Vec<&str>>(), vec![
+        r#"{"step":1,"file":0,"line":1,"column":1,"kind":"if","branch":"else"}"#,
+        r#"{"step":2,"file":0,"line":3,"column":1,"kind":"repeat","times":-1}"#
+    ]);
+}
+
+#[test]
+fn records_assertions() {
+    let (result, out) = trace("x = 2;\nASSERT (x);\nASSERT_EQ (x, 3);");
+
+    assert!(matches!(result, Err(ErrorType::AssertionFailed{..})));
+    assert_eq!(out.lines().skip(1).collect::<Vec<&str>>(), vec![
+        r#"{"step":2,"file":0,"line":2,"column":1,"kind":"assert","value":2,"holds":true}"#,
+        r#"{"step":3,"file":0,"line":3,"column":1,"kind":"assert","value":2,"expected":3,"holds":false}"#
+    ]);
+}
+
+#[test]
+fn records_the_file() {
+    let dir = std::env::temp_dir().join(format!("tracer-imports-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lib.xa"), "x = 4;\n").unwrap();
+    let program = parser::Loader::new().load_source("IMPORT \"lib.xa\";\nOUTPUT lib.x;", &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut tracer = Tracer::new(Vec::new());
+    tracer.add_file(0, "main.xa");
+    tracer.add_file(1, "lib.xa");
+    let mut interpreter = Interpreter::with_hook(tracer);
+    assert_eq!(interpreter.run(&program), Ok(()));
+    let out = String::from_utf8(interpreter.hook.finish().unwrap()).unwrap();
+    assert_eq!(out.lines().collect::<Vec<&str>>(), vec![
+        r#"{"kind":"file","file":0,"path":"main.xa"}"#,
+        r#"{"kind":"file","file":1,"path":"lib.xa"}"#,
+        r#"{"step":1,"file":1,"line":1,"column":1,"kind":"assign","var":"lib.x","value":4}"#,
+        r#"{"step":2,"file":0,"line":2,"column":1,"kind":"output","value":4}"#
+    ]);
+}
+
+#[test]
+fn escapes_strings() {
+    assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+}
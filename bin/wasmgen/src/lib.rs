@@ -0,0 +1,301 @@
+//! Compiles a program to a WebAssembly module. The module exports its
+//! `memory` and a `main` function that takes and returns nothing, and
+//! imports two functions from the host under `xa`:
+//!
+//! - `output(value: i32)` is called for every value output
+//! - `fail(message: i32, length: i32, line: i32, column: i32)` is called on a
+//!   runtime error with the UTF-8 message at `message` in the exported
+//!   memory, after which the module traps
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use language::*;
+
+/// Checked arithmetic, only the ones a program uses are written out. The
+/// interpreter stops with an error on overflow and division by zero so these
+/// fail too.
+/// Each has its function name, failure messages and the instruction to use
+const HELPERS: [(Op, &str, &str, &str, &str); 5] = [
+    (Op::Add, "$add", "addition overflowed", "", "i64.add"),
+    (Op::Sub, "$sub", "subtraction overflowed", "", "i64.sub"),
+    (Op::Multiply, "$mul", "multiplication overflowed", "", "i64.mul"),
+    (Op::Divide, "$div", "division overflowed", "division by zero", "i32.div_s"),
+    (Op::Remainder, "$rem", "remainder overflowed", "remainder by zero", "i32.rem_s")
+];
+
+/// Messages for `fail`, laid out one after another from the start of memory
+#[derive(Default)]
+struct Messages {
+    text: String,
+    offsets: Vec<(String, usize)>
+}
+
+impl Messages {
+    /// Instructions that call `fail` with `message` and trap
+    fn fail(&mut self, message: &str, line: &str, column: &str) -> String {
+        let offset = match self.offsets.iter().find(|(m, _)| m == message) {
+            Some((_, offset)) => *offset,
+            None => {
+                let offset = self.text.len();
+                self.text.push_str(message);
+                self.offsets.push((message.to_string(), offset));
+                offset
+            }
+        };
+
+        format!(
+            "i32.const {} i32.const {} {} {} call $fail unreachable",
+            offset, message.len(), line, column
+        )
+    }
+
+    /// The data segment as a WAT string, escaping anything unusual
+    fn data(&self) -> String {
+        let mut result = String::new();
+        for byte in self.text.bytes() {
+            match byte {
+                b'"' | b'\\' => write!(result, "\\{:02x}", byte).unwrap(),
+                0x20..=0x7e => result.push(byte as char),
+                _ => write!(result, "\\{:02x}", byte).unwrap()
+            }
+        }
+        return result;
+    }
+}
+
+struct Generator {
+    out: String,
+    indent: usize,
+    vars: BTreeSet<String>,
+    temps: usize,
+    used: BTreeSet<usize>,
+    messages: Messages
+}
+
+impl Generator {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("$t{}", self.temps)
+    }
+
+    fn fail_at(&mut self, message: &str, loc: Location) -> String {
+        self.messages.fail(message, &format!("i32.const {}", loc.line), &format!("i32.const {}", loc.column))
+    }
+
+    /// Leaves the value of `exp` on the stack, evaluating left to right
+    fn exp(&mut self, exp: &Expression, loc: Location) {
+        match exp {
+            Expression::Val(v) => self.line(&format!("i32.const {}", v)),
+            Expression::Var(var) => {
+                let fail = self.fail_at(&format!("Variable \"{}\" has not been assigned", var), loc);
+                self.line(&format!("local.get $v_{}_set", var));
+                self.line("i32.eqz");
+                self.line("if");
+                self.line(&format!("    {}", fail));
+                self.line("end");
+                self.line(&format!("local.get $v_{}", var));
+            },
+            Expression::BinOp(op, lhs, rhs) => {
+                self.exp(lhs, loc);
+                // Both sides of AND and OR are always evaluated, so turn
+                // each into 0 or 1 as soon as it is known
+                if let Op::And | Op::Or = op {
+                    self.line("i32.const 0");
+                    self.line("i32.ne");
+                }
+                self.exp(rhs, loc);
+
+                match op {
+                    Op::And => self.line("i32.const 0 i32.ne i32.and"),
+                    Op::Or => self.line("i32.const 0 i32.ne i32.or"),
+                    Op::Equal => self.line("i32.eq"),
+                    Op::NotEqual => self.line("i32.ne"),
+                    Op::LessThan => self.line("i32.lt_s"),
+                    Op::LessThanOrEqual => self.line("i32.le_s"),
+                    Op::GreaterThan => self.line("i32.gt_s"),
+                    Op::GreaterThanOrEqual => self.line("i32.ge_s"),
+                    _ => {
+                        let helper = HELPERS.iter().position(|h| h.0 == *op).unwrap();
+                        self.used.insert(helper);
+                        self.line(&format!("i32.const {} i32.const {} call {}", loc.line, loc.column, HELPERS[helper].1));
+                    }
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        let loc = stmt.location();
+
+        match stmt {
+            Statement::AssignStatement { var, exp, .. } => {
+                self.exp(exp, loc);
+                if self.vars.contains(var) {
+                    self.line(&format!("local.set $v_{}", var));
+                    self.line(&format!("i32.const 1 local.set $v_{}_set", var));
+                } else {
+                    self.line("drop");
+                }
+            },
+            Statement::OutputStatement { to_output, .. } => {
+                self.exp(to_output, loc);
+                self.line("call $output");
+            },
+            Statement::AssertStatement { exp, expected, .. } => {
+                self.exp(exp, loc);
+                match expected {
+                    Some(expected) => {
+                        self.exp(expected, loc);
+                        self.line("i32.ne");
+                    },
+                    None => self.line("i32.eqz")
+                }
+                self.line("if");
+                let fail = self.fail_at("assertion failed", loc);
+                self.line(&format!("    {}", fail));
+                self.line("end");
+            },
+            Statement::RepeatStatement { times, body, .. } => {
+                // Counts down a local of its own so the body can't change
+                // how many times it runs
+                let count = self.temp();
+                self.exp(times, loc);
+                self.line(&format!("local.tee {}", count));
+                self.line("i32.const 0");
+                self.line("i32.lt_s");
+                self.line("if");
+                let fail = self.fail_at("REPEAT count is negative", loc);
+                self.line(&format!("    {}", fail));
+                self.line("end");
+
+                self.line("block");
+                self.indent += 1;
+                self.line("loop");
+                self.indent += 1;
+                self.line(&format!("local.get {}", count));
+                self.line("i32.eqz");
+                self.line("br_if 1");
+                self.line(&format!("local.get {0} i32.const 1 i32.sub local.set {0}", count));
+                self.block(body);
+                self.line("br 0");
+                self.indent -= 1;
+                self.line("end");
+                self.indent -= 1;
+                self.line("end");
+            },
+            Statement::IfStatement { condition, body, else_if, else_body, .. } => {
+                // Each ELSEIF condition only runs once the ones before it are
+                // false, so it goes inside the else of the previous arm
+                let mut arms = vec![(condition, body)];
+                arms.extend(else_if.iter().map(|(exp, block)| (exp, block)));
+
+                for (exp, block) in arms.iter() {
+                    self.exp(exp, loc);
+                    self.line("if");
+                    self.indent += 1;
+                    self.block(block);
+                    self.indent -= 1;
+                    self.line("else");
+                    self.indent += 1;
+                }
+
+                self.block(else_body);
+
+                for _ in arms.iter() {
+                    self.indent -= 1;
+                    self.line("end");
+                }
+            }
+        }
+    }
+
+    /// A checked arithmetic function taking both operands then the line and
+    /// column of the statement
+    fn helper(&mut self, helper: usize) -> String {
+        let (_, name, overflow, zero, instr) = HELPERS[helper];
+        let overflow = self.messages.fail(overflow, "local.get $line", "local.get $column");
+
+        let mut result = format!("    (func {} (param $a i32) (param $b i32) (param $line i32) (param $column i32) (result i32)\n", name);
+        if instr.starts_with("i64") {
+            // Work it out in 64 bits and check it still fits in 32
+            writeln!(result, "        (local $r i64)").unwrap();
+            writeln!(result, "        local.get $a i64.extend_i32_s local.get $b i64.extend_i32_s {} local.tee $r", instr).unwrap();
+            writeln!(result, "        local.get $r i32.wrap_i64 i64.extend_i32_s i64.ne").unwrap();
+            writeln!(result, "        if {} end", overflow).unwrap();
+            writeln!(result, "        local.get $r i32.wrap_i64)").unwrap();
+        } else {
+            let zero = self.messages.fail(zero, "local.get $line", "local.get $column");
+            writeln!(result, "        local.get $b i32.eqz").unwrap();
+            writeln!(result, "        if {} end", zero).unwrap();
+            // WebAssembly traps on this division but gives 0 for the remainder
+            writeln!(result, "        local.get $a i32.const -2147483648 i32.eq local.get $b i32.const -1 i32.eq i32.and").unwrap();
+            writeln!(result, "        if {} end", overflow).unwrap();
+            writeln!(result, "        local.get $a local.get $b {})", instr).unwrap();
+        }
+
+        return result;
+    }
+}
+
+/// The WebAssembly text for `program`
+pub fn generate_wat(program: &Program) -> String {
+    // Variables that are never read don't need storing
+    let vars = program.program.read_variables();
+
+    let mut gen = Generator{
+        out: String::new(),
+        indent: 2,
+        vars,
+        temps: 0,
+        used: BTreeSet::new(),
+        messages: Messages::default()
+    };
+    gen.block(&program.program);
+
+    let mut helpers = String::new();
+    for helper in gen.used.clone() {
+        helpers.push_str(&gen.helper(helper));
+    }
+
+    let mut result = String::from("(module\n");
+    writeln!(result, "    (import \"xa\" \"output\" (func $output (param i32)))").unwrap();
+    writeln!(result, "    (import \"xa\" \"fail\" (func $fail (param i32 i32 i32 i32)))").unwrap();
+    writeln!(result, "    (memory (export \"memory\") 1)").unwrap();
+    writeln!(result, "    (data (i32.const 0) \"{}\")", gen.messages.data()).unwrap();
+    result.push_str(&helpers);
+
+    writeln!(result, "    (func (export \"main\")").unwrap();
+    for var in gen.vars.iter() {
+        writeln!(result, "        (local $v_{0} i32) (local $v_{0}_set i32)", var).unwrap();
+    }
+    for temp in 1..=gen.temps {
+        writeln!(result, "        (local $t{} i32)", temp).unwrap();
+    }
+    result.push_str(&gen.out);
+    result.push_str("    )\n)\n");
+
+    return result;
+}
+
+/// The binary encoding of the module `generate_wat` gives
+pub fn generate_wasm(program: &Program) -> Vec<u8> {
+    return wat::parse_str(generate_wat(program)).expect("Generated WebAssembly text is invalid");
+}
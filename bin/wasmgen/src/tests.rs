@@ -0,0 +1,113 @@
+use crate::*;
+
+use wasmtime::{Caller, Engine, Linker, Module, Store};
+
+#[derive(Default)]
+struct Host {
+    outputs: Vec<i32>,
+    error: Option<String>
+}
+
+/// Validates the module for `source` and runs it with an embedded runtime
+fn run(source: &str) -> Host {
+    let program = parser::parse_source(source).unwrap();
+    let wasm = generate_wasm(&program);
+
+    wasmparser::Validator::new().validate_all(&wasm).unwrap();
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm).unwrap();
+
+    let mut linker = Linker::new(&engine);
+    linker.func_wrap("xa", "output", |mut caller: Caller<'_, Host>, value: i32| {
+        caller.data_mut().outputs.push(value);
+    }).unwrap();
+    linker.func_wrap("xa", "fail", |mut caller: Caller<'_, Host>, message: i32, length: i32, line: i32, column: i32| {
+        let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+        let bytes = &memory.data(&caller)[message as usize..(message + length) as usize];
+        let error = format!("{}:{}: {}", line, column, String::from_utf8(bytes.to_vec()).unwrap());
+        caller.data_mut().error = Some(error);
+    }).unwrap();
+
+    let mut store = Store::new(&engine, Host::default());
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+    let main = instance.get_typed_func::<(), ()>(&mut store, "main").unwrap();
+
+    let trapped = main.call(&mut store, ()).is_err();
+    let host = store.into_data();
+    assert_eq!(trapped, host.error.is_some());
+
+    host
+}
+
+/// The module has to output what the interpreter does
+fn matches_interpreter(source: &str) {
+    let host = run(source);
+    let expected = interpreter::interpret(parser::parse_source(source).unwrap()).unwrap();
+
+    assert_eq!(host.error, None);
+    assert_eq!(host.outputs, expected);
+}
+
+#[test]
+fn examples() {
+    matches_interpreter(include_str!("../../../examples/fibbonachi.xa"));
+    matches_interpreter(include_str!("../../../examples/moreops.xa"));
+}
+
+#[test]
+fn control_flow() {
+    matches_interpreter("n = 3;
+unused = 7;
+REPEAT (n) {
+    n = n + 1;
+    IF (n == 4) {
+        OUTPUT 40;
+    } ELSEIF (n == 5) {
+        OUTPUT 50;
+    } ELSEIF (n == 6 && 1) {
+        OUTPUT 60;
+    } ELSE {
+        OUTPUT 0;
+    }
+    REPEAT (0) {
+        OUTPUT 1;
+    }
+}
+OUTPUT n;
+OUTPUT 7 - 9 * 2 >= 0 - 11 || 0;
+OUTPUT 2 && 3;");
+}
+
+#[test]
+fn runtime_errors() {
+    let host = run("OUTPUT 1;\nx = 0 - 2;\nREPEAT (x) {\n    OUTPUT 2;\n}");
+    assert_eq!(host.outputs, vec![1]);
+    assert_eq!(host.error.as_deref(), Some("3:1: REPEAT count is negative"));
+
+    let host = run("IF (0) {\n    x = 1;\n}\nOUTPUT x;");
+    assert_eq!(host.error.as_deref(), Some("4:1: Variable \"x\" has not been assigned"));
+
+    let host = run("x = 2147483647;\nOUTPUT x;\nOUTPUT x + 1;");
+    assert_eq!(host.outputs, vec![2147483647]);
+    assert_eq!(host.error.as_deref(), Some("3:1: addition overflowed"));
+
+    let host = run("x = 0;\nOUTPUT 5 % 3;\nOUTPUT 1 / x;");
+    assert_eq!(host.outputs, vec![2]);
+    assert_eq!(host.error.as_deref(), Some("3:1: division by zero"));
+
+    let host = run("x = 2;\nASSERT (x > 1);\nASSERT_EQ (x * 2, 4);\nOUTPUT x;\nASSERT_EQ (x, 3);");
+    assert_eq!(host.outputs, vec![2]);
+    assert_eq!(host.error.as_deref(), Some("5:1: assertion failed"));
+}
+
+#[test]
+fn text_format() {
+    let wat = generate_wat(&parser::parse_source("x = 1;\nOUTPUT x * 2;").unwrap());
+
+    assert!(wat.contains("(import \"xa\" \"output\" (func $output (param i32)))"), "{}", wat);
+    assert!(wat.contains("(func $mul "), "{}", wat);
+    // Only the arithmetic that is used is written out
+    assert!(!wat.contains("(func $add "), "{}", wat);
+    assert!(wat.contains("(local $v_x i32) (local $v_x_set i32)"), "{}", wat);
+}
@@ -1,22 +1,68 @@
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use language::{FileId, Program, Sources};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let context = parser::get_file_context(&(args[1]))
-                                        .expect(&format!("Could not open file: {:?}", &(args[1])));
+    // Options can go anywhere, everything else is positional
+    let mut trace = None;
+    let mut folded = None;
+    let mut top = 10;
+    let mut lcov = None;
+    let mut target = "c";
+    let mut output = None;
+    let mut optimise = false;
+    let mut as_html = false;
+    let mut bless = false;
+    let mut positional = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--trace" => trace = Some(iter.next().expect("--trace needs a file to write to").as_str()),
+            "--folded" => folded = Some(iter.next().expect("--folded needs a file to write to").as_str()),
+            "--target" => target = iter.next().expect("--target needs a backend name").as_str(),
+            "-o" | "--output" => output = Some(iter.next().expect("--output needs a file to write to").as_str()),
+            "-O" | "--optimise" => optimise = true,
+            "--html" => as_html = true,
+            "--bless" => bless = true,
+            "--lcov" => lcov = Some(iter.next().expect("--lcov needs a file to write to").as_str()),
+            "--top" => top = iter.next().and_then(|n| n.parse().ok()).expect("--top needs a number of sites"),
+            _ => positional.push(arg.as_str())
+        }
+    }
 
-    
-    let parse_result = parser::gen_ast(context);
+    match positional.as_slice() {
+        ["debug", filename] => debug(filename),
+        ["replay", filename] => replay(filename),
+        ["profile", filename] => profile(filename, top, folded),
+        ["coverage", filenames @ ..] if !filenames.is_empty() => coverage(filenames, lcov),
+        ["lint", filenames @ ..] if !filenames.is_empty() => lint(filenames),
+        ["check", filenames @ ..] if !filenames.is_empty() => check(filenames),
+        ["optimise", filenames @ ..] if !filenames.is_empty() => optimise_files(filenames),
+        ["lsp"] => lsp(),
+        ["test", paths @ ..] => test(paths, bless),
+        ["highlight", filename] => highlight(filename, as_html),
+        ["grammar"] => print!("{}", highlight::textmate_grammar()),
+        ["compile", filename] => compile(filename, target, output, optimise),
+        [filename] => run(filename, trace, optimise),
+        _ => panic!("Usage: my_language [-O] [--trace TRACE_FILE] FILE | debug FILE | replay FILE | profile [--top N] [--folded FOLDED_FILE] FILE | coverage [--lcov LCOV_FILE] FILE... | lint FILE... | check FILE... | optimise FILE... | test [--bless] [PATH...] | lsp | highlight [--html] FILE | grammar | compile [-O] [--target c|wat|wasm|asm|llvm|rust|ir] [-o OUTPUT_FILE] FILE")
+    }
+}
 
-    parser::close_file_from_context(context);
+fn run(filename: &str, trace: Option<&str>, optimise: bool) {
+    let (ast, loader) = load(filename);
+    let ast = if optimise { optimiser::optimise(ast).0 } else { ast };
 
-    let ast = match parse_result {
-        Err(e) => panic!("PARSING FAILED :(: {:?}", e),
-        Ok(a) => a
+    let result = match trace {
+        Some(trace_file) => run_traced(ast, &loader, trace_file),
+        None => interpreter::interpret(ast)
     };
 
-    let result = match interpreter::interpret(ast) {
+    let result = match result {
         Err(e) => panic!("Interpreting Failed :( : {:?}", e),
         Ok(r) => r
     };
@@ -24,4 +70,330 @@ fn main() {
     for item in result.iter() {
         println!("{}", item);
     }
-}
\ No newline at end of file
+}
+
+/// Parses the program in `filename` and everything it imports
+fn parse_file(filename: &str) -> Program {
+    return load(filename).0;
+}
+
+/// The program in `filename`, and the loader that knows which files it read
+fn load(filename: &str) -> (Program, parser::Loader) {
+    let mut loader = parser::Loader::new();
+    match loader.load(Path::new(filename)) {
+        Err(parser::ParseError::FileNotFound) => panic!("Could not open file: {:?}", filename),
+        Err(e) => panic!("PARSING FAILED :(: {}: {}", filename, loader.describe(&e)),
+        Ok(a) => (a, loader)
+    }
+}
+
+fn run_traced(ast: Program, loader: &parser::Loader, trace_file: &str) -> Result<Vec<i32>, interpreter::ErrorType> {
+    let file = File::create(trace_file)
+                        .unwrap_or_else(|_| panic!("Could not create trace file: {:?}", trace_file));
+
+    let mut tracer = tracer::Tracer::new(BufWriter::new(file));
+    for (file, path) in (0..).map_while(|file| Some((file, loader.path(file)?))) {
+        tracer.add_file(file, &path.display().to_string());
+    }
+
+    let mut interpreter = interpreter::Interpreter::with_hook(tracer);
+    let result = interpreter.run(&ast);
+
+    // Keep the trace of a failed run, it says how it got there
+    if let Err(e) = interpreter.hook.finish() {
+        panic!("Could not write trace file: {:?}: {}", trace_file, e);
+    }
+
+    result.map(|()| interpreter.output_vec)
+}
+
+/// Every file the loader read with its path and text, the main file first
+fn read_files(loader: &parser::Loader) -> Vec<(FileId, &Path, String)> {
+    return (0..).map_while(|file| Some((file, loader.path(file)?)))
+        .map(|(file, path)| {
+            let source = fs::read_to_string(path)
+                        .unwrap_or_else(|_| panic!("Could not open file: {:?}", path));
+            (file, path, source)
+        })
+        .collect();
+}
+
+/// The text of every file the program was read from, to show lines from
+fn sources(loader: &parser::Loader) -> Sources {
+    let files = read_files(loader);
+    let mut sources = Sources::new(&files[0].2);
+    for (file, path, source) in files.iter().skip(1) {
+        sources.add(*file, &path.display().to_string(), source);
+    }
+    return sources;
+}
+
+fn debug(filename: &str) {
+    let (ast, loader) = load(filename);
+
+    let debugger = debugger::Debugger::new(sources(&loader), io::stdin().lock(), io::stdout());
+    let mut interpreter = interpreter::Interpreter::with_hook(debugger);
+
+    let result = interpreter.run(&ast);
+
+    for item in interpreter.output_vec.iter() {
+        println!("{}", item);
+    }
+
+    if let Err(e) = result {
+        let loc = interpreter.error_location().unwrap_or_default();
+        println!("stopped at {}: {}", loader.locate(loc), e);
+    }
+}
+
+fn replay(filename: &str) {
+    let (ast, loader) = load(filename);
+
+    let mut interpreter = interpreter::Interpreter::with_hook(replay::Recorder::new());
+
+    // A failed run is still worth going back through
+    if let Err(e) = interpreter.run(&ast) {
+        let loc = interpreter.error_location().unwrap_or_default();
+        println!("failed at {}: {}", loader.locate(loc), e);
+    }
+
+    let recording = interpreter.hook.finish();
+    if let Err(e) = replay::explore(&recording, &sources(&loader), io::stdin().lock(), io::stdout()) {
+        panic!("Could not talk to the terminal: {}", e);
+    }
+}
+
+fn profile(filename: &str, top: usize, folded: Option<&str>) {
+    let (ast, loader) = load(filename);
+
+    let mut interpreter = interpreter::Interpreter::with_hook(profiler::Profiler::new(&ast));
+
+    if let Err(e) = interpreter.run(&ast) {
+        let loc = interpreter.error_location().unwrap_or_default();
+        println!("failed at {}: {}", loader.locate(loc), e);
+    }
+
+    let profile = interpreter.hook.finish();
+    for (file, path, source) in read_files(&loader) {
+        // Imported files are named, the main file is the one asked for
+        if file != 0 {
+            println!("{}", path.display());
+        }
+        println!("{}", profile.annotate(file, &source));
+    }
+    print!("{}", profile.top(top));
+
+    if let Some(folded_file) = folded {
+        fs::write(folded_file, profile.folded())
+            .unwrap_or_else(|e| panic!("Could not write folded stacks: {:?}: {}", folded_file, e));
+    }
+}
+
+/// Prints the lint warnings for each file, using the `.xalint` of the
+/// project it is in
+fn lint(filenames: &[&str]) {
+    for filename in filenames {
+        let source = fs::read_to_string(filename)
+                            .unwrap_or_else(|_| panic!("Could not open file: {:?}", filename));
+        let config = lint::Config::find(Path::new(filename))
+                            .unwrap_or_else(|e| panic!("Could not read lint config: {}", e));
+
+        let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+        match lint::lint_in(&source, dir, &config) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    println!("{}:{}", filename, warning);
+                }
+            },
+            Err(e) if e.location().is_some_and(|loc| loc.file != 0) => {
+                // Reading it again finds which imported file it is in
+                let mut loader = parser::Loader::new();
+                let _ = loader.load(Path::new(filename));
+                println!("{}", loader.describe(&e));
+            },
+            Err(e) => println!("{}:{}", filename, e)
+        }
+    }
+}
+
+/// Reports reads of variables that might not have been assigned, and
+/// arithmetic and REPEATs that might fail, without running anything
+fn check(filenames: &[&str]) {
+    for filename in filenames {
+        let (ast, loader) = load(filename);
+
+        let mut found: Vec<_> = analysis::uninitialised_reads(&ast).iter().map(|r| (r.loc, r.to_string())).collect();
+        found.extend(analysis::intervals(&ast).problems.iter().map(|p| (p.loc, p.to_string())));
+        found.sort_by_key(|(loc, _)| *loc);
+
+        // Anything found in an imported file is said to be there
+        for (loc, message) in found {
+            let path = loader.path(loc.file).unwrap_or(Path::new(filename));
+            println!("{}:{}", path.display(), message);
+        }
+    }
+}
+
+/// Checks every program in `paths` against its expected results, or makes
+/// what they give now expected with `bless`
+fn test(paths: &[&str], bless: bool) {
+    let paths: Vec<_> = match paths.is_empty() {
+        true => vec![".".into()],
+        false => paths.iter().map(|p| p.into()).collect()
+    };
+    let files = golden::find(&paths).expect("Could not look for programs");
+
+    let (mut passed, mut failed, mut without) = (0, 0, 0);
+    let (mut tests_passed, mut tests_failed) = (0, 0);
+    for file in files {
+        let outcome = golden::check(&file).unwrap_or_else(|e| panic!("Could not check {:?}: {}", file, e));
+        let has_tests = !outcome.tests.is_empty();
+
+        if bless {
+            // Only the output can be blessed, failing tests need fixing
+            let checks_output = outcome.expected_from != golden::Expected::Nothing || !has_tests;
+            if checks_output && !outcome.output_passed() {
+                golden::bless(&outcome).unwrap_or_else(|e| panic!("Could not bless {:?}: {}", file, e));
+                println!("BLESSED {}", file.display());
+            }
+            continue;
+        }
+
+        if outcome.expected_from == golden::Expected::Nothing && !has_tests {
+            println!("NONE {} (no .out file, // expect: comments or TEST blocks)", file.display());
+            without += 1;
+            continue;
+        }
+
+        if outcome.passed() {
+            println!("PASS {}", file.display());
+            passed += 1;
+        } else {
+            println!("FAIL {}", file.display());
+            failed += 1;
+        }
+        if outcome.expected_from != golden::Expected::Nothing && !outcome.output_passed() {
+            println!("  --- expected");
+            println!("  +++ actual");
+            for line in golden::diff(&outcome.expected, &outcome.actual) {
+                println!("  {}", line);
+            }
+        }
+        for test in outcome.tests.iter() {
+            match &test.failure {
+                None => {
+                    println!("  PASS TEST {:?}", test.name);
+                    tests_passed += 1;
+                },
+                Some(failure) => {
+                    println!("  FAIL TEST {:?}: {}", test.name, failure);
+                    tests_failed += 1;
+                }
+            }
+        }
+    }
+
+    if !bless {
+        println!("{} passed, {} failed, {} without expected results", passed, failed, without);
+        if tests_passed + tests_failed > 0 {
+            println!("TEST blocks: {} passed, {} failed", tests_passed, tests_failed);
+        }
+        if failed > 0 {
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the file highlighted for the terminal, or as HTML
+fn highlight(filename: &str, as_html: bool) {
+    let source = fs::read_to_string(filename)
+                        .unwrap_or_else(|_| panic!("Could not open file: {:?}", filename));
+
+    match as_html {
+        true => print!("{}", highlight::html(&source)),
+        false => print!("{}", highlight::ansi(&source))
+    }
+}
+
+/// Runs the language server on stdin and stdout until the editor stops it
+fn lsp() {
+    let code = lsp::serve(io::stdin().lock(), io::stdout().lock()).expect("Could not talk to the editor");
+    std::process::exit(code);
+}
+
+/// Optimises each file, saying how much smaller it got and checking it
+/// still runs the same
+fn optimise_files(filenames: &[&str]) {
+    for filename in filenames {
+        let ast = parse_file(filename);
+
+        let before = ir::run(&ir::lower(&ast));
+        let (optimised, report) = optimiser::optimise(ast);
+        let after = ir::run(&ir::lower(&optimised));
+
+        if before != after {
+            panic!("Optimising changed what {:?} does: {:?} became {:?}", filename, before, after);
+        }
+
+        println!(
+            "{}: {} nodes to {}, {} eliminated in {} passes, output unchanged",
+            filename, report.nodes_before, report.nodes_after, report.eliminated(), report.passes
+        );
+    }
+}
+
+fn coverage(filenames: &[&str], lcov: Option<&str>) {
+    let mut records = String::new();
+
+    for filename in filenames {
+        let (ast, loader) = load(filename);
+
+        let mut interpreter = interpreter::Interpreter::with_hook(coverage::Coverage::new(&ast));
+
+        // What ran before the error still counts
+        println!("{}", filename);
+        if let Err(e) = interpreter.run(&ast) {
+            let loc = interpreter.error_location().unwrap_or_default();
+            println!("failed at {}: {}", loader.locate(loc), e);
+        }
+
+        // Each file gets its own record, named as it was asked for
+        let report = interpreter.hook.finish();
+        for (file, path, source) in read_files(&loader) {
+            let path = if file == 0 { filename.to_string() } else { path.display().to_string() };
+            if file != 0 {
+                println!("{}", path);
+            }
+            println!("{}", report.annotate(file, &source));
+            records.push_str(&report.lcov(file, &path));
+        }
+    }
+
+    if let Some(lcov_file) = lcov {
+        fs::write(lcov_file, records)
+            .unwrap_or_else(|e| panic!("Could not write LCOV file: {:?}: {}", lcov_file, e));
+    }
+}
+
+fn compile(filename: &str, target: &str, output: Option<&str>, optimise: bool) {
+    let ast = parse_file(filename);
+    let ast = if optimise { optimiser::optimise(ast).0 } else { ast };
+
+    let code = match target {
+        "c" => cgen::generate(&ast).into_bytes(),
+        "wat" => wasmgen::generate_wat(&ast).into_bytes(),
+        "wasm" => wasmgen::generate_wasm(&ast),
+        "asm" => asmgen::generate(&ast).into_bytes(),
+        "llvm" => llvmgen::generate(&ast).into_bytes(),
+        "rust" => rustgen::generate(&ast).into_bytes(),
+        "ir" => ir::lower(&ast).to_string().into_bytes(),
+        _ => panic!("Unknown target {:?}, try c, wat, wasm, asm, llvm, rust or ir", target)
+    };
+
+    match output {
+        Some(output_file) => fs::write(output_file, code)
+            .unwrap_or_else(|e| panic!("Could not write output file: {:?}: {}", output_file, e)),
+        None => io::stdout().write_all(&code)
+            .unwrap_or_else(|e| panic!("Could not write to stdout: {}", e))
+    }
+}